@@ -1,11 +1,63 @@
 use num_bigint::BigInt;
 use crate::parser::expression::PartExpression;
+use crate::value::Value;
+use crate::error::{ErrorCode, raise_at};
+use std::sync::Arc;
 
 #[derive(Debug, Clone)]
 pub struct AST {
     pub functions: Vec<Function>,
     pub variables: Vec<Variable>,
-    pub loose_expressions: Vec<Expression>
+    pub loose_expressions: Vec<Expression>,
+    pub locations: Vec<Location>, // where each loose expression starts
+    pub tests: Vec<Test> // only run by math test
+}
+
+// test "name" = condition, passes if the condition is 1
+#[derive(Debug, Clone)]
+pub struct Test {
+    pub name: String,
+    pub condition: Expression,
+    pub location: Location
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Location {
+    pub file: String,
+    pub line: usize // from 0 like the lexer's
+}
+
+// a token's place in the source, runtime errors raised at one are shown like parse errors
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Span {
+    pub file: String,
+    pub line: usize,
+    pub index: usize,
+    pub length: usize,
+    pub line_content: String
+}
+
+impl Span {
+    // the offset moves the marker to the right (or to the left if it's negative), the marker is only labelled with the first line
+    // of the message (the rest, like the values of a failed assert_eq, is already shown above it)
+    pub fn diagnostic(&self, message: &str, offset: isize) -> String {
+        let gutter = " ".repeat((self.line + 1).to_string().len());
+
+        format!("\n{} |\n{} |     {}\n{} |{}{} {} [{}]",
+               gutter,
+               self.line + 1,
+               self.line_content,
+               gutter,
+               " ".repeat(("     ".len() as isize + self.index as isize - offset) as usize),
+               "^".repeat(self.length),
+               message.lines().next().unwrap_or_default(),
+               self.file
+        )
+    }
+
+    pub fn raise(&self, code: ErrorCode, message: &str) -> ! {
+        raise_at(code, message, self.diagnostic(message, 0))
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -13,18 +65,19 @@ pub struct Function {
     pub name: String,
     pub definition: Expression,
     pub parameters: Vec<String>,
+    pub variadic: bool, // the last parameter collects the remaining arguments into a list
     pub pre_definition: PartExpression,
-    pub cached: bool
+    pub cached: bool,
+    pub documentation: Option<String>
 }
 
 #[derive(Debug, Clone)]
 pub struct Variable {
     pub name: String,
     pub definition: Expression,
-    pub wherepart: Vec<Expression>,
     pub pre_definition: PartExpression,
-    pub pre_wherepart: Vec<PartExpression>,
-    pub constant: bool
+    pub constant: bool,
+    pub documentation: Option<String>
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -34,42 +87,149 @@ pub enum Expression {
     NumberValue {
         value: BigInt
     },
+    Value { // already evaluated values (e.g. bound arguments)
+        value: Value
+    },
     VariableAccess {
         variable: String
     },
-    Math {
-        var1: Box<Expression>,
-        var2: Box<Expression>,
-        math: MathType
+    Conditional { // condition ? then : otherwise, only one of the branches is evaluated
+        condition: Arc<Expression>,
+        then: Arc<Expression>,
+        otherwise: Arc<Expression>
+    },
+    Negate { // -value, the value is only evaluated once
+        value: Arc<Expression>,
+        span: Option<Arc<Span>> // of the -
+    },
+    Math { // subtrees are shared so that cloning an expression is shallow
+        var1: Arc<Expression>,
+        var2: Arc<Expression>,
+        math: MathType,
+        span: Option<Arc<Span>> // of the operator, runtime errors raised while evaluating it point there (None if it isn't from the source)
     },
     FunctionInvocation {
         function: String,
-        arguments: Vec<Expression>
+        arguments: Vec<Expression>,
+        span: Option<Arc<Span>> // of the name
     },
     VariableAssignment {
         variable: String,
-        value: Box<Expression>
+        value: Arc<Expression>
     },
     Pointer {
         to: String
+    },
+    ResolvedVariable { // VariableAccess and FunctionInvocation after name resolution (see RuntimeAST::resolve)
+        variable: String,
+        slot: Slot
+    },
+    ResolvedInvocation {
+        function: String,
+        target: CallTarget,
+        arguments: Vec<Expression>,
+        span: Option<Arc<Span>>
+    },
+    Match { // the arms are tried in order, only the value of the first one that matches is evaluated
+        value: Arc<Expression>,
+        arms: Vec<MatchArm>
+    },
+    Comprehension { // [value for variable in source if condition], the variable is an extra argument of the current call
+        value: Arc<Expression>,
+        variable: String,
+        source: Arc<Expression>,
+        condition: Option<Arc<Expression>>
+    },
+    Where { // body where variable = value, the value is evaluated once and is an extra argument of the current call in the body
+        variable: String,
+        value: Arc<Expression>,
+        body: Arc<Expression>
+    },
+    Block { // (a; b; c), all of them are evaluated in order, the value is the last one's
+        expressions: Vec<Expression>
+    },
+    Index { // value[index], from 0
+        value: Arc<Expression>,
+        index: Arc<Expression>,
+        span: Span
+    },
+    Slice { // value[from:to] without to, from 0 and the length if left out
+        value: Arc<Expression>,
+        from: Option<Arc<Expression>>,
+        to: Option<Arc<Expression>>,
+        span: Span
     }
 }
 
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct MatchArm {
+    pub pattern: Option<Expression>, // None for _, which matches everything
+    pub value: Expression
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Slot {
+    Global(usize), // index into the runtime's variables
+    Argument(usize) // parameter index in the current frame
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum CallTarget {
+    Function(usize),
+    External(usize)
+}
+
 impl Clone for Expression {
     fn clone(&self) -> Self {
         match self {
             Expression::None => Expression::None,
             Expression::External => Expression::External,
             Expression::NumberValue { value } => Expression::NumberValue { value: value.clone() },
+            Expression::Value { value } => Expression::Value { value: value.clone() },
             Expression::VariableAccess { variable } => Expression::VariableAccess { variable: variable.to_owned() },
-            Expression::Math { var1, var2, math } => Expression::Math { var1: var1.to_owned(), var2: var2.to_owned(), math: math.clone() },
-            Expression::FunctionInvocation { function, arguments } => Expression::FunctionInvocation { function: function.to_owned(), arguments: arguments.clone() },
+            Expression::Conditional { condition, then, otherwise } => Expression::Conditional { condition: condition.to_owned(), then: then.to_owned(), otherwise: otherwise.to_owned() },
+            Expression::Negate { value, span } => Expression::Negate { value: value.to_owned(), span: span.clone() },
+            Expression::Math { var1, var2, math, span } => Expression::Math { var1: var1.to_owned(), var2: var2.to_owned(), math: math.clone(), span: span.clone() },
+            Expression::FunctionInvocation { function, arguments, span } => Expression::FunctionInvocation { function: function.to_owned(), arguments: arguments.clone(), span: span.clone() },
             Expression::VariableAssignment { variable, value } => Expression::VariableAssignment { variable: variable.to_owned(), value: value.to_owned() },
-            Expression::Pointer { to } => Expression::Pointer { to: to.clone() }
+            Expression::Pointer { to } => Expression::Pointer { to: to.clone() },
+            Expression::ResolvedVariable { variable, slot } => Expression::ResolvedVariable { variable: variable.to_owned(), slot: *slot },
+            Expression::ResolvedInvocation { function, target, arguments, span } => Expression::ResolvedInvocation { function: function.to_owned(), target: *target, arguments: arguments.clone(), span: span.clone() },
+            Expression::Match { value, arms } => Expression::Match { value: value.to_owned(), arms: arms.clone() },
+            Expression::Comprehension { value, variable, source, condition } => Expression::Comprehension { value: value.to_owned(), variable: variable.to_owned(), source: source.to_owned(), condition: condition.clone() },
+            Expression::Where { variable, value, body } => Expression::Where { variable: variable.to_owned(), value: value.to_owned(), body: body.to_owned() },
+            Expression::Block { expressions } => Expression::Block { expressions: expressions.clone() },
+            Expression::Index { value, index, span } => Expression::Index { value: value.to_owned(), index: index.to_owned(), span: span.clone() },
+            Expression::Slice { value, from, to, span } => Expression::Slice { value: value.to_owned(), from: from.clone(), to: to.clone(), span: span.clone() }
         }
     }
 }
 
+impl Function {
+    pub fn accepts(&self, arguments: usize) -> bool {
+        if self.variadic {
+            arguments + 1 >= self.parameters.len()
+        } else {
+            arguments == self.parameters.len()
+        }
+    }
+}
+
+// for calls of a name that exists but not with that many arguments, the definitions are given as (parameters, variadic)
+pub fn arity_mismatch(name: &str, definitions: &[(usize, bool)], supplied: usize) -> String {
+    let mut arities = definitions.iter().map(|(parameters, variadic)| if *variadic { (parameters - 1, true) } else { (*parameters, false) }).collect::<Vec<(usize, bool)>>();
+
+    arities.sort();
+    arities.dedup();
+
+    let mut takes = arities.iter().map(|(n, variadic)| if *variadic { format!("{} or more", n) } else { n.to_string() }).collect::<Vec<String>>();
+    let last = takes.pop().unwrap();
+    let takes = if takes.is_empty() { last } else { format!("{} or {}", takes.join(", "), last) };
+    let plural = arities != [(1, false)];
+
+    format!("`{}` exists but takes {} argument{}, {} {} supplied", name, takes, if plural { "s" } else { "" }, supplied, if supplied == 1 { "was" } else { "were" })
+}
+
 impl Expression {
     pub fn variable_access_variable(&self) -> &String {
         match self {
@@ -77,6 +237,13 @@ impl Expression {
             _ => panic!("Not supported")
         }
     }
+
+    pub fn value(&self) -> &Value {
+        match self {
+            Expression::Value { value } => value,
+            _ => panic!("Not supported")
+        }
+    }
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
@@ -85,13 +252,16 @@ pub enum MathType {
     Subtract,
     Multiply,
     Divide,
+    FloorDivide,
     Equals,
     NotEquals,
     BiggerOrEquals,
     Bigger,
     SmallerOrEquals,
     Smaller,
-    Pow
+    Pow,
+    And,
+    Or
 }
 
 impl MathType {
@@ -101,21 +271,24 @@ impl MathType {
             MathType::Subtract => "-",
             MathType::Multiply => "*",
             MathType::Divide => "/",
+            MathType::FloorDivide => "//",
             MathType::Equals => "==",
             MathType::NotEquals => "=!",
             MathType::BiggerOrEquals => ">=",
             MathType::Bigger => ">",
             MathType::SmallerOrEquals => "<=",
             MathType::Smaller => "<",
-            MathType::Pow => "^"
+            MathType::Pow => "^",
+            MathType::And => "&&",
+            MathType::Or => "||"
         }.to_owned()
     }
 
     fn entries() -> Vec<MathType> {
-        vec![MathType::Add, MathType::Subtract, MathType::Multiply, MathType::Divide, MathType::Equals, MathType::NotEquals, MathType::BiggerOrEquals, MathType::Bigger, MathType::SmallerOrEquals, MathType::Smaller, MathType::Pow]
+        vec![MathType::Add, MathType::Subtract, MathType::Multiply, MathType::Divide, MathType::Equals, MathType::NotEquals, MathType::BiggerOrEquals, MathType::Bigger, MathType::SmallerOrEquals, MathType::Smaller, MathType::Pow, MathType::And, MathType::Or, MathType::FloorDivide]
     }
 
     pub fn of(operator: String) -> MathType {
-        MathType::entries().into_iter().find(|m| m.operator().eq(&operator)).expect(&format!("Operator not found ('{}')", operator))
+        MathType::entries().into_iter().find(|m| m.operator().eq(&operator)).unwrap_or_else(|| panic!("Operator not found ('{}')", operator))
     }
 }
\ No newline at end of file
@@ -0,0 +1,600 @@
+use crate::ast::{AST, CallTarget, Expression, Function, Location, MatchArm, MathType, Slot, Span, Test, Variable};
+use crate::parser::expression::PartExpression;
+use crate::value::{Set, Value};
+use std::collections::HashSet;
+use num_bigint::BigInt;
+use std::convert::{TryFrom, TryInto};
+use std::sync::Arc;
+
+// the encoding of compiled programs and snapshots, integers are little endian, lengths are written before what they count
+
+const OPERATORS: [MathType; 14] = [MathType::Add, MathType::Subtract, MathType::Multiply, MathType::Divide, MathType::Equals, MathType::NotEquals, MathType::BiggerOrEquals, MathType::Bigger, MathType::SmallerOrEquals, MathType::Smaller, MathType::Pow, MathType::And, MathType::Or, MathType::FloorDivide];
+
+pub struct Writer(Vec<u8>);
+
+impl Writer {
+    // files start with a magic number
+    pub fn new(magic: &[u8]) -> Self {
+        Writer(magic.to_vec())
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.0
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn usize(&mut self, value: usize) {
+        self.u64(value as u64);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.usize(bytes.len());
+        self.0.extend_from_slice(bytes);
+    }
+
+    pub fn string(&mut self, s: &str) {
+        self.bytes(s.as_bytes());
+    }
+
+    pub fn strings(&mut self, strings: &[String]) {
+        self.usize(strings.len());
+        strings.iter().for_each(|s| self.string(s));
+    }
+
+    pub fn optional(&mut self, s: &Option<String>) {
+        self.bool(s.is_some());
+
+        if let Some(s) = s {
+            self.string(s);
+        }
+    }
+
+    pub fn number(&mut self, n: &BigInt) {
+        self.bytes(&n.to_signed_bytes_le());
+    }
+
+    pub fn ast(&mut self, ast: &AST) {
+        self.usize(ast.functions.len());
+        ast.functions.iter().for_each(|f| self.function(f));
+        self.usize(ast.variables.len());
+        ast.variables.iter().for_each(|v| self.variable(v));
+        self.expressions(&ast.loose_expressions);
+        self.usize(ast.locations.len());
+
+        for location in &ast.locations {
+            self.string(&location.file);
+            self.usize(location.line);
+        }
+
+        self.usize(ast.tests.len());
+
+        for test in &ast.tests {
+            self.string(&test.name);
+            self.expression(&test.condition);
+            self.string(&test.location.file);
+            self.usize(test.location.line);
+        }
+    }
+
+    pub fn function(&mut self, f: &Function) {
+        self.string(&f.name);
+        self.expression(&f.definition);
+        self.strings(&f.parameters);
+        self.bool(f.variadic);
+        self.bool(f.cached);
+        self.optional(&f.documentation);
+    }
+
+    pub fn variable(&mut self, v: &Variable) {
+        self.string(&v.name);
+        self.expression(&v.definition);
+        self.bool(v.constant);
+        self.optional(&v.documentation);
+    }
+
+    pub fn expressions(&mut self, expressions: &[Expression]) {
+        self.usize(expressions.len());
+        expressions.iter().for_each(|e| self.expression(e));
+    }
+
+    pub fn expression(&mut self, expr: &Expression) {
+        match expr {
+            Expression::None => self.u8(0),
+            Expression::External => self.u8(1),
+            Expression::NumberValue { value } => {
+                self.u8(2);
+                self.number(value);
+            },
+            Expression::Value { value } => {
+                self.u8(3);
+                self.value(value);
+            },
+            Expression::VariableAccess { variable } => {
+                self.u8(4);
+                self.string(variable);
+            },
+            Expression::Math { var1, var2, math, span } => {
+                self.u8(5);
+                self.expression(var1);
+                self.expression(var2);
+                self.u8(OPERATORS.iter().position(|m| m == math).unwrap() as u8);
+                self.located(span);
+            },
+            Expression::FunctionInvocation { function, arguments, span } => {
+                self.u8(6);
+                self.string(function);
+                self.expressions(arguments);
+                self.located(span);
+            },
+            Expression::VariableAssignment { variable, value } => {
+                self.u8(7);
+                self.string(variable);
+                self.expression(value);
+            },
+            Expression::Pointer { to } => {
+                self.u8(8);
+                self.string(to);
+            },
+            Expression::ResolvedVariable { variable, slot } => {
+                self.u8(9);
+                self.string(variable);
+
+                match slot {
+                    Slot::Global(i) => {
+                        self.u8(0);
+                        self.usize(*i);
+                    },
+                    Slot::Argument(i) => {
+                        self.u8(1);
+                        self.usize(*i);
+                    }
+                }
+            },
+            Expression::ResolvedInvocation { function, target, arguments, span } => {
+                self.u8(10);
+                self.string(function);
+
+                match target {
+                    CallTarget::Function(i) => {
+                        self.u8(0);
+                        self.usize(*i);
+                    },
+                    CallTarget::External(i) => {
+                        self.u8(1);
+                        self.usize(*i);
+                    }
+                }
+
+                self.expressions(arguments);
+                self.located(span);
+            },
+            Expression::Match { value, arms } => {
+                self.u8(11);
+                self.expression(value);
+                self.usize(arms.len());
+
+                for arm in arms {
+                    self.bool(arm.pattern.is_some());
+
+                    if let Some(pattern) = &arm.pattern {
+                        self.expression(pattern);
+                    }
+
+                    self.expression(&arm.value);
+                }
+            },
+            Expression::Comprehension { value, variable, source, condition } => {
+                self.u8(12);
+                self.expression(value);
+                self.string(variable);
+                self.expression(source);
+                self.bool(condition.is_some());
+
+                if let Some(condition) = condition {
+                    self.expression(condition);
+                }
+            },
+            Expression::Where { variable, value, body } => {
+                self.u8(17);
+                self.string(variable);
+                self.expression(value);
+                self.expression(body);
+            },
+            Expression::Block { expressions } => {
+                self.u8(18);
+                self.usize(expressions.len());
+                expressions.iter().for_each(|expr| self.expression(expr));
+            },
+            Expression::Index { value, index, span } => {
+                self.u8(13);
+                self.expression(value);
+                self.expression(index);
+                self.span(span);
+            },
+            Expression::Slice { value, from, to, span } => {
+                self.u8(14);
+                self.expression(value);
+
+                for bound in [from, to] {
+                    self.bool(bound.is_some());
+
+                    if let Some(bound) = bound {
+                        self.expression(bound);
+                    }
+                }
+
+                self.span(span);
+            },
+            Expression::Negate { value, span } => {
+                self.u8(15);
+                self.expression(value);
+                self.located(span);
+            },
+            Expression::Conditional { condition, then, otherwise } => {
+                self.u8(16);
+                self.expression(condition);
+                self.expression(then);
+                self.expression(otherwise);
+            }
+        }
+    }
+
+    pub fn located(&mut self, span: &Option<Arc<Span>>) {
+        self.bool(span.is_some());
+
+        if let Some(span) = span {
+            self.span(span);
+        }
+    }
+
+    pub fn span(&mut self, span: &Span) {
+        self.string(&span.file);
+        self.usize(span.line);
+        self.usize(span.index);
+        self.usize(span.length);
+        self.string(&span.line_content);
+    }
+
+    pub fn value(&mut self, value: &Value) {
+        match value {
+            Value::Small(n) => {
+                self.u8(0);
+                self.u64(*n as u64);
+            },
+            Value::Number(n) => {
+                self.u8(1);
+                self.number(n);
+            },
+            Value::Fraction(p, q) => {
+                self.u8(2);
+                self.number(p);
+                self.number(q);
+            },
+            Value::Decimal(digits, scale) => {
+                self.u8(3);
+                self.number(digits);
+                self.u64(*scale as u64);
+            },
+            Value::List(values) => {
+                self.u8(4);
+                self.usize(values.len());
+                values.iter().for_each(|v| self.value(v));
+            },
+            Value::String(s) => {
+                self.u8(5);
+                self.string(s);
+            },
+            Value::Range(from, to, step) => {
+                self.u8(6);
+                self.number(from);
+                self.number(to);
+                self.number(step);
+            },
+            Value::Tuple(values) => {
+                self.u8(7);
+                self.usize(values.len());
+                values.iter().for_each(|v| self.value(v));
+            },
+            Value::Set(set) => {
+                self.u8(8);
+                self.usize(set.0.len());
+                set.sorted().into_iter().for_each(|n| self.number(n));
+            },
+            Value::None => self.u8(9)
+        }
+    }
+}
+
+// every read fails (None) instead of panicking on truncated or corrupted files
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize
+}
+
+impl<'a> Reader<'a> {
+    // None if the bytes don't start with the magic number
+    pub fn new(bytes: &'a [u8], magic: &[u8]) -> Option<Self> {
+        let mut r = Reader {
+            bytes,
+            position: 0
+        };
+
+        if r.take(magic.len())? == magic {
+            Some(r)
+        } else {
+            None
+        }
+    }
+
+    // None if there's anything left
+    pub fn end(&self) -> Option<()> {
+        if self.position == self.bytes.len() {
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let bytes = self.bytes.get(self.position..self.position.checked_add(n)?)?;
+
+        self.position += n;
+
+        Some(bytes)
+    }
+
+    pub fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    pub fn u64(&mut self) -> Option<u64> {
+        self.take(8).map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+    }
+
+    pub fn usize(&mut self) -> Option<usize> {
+        self.u64().and_then(|n| usize::try_from(n).ok())
+    }
+
+    pub fn bool(&mut self) -> Option<bool> {
+        match self.u8()? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None
+        }
+    }
+
+    pub fn bytes(&mut self) -> Option<&'a [u8]> {
+        let length = self.usize()?;
+
+        self.take(length)
+    }
+
+    pub fn string(&mut self) -> Option<String> {
+        String::from_utf8(self.bytes()?.to_vec()).ok()
+    }
+
+    pub fn strings(&mut self) -> Option<Vec<String>> {
+        (0..self.usize()?).map(|_| self.string()).collect()
+    }
+
+    pub fn optional(&mut self) -> Option<Option<String>> {
+        if self.bool()? {
+            self.string().map(Some)
+        } else {
+            Some(None)
+        }
+    }
+
+    pub fn number(&mut self) -> Option<BigInt> {
+        Some(BigInt::from_signed_bytes_le(self.bytes()?))
+    }
+
+    pub fn ast(&mut self) -> Option<AST> {
+        let functions = (0..self.usize()?).map(|_| self.function()).collect::<Option<Vec<Function>>>()?;
+        let variables = (0..self.usize()?).map(|_| self.variable()).collect::<Option<Vec<Variable>>>()?;
+        let loose_expressions = self.expressions()?;
+        let locations = (0..self.usize()?).map(|_| Some(Location {
+            file: self.string()?,
+            line: self.usize()?
+        })).collect::<Option<Vec<Location>>>()?;
+        let tests = (0..self.usize()?).map(|_| Some(Test {
+            name: self.string()?,
+            condition: self.expression()?,
+            location: Location {
+                file: self.string()?,
+                line: self.usize()?
+            }
+        })).collect::<Option<Vec<Test>>>()?;
+
+        Some(AST {
+            functions,
+            variables,
+            loose_expressions,
+            locations,
+            tests
+        })
+    }
+
+    // the parts only the parser needs aren't stored
+    pub fn function(&mut self) -> Option<Function> {
+        Some(Function {
+            name: self.string()?,
+            definition: self.expression()?,
+            parameters: self.strings()?,
+            variadic: self.bool()?,
+            pre_definition: PartExpression::None,
+            cached: self.bool()?,
+            documentation: self.optional()?
+        })
+    }
+
+    pub fn variable(&mut self) -> Option<Variable> {
+        Some(Variable {
+            name: self.string()?,
+            definition: self.expression()?,
+            pre_definition: PartExpression::None,
+            constant: self.bool()?,
+            documentation: self.optional()?
+        })
+    }
+
+    pub fn expressions(&mut self) -> Option<Vec<Expression>> {
+        (0..self.usize()?).map(|_| self.expression()).collect()
+    }
+
+    pub fn expression(&mut self) -> Option<Expression> {
+        Some(match self.u8()? {
+            0 => Expression::None,
+            1 => Expression::External,
+            2 => Expression::NumberValue {
+                value: self.number()?
+            },
+            3 => Expression::Value {
+                value: self.value()?
+            },
+            4 => Expression::VariableAccess {
+                variable: self.string()?
+            },
+            5 => Expression::Math {
+                var1: Arc::new(self.expression()?),
+                var2: Arc::new(self.expression()?),
+                math: OPERATORS.get(self.u8()? as usize)?.clone(),
+                span: self.located()?
+            },
+            6 => Expression::FunctionInvocation {
+                function: self.string()?,
+                arguments: self.expressions()?,
+                span: self.located()?
+            },
+            7 => Expression::VariableAssignment {
+                variable: self.string()?,
+                value: Arc::new(self.expression()?)
+            },
+            8 => Expression::Pointer {
+                to: self.string()?
+            },
+            9 => Expression::ResolvedVariable {
+                variable: self.string()?,
+                slot: match self.u8()? {
+                    0 => Slot::Global(self.usize()?),
+                    1 => Slot::Argument(self.usize()?),
+                    _ => return None
+                }
+            },
+            10 => Expression::ResolvedInvocation {
+                function: self.string()?,
+                target: match self.u8()? {
+                    0 => CallTarget::Function(self.usize()?),
+                    1 => CallTarget::External(self.usize()?),
+                    _ => return None
+                },
+                arguments: self.expressions()?,
+                span: self.located()?
+            },
+            11 => Expression::Match {
+                value: Arc::new(self.expression()?),
+                arms: (0..self.usize()?).map(|_| Some(MatchArm {
+                    pattern: if self.bool()? {
+                        Some(self.expression()?)
+                    } else {
+                        None
+                    },
+                    value: self.expression()?
+                })).collect::<Option<Vec<MatchArm>>>()?
+            },
+            12 => Expression::Comprehension {
+                value: Arc::new(self.expression()?),
+                variable: self.string()?,
+                source: Arc::new(self.expression()?),
+                condition: if self.bool()? {
+                    Some(Arc::new(self.expression()?))
+                } else {
+                    None
+                }
+            },
+            13 => Expression::Index {
+                value: Arc::new(self.expression()?),
+                index: Arc::new(self.expression()?),
+                span: self.span()?
+            },
+            14 => Expression::Slice {
+                value: Arc::new(self.expression()?),
+                from: self.bound()?,
+                to: self.bound()?,
+                span: self.span()?
+            },
+            15 => Expression::Negate {
+                value: Arc::new(self.expression()?),
+                span: self.located()?
+            },
+            16 => Expression::Conditional {
+                condition: Arc::new(self.expression()?),
+                then: Arc::new(self.expression()?),
+                otherwise: Arc::new(self.expression()?)
+            },
+            17 => Expression::Where {
+                variable: self.string()?,
+                value: Arc::new(self.expression()?),
+                body: Arc::new(self.expression()?)
+            },
+            18 => Expression::Block {
+                expressions: (0..self.usize()?).map(|_| self.expression()).collect::<Option<Vec<Expression>>>()?
+            },
+            _ => return None
+        })
+    }
+
+    // a bound of a slice that can be left out
+    fn bound(&mut self) -> Option<Option<Arc<Expression>>> {
+        if self.bool()? {
+            self.expression().map(|e| Some(Arc::new(e)))
+        } else {
+            Some(None)
+        }
+    }
+
+    pub fn located(&mut self) -> Option<Option<Arc<Span>>> {
+        if self.bool()? {
+            self.span().map(|span| Some(Arc::new(span)))
+        } else {
+            Some(None)
+        }
+    }
+
+    pub fn span(&mut self) -> Option<Span> {
+        Some(Span {
+            file: self.string()?,
+            line: self.usize()?,
+            index: self.usize()?,
+            length: self.usize()?,
+            line_content: self.string()?
+        })
+    }
+
+    pub fn value(&mut self) -> Option<Value> {
+        Some(match self.u8()? {
+            0 => Value::Small(self.u64()? as i64),
+            1 => Value::Number(self.number()?),
+            2 => Value::Fraction(self.number()?, self.number()?),
+            3 => Value::Decimal(self.number()?, u32::try_from(self.u64()?).ok()?),
+            4 => Value::List((0..self.usize()?).map(|_| self.value()).collect::<Option<Vec<Value>>>()?),
+            5 => Value::String(self.string()?),
+            6 => Value::Range(self.number()?, self.number()?, self.number()?),
+            7 => Value::Tuple((0..self.usize()?).map(|_| self.value()).collect::<Option<Vec<Value>>>()?),
+            8 => Value::Set(Set((0..self.usize()?).map(|_| self.number()).collect::<Option<HashSet<BigInt>>>()?)),
+            9 => Value::None,
+            _ => return None
+        })
+    }
+}
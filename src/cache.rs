@@ -0,0 +1,76 @@
+use crate::ast::AST;
+use crate::binary::{Reader, Writer};
+use crate::lexer::LexedToken;
+use std::fs::{read, read_to_string, write};
+use std::path::{Path, PathBuf};
+
+// compiled programs (.mathc) are the parsed AST together with the sources it was parsed from,
+// they're only used as long as they were compiled by the same version with the same options from unchanged sources
+const MAGIC: &[u8] = b"MATHC";
+const FORMAT: u8 = 2;
+
+// src/main.math -> src/main.mathc
+pub fn path(entry: &Path) -> PathBuf {
+    entry.with_extension("mathc")
+}
+
+// the files the tokens came from (the entry and everything it includes), in the order they were read
+pub fn sources(tokens: &[LexedToken]) -> Vec<String> {
+    let mut files = Vec::<String>::new();
+
+    for t in tokens {
+        if !files.contains(t.file()) {
+            files.push(t.file().clone());
+        }
+    }
+
+    files
+}
+
+// options are whatever else changes the result of parsing (e.g. the comment marker), they have to be the same when loading
+pub fn save(path: &Path, ast: &AST, sources: &[String], options: &str) {
+    let mut w = Writer::new(MAGIC);
+
+    w.u8(FORMAT);
+    w.string(env!("CARGO_PKG_VERSION"));
+    w.string(options);
+    w.usize(sources.len());
+
+    for source in sources {
+        w.string(source);
+        w.u64(hash(&read_to_string(source).expect("Error while reading file")));
+    }
+
+    w.ast(ast);
+
+    write(path, w.finish()).expect("Error while writing file");
+}
+
+// None if there's no compiled program or it's stale
+pub fn load(path: &Path, options: &str) -> Option<AST> {
+    let bytes = read(path).ok()?;
+    let mut r = Reader::new(&bytes, MAGIC)?;
+
+    if r.u8()? != FORMAT || r.string()? != env!("CARGO_PKG_VERSION") || r.string()? != options {
+        return None;
+    }
+
+    for _ in 0..r.usize()? {
+        let source = r.string()?;
+
+        if hash(&read_to_string(&source).ok()?) != r.u64()? {
+            return None;
+        }
+    }
+
+    let ast = r.ast()?;
+
+    r.end()?;
+
+    Some(ast)
+}
+
+// FNV-1a, unlike std's hasher it's guaranteed to stay the same between Rust versions
+fn hash(content: &str) -> u64 {
+    content.bytes().fold(0xcbf29ce484222325, |h, b| (h ^ b as u64).wrapping_mul(0x100000001b3))
+}
@@ -0,0 +1,307 @@
+use crate::default_externals;
+use crate::ast::{AST, Expression, Variable};
+use crate::error::{ErrorCode, MathError, catch};
+use crate::interpreter::run_at;
+use crate::interpreter::runtime::{Eval, ExternalRuntimeFunction, Invocation, RuntimeAST, RuntimeExpression, RuntimeFunction, RuntimeVariable};
+use crate::lexer::{full_lex, LexedToken, LexerData};
+use crate::parser::expression::PartExpression;
+use crate::parser::{continues, nesting, parse};
+use crate::snapshot;
+use crate::value::Value;
+use std::sync::Arc;
+
+// a runtime that sources are run in one after another, what they define stays around for the following ones
+pub struct Context {
+    pub runtime: RuntimeAST,
+    data: LexerData,
+    externals: Vec<ExternalRuntimeFunction>,
+    definitions: Vec<LexedToken>, // tokens of every definition so far, parsed again with each source so that names resolve
+    variables: usize,
+    functions: usize,
+    sources: usize
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Context::create(LexerData::default(), default_externals())
+    }
+}
+
+impl Context {
+    pub fn new() -> Self {
+        Context::default()
+    }
+
+    pub fn create(data: LexerData, externals: Vec<ExternalRuntimeFunction>) -> Self {
+        Context {
+            runtime: RuntimeAST::create(AST {
+                functions: vec![],
+                variables: vec![],
+                loose_expressions: vec![],
+                locations: vec![],
+                tests: vec![]
+            }, externals.clone()),
+            data,
+            externals,
+            definitions: vec![],
+            variables: 0,
+            functions: 0,
+            sources: 0
+        }
+    }
+
+    // called before every loose expression and function call, returning an error aborts the evaluation (e.g. for custom limits)
+    pub fn on_before_eval(mut self, hook: impl Fn(&Eval) -> Result<(), MathError> + Send + Sync + 'static) -> Self {
+        self.runtime.hooks.before_eval = Some(Arc::new(hook));
+
+        self
+    }
+
+    // called with the result after every loose expression and function call that didn't fail
+    pub fn on_after_eval(mut self, hook: impl Fn(&Eval, &Value) + Send + Sync + 'static) -> Self {
+        self.runtime.hooks.after_eval = Some(Arc::new(hook));
+
+        self
+    }
+
+    // called after every call of a defined function with its argument values, how long it took and whether the result was cached
+    pub fn on_invoke(mut self, hook: impl Fn(&Invocation) + Send + Sync + 'static) -> Self {
+        self.runtime.hooks.invoke = Some(Arc::new(hook));
+
+        self
+    }
+
+    // returns the value of the last expression
+    pub fn exec(&mut self, source: &str) -> Result<Option<Value>, MathError> {
+        self.sources += 1;
+
+        let file = format!("<exec:{}>", self.sources);
+
+        self.exec_file(source, &file)
+    }
+
+    // the file name is only used in diagnostics
+    pub fn exec_file(&mut self, source: &str, file: &str) -> Result<Option<Value>, MathError> {
+        let tokens = catch(|| full_lex(source.to_owned(), file.to_owned(), "#".to_owned(), &self.data))?;
+        let definitions = definitions(&tokens);
+        let mut all = self.definitions.clone();
+
+        all.extend(tokens);
+
+        let ast = catch(|| parse(all, self.externals.clone()))?;
+        let functions = ast.functions.iter().filter(|f| Expression::External != f.definition).cloned().collect::<Vec<_>>();
+
+        for var in ast.variables.iter().skip(self.variables) {
+            self.runtime.define_variable(RuntimeVariable::from_raw(var.clone()));
+        }
+
+        for func in functions.iter().skip(self.functions) {
+            self.runtime.define_function(RuntimeFunction::from(func.clone()));
+        }
+
+        self.runtime.resolve_definitions();
+
+        self.variables = ast.variables.len();
+        self.functions = functions.len();
+        self.definitions.extend(definitions);
+
+        self.runtime.guarded(|runtime| run_at(runtime, &ast.loose_expressions, &ast.locations))
+    }
+
+    pub fn get_var(&mut self, name: &str) -> Result<Value, MathError> {
+        let access = Expression::VariableAccess {
+            variable: name.to_owned()
+        };
+
+        self.runtime.guarded(|runtime| RuntimeExpression::execute_expr(&access, runtime))
+    }
+
+    // defines the variable if it doesn't exist yet, sources run afterwards can use it
+    pub fn set_var(&mut self, name: &str, value: Value) -> Result<(), MathError> {
+        if !self.runtime.get_variables().iter().any(|v| !v.function_argument && v.name.eq(name)) {
+            let tokens = catch(|| full_lex(format!("let {} = 0", name), "<set_var>".to_owned(), "#".to_owned(), &self.data))?;
+            let significant = tokens.iter().filter(|t| t.token_type().id().ne("WHITESPACE")).map(|t| t.token_type().id()).collect::<Vec<&str>>();
+
+            if significant.ne(&["LET", "IDENTIFIER", "ASSIGN", "NUMBER", "NEW_LINE"]) || tokens.iter().all(|t| t.content().ne(name)) {
+                return Err(MathError::new(ErrorCode::InvalidArgument, &format!("Invalid variable name ('{}')", name)));
+            }
+
+            self.definitions.extend(tokens);
+            self.variables += 1;
+        }
+
+        self.runtime.define_variable(RuntimeVariable::from_raw(Variable {
+            name: name.to_owned(),
+            definition: Expression::Value {
+                value
+            },
+            pre_definition: PartExpression::None,
+            constant: false,
+            documentation: None
+        }));
+
+        Ok(())
+    }
+
+    // see snapshot::snapshot, restoring only makes sense into a context that ran the same sources
+    pub fn snapshot(&self) -> Vec<u8> {
+        snapshot::snapshot(&self.runtime)
+    }
+
+    pub fn restore(&mut self, bytes: &[u8]) -> Result<usize, MathError> {
+        snapshot::restore(&mut self.runtime, bytes)
+    }
+
+    pub fn call(&mut self, function: &str, arguments: &[Value]) -> Result<Value, MathError> {
+        let invocation = Expression::FunctionInvocation {
+            function: function.to_owned(),
+            arguments: arguments.iter().map(|value| Expression::Value { value: value.clone() }).collect(),
+            span: None
+        };
+
+        self.runtime.guarded(|runtime| RuntimeExpression::execute_expr(&invocation, runtime))
+    }
+}
+
+// splits a source into statements and keeps the ones that define something
+fn definitions(tokens: &[LexedToken]) -> Vec<LexedToken> {
+    let mut definitions = Vec::<LexedToken>::new();
+    let mut statement = Vec::<LexedToken>::new();
+    let mut continued = false;
+    let mut depth = 0;
+
+    for t in tokens {
+        let id = t.token_type().id();
+
+        statement.push(t.clone());
+        depth = nesting(id, depth);
+
+        match id {
+            "WHITESPACE" => {},
+            "PIPE" => continued = true,
+            "NEW_LINE" if continued => continued = false,
+            "SEMICOLON" if depth > 0 => continued = true, // inside a block
+            "NEW_LINE" | "SEMICOLON" => {
+                depth = 0;
+
+                let first = statement.iter().find(|t| t.token_type().id().ne("WHITESPACE")).unwrap();
+
+                if ["LET", "CONST", "DEFINE", "CACHE", "MACRO"].contains(&first.token_type().id()) {
+                    definitions.append(&mut statement);
+                } else {
+                    statement.clear();
+                }
+            },
+            _ => continued = continues(id, depth)
+        }
+    }
+
+    definitions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Context;
+    use crate::error::ErrorCode;
+    use crate::value::Value;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn definitions_persist() {
+        let mut context = Context::new();
+
+        assert_eq!(context.exec("define sq(x) = x * x\nlet a = 3").unwrap(), None);
+        assert_eq!(context.exec("sq(a) + 1").unwrap(), Some(Value::from(10)));
+        assert_eq!(context.exec("define cube(x) = x * sq(x); cube(a)").unwrap(), Some(Value::from(27)));
+        assert_eq!(context.exec("cube(2) + sq(2)").unwrap(), Some(Value::from(12)));
+        assert_eq!(context.exec("define f(x) = (x; y) where y = x * 2").unwrap(), None);
+        assert_eq!(context.exec("f(4)").unwrap(), Some(Value::from(8)));
+    }
+
+    #[test]
+    fn assignments_persist() {
+        let mut context = Context::new();
+
+        context.exec("let counter = 0").unwrap();
+        context.exec("counter = counter + 1").unwrap();
+        context.exec("counter = counter + 1").unwrap();
+
+        assert_eq!(context.get_var("counter").unwrap(), Value::from(2));
+    }
+
+    #[test]
+    fn errors_keep_the_state() {
+        let mut context = Context::new();
+
+        context.exec("let a = 5").unwrap();
+
+        assert_eq!(context.exec("a / 0").unwrap_err().code(), ErrorCode::InvalidArgument);
+        assert_eq!(context.exec("(a + 2").unwrap_err().code(), ErrorCode::UnbalancedParentheses);
+        assert_eq!(context.exec("missing(a)").unwrap_err().code(), ErrorCode::UndefinedFunction);
+        assert_eq!(context.exec("a * 2").unwrap(), Some(Value::from(10)));
+    }
+
+    #[test]
+    fn variable_where() {
+        let mut context = Context::new();
+        let error = context.exec("let y = a where a = 1").unwrap_err();
+
+        assert_eq!(error.code(), ErrorCode::UnexpectedToken);
+        assert_eq!(error.message(), "where is only allowed on functions");
+        assert_eq!(context.exec("define y(b) = a * b where a = 2; y(3)").unwrap(), Some(Value::from(6)));
+    }
+
+    #[test]
+    fn variables() {
+        let mut context = Context::new();
+
+        context.set_var("x", Value::from(7)).unwrap();
+
+        assert_eq!(context.get_var("x").unwrap(), Value::from(7));
+        assert_eq!(context.exec("x * 2").unwrap(), Some(Value::from(14)));
+
+        context.set_var("x", Value::from(8)).unwrap();
+
+        assert_eq!(context.exec("x * 2").unwrap(), Some(Value::from(16)));
+        assert_eq!(context.get_var("y").unwrap_err().code(), ErrorCode::UndefinedVariable);
+        assert_eq!(context.set_var("1x", Value::from(1)).unwrap_err().code(), ErrorCode::InvalidArgument);
+        assert_eq!(context.set_var("let", Value::from(1)).unwrap_err().code(), ErrorCode::InvalidArgument);
+    }
+
+    #[test]
+    fn calls() {
+        let mut context = Context::new();
+
+        context.exec("define add(a, b) = a + b").unwrap();
+
+        assert_eq!(context.call("add", &[Value::from(2), Value::from(3)]).unwrap(), Value::from(5));
+        assert_eq!(context.call("add", &[Value::from(2)]).unwrap_err().code(), ErrorCode::ArityMismatch);
+        assert_eq!(context.call("sub", &[]).unwrap_err().code(), ErrorCode::UndefinedFunction);
+    }
+
+    #[test]
+    fn hooks() {
+        let results = Arc::new(Mutex::new(Vec::<Value>::new()));
+        let seen = results.clone();
+        let mut context = Context::new().on_after_eval(move |_, value| seen.lock().unwrap().push(value.clone()));
+
+        context.exec("1 + 1\n2 * 3").unwrap();
+
+        assert!(results.lock().unwrap().ends_with(&[Value::from(6)]));
+        assert!(results.lock().unwrap().contains(&Value::from(2)));
+    }
+
+    #[test]
+    fn snapshots() {
+        let mut context = Context::new();
+
+        context.exec("let a = 0; a = 1").unwrap();
+
+        let snapshot = context.snapshot();
+
+        context.exec("a = 2").unwrap();
+        context.restore(&snapshot).unwrap();
+
+        assert_eq!(context.get_var("a").unwrap(), Value::from(1));
+    }
+}
@@ -0,0 +1,98 @@
+use crate::ast::{AST, Expression, Function, Variable};
+use crate::interpreter::runtime::RuntimeExpression;
+
+pub fn markdown(title: &str, ast: &AST) -> String {
+    let mut out = format!("# {}\n", title);
+    let functions = documented_functions(ast);
+
+    if !functions.is_empty() {
+        out.push_str("\n## Functions\n");
+
+        for f in functions {
+            out.push_str(&format!("\n### `{}`\n\n", function_signature(f)));
+
+            if let Some(documentation) = &f.documentation {
+                out.push_str(&format!("{}\n\n", documentation));
+            }
+
+            out.push_str(&format!("```\n{}\n```\n", function_definition(f)));
+        }
+    }
+
+    if !ast.variables.is_empty() {
+        out.push_str("\n## Variables\n");
+
+        for v in &ast.variables {
+            out.push_str(&format!("\n### `{}`\n\n", variable_signature(v)));
+
+            if let Some(documentation) = &v.documentation {
+                out.push_str(&format!("{}\n\n", documentation));
+            }
+
+            out.push_str(&format!("```\n{}\n```\n", variable_definition(v)));
+        }
+    }
+
+    out
+}
+
+pub fn html(title: &str, ast: &AST) -> String {
+    let mut out = format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n</head>\n<body>\n<h1>{0}</h1>\n", escape(title));
+    let functions = documented_functions(ast);
+
+    if !functions.is_empty() {
+        out.push_str("<h2>Functions</h2>\n");
+
+        for f in functions {
+            out.push_str(&format!("<h3><code>{}</code></h3>\n", escape(&function_signature(f))));
+
+            if let Some(documentation) = &f.documentation {
+                out.push_str(&format!("<p>{}</p>\n", escape(documentation).replace('\n', "<br>\n")));
+            }
+
+            out.push_str(&format!("<pre>{}</pre>\n", escape(&function_definition(f))));
+        }
+    }
+
+    if !ast.variables.is_empty() {
+        out.push_str("<h2>Variables</h2>\n");
+
+        for v in &ast.variables {
+            out.push_str(&format!("<h3><code>{}</code></h3>\n", escape(&variable_signature(v))));
+
+            if let Some(documentation) = &v.documentation {
+                out.push_str(&format!("<p>{}</p>\n", escape(documentation).replace('\n', "<br>\n")));
+            }
+
+            out.push_str(&format!("<pre>{}</pre>\n", escape(&variable_definition(v))));
+        }
+    }
+
+    out.push_str("</body>\n</html>\n");
+
+    out
+}
+
+fn documented_functions(ast: &AST) -> Vec<&Function> {
+    ast.functions.iter().filter(|f| Expression::External != f.definition).collect()
+}
+
+fn function_signature(f: &Function) -> String {
+    format!("{}({})", f.name, f.parameters.join(", "))
+}
+
+fn function_definition(f: &Function) -> String {
+    format!("define {}{} = {}", if f.cached { "cache " } else { "" }, function_signature(f), RuntimeExpression::expr_to_string(&f.definition))
+}
+
+fn variable_signature(v: &Variable) -> String {
+    format!("{} {}", if v.constant { "const" } else { "let" }, v.name)
+}
+
+fn variable_definition(v: &Variable) -> String {
+    format!("{} = {}", variable_signature(v), RuntimeExpression::expr_to_string(&v.definition))
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
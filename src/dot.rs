@@ -0,0 +1,82 @@
+use crate::ast::{AST, Expression};
+use crate::value::Value;
+
+// one cluster per definition, edges point from operators and invocations to their operands
+pub fn dot(ast: &AST) -> String {
+    let mut out = "digraph ast {\n    node [shape=box, fontname=\"monospace\"];\n".to_owned();
+    let mut nodes = 0;
+    let mut clusters = 0;
+
+    for f in ast.functions.iter().filter(|f| Expression::External != f.definition) {
+        let label = format!("define {}{}({}{})", if f.cached { "cache " } else { "" }, f.name, f.parameters.join(", "), if f.variadic { "..." } else { "" });
+
+        cluster(&label, &[&f.definition], &mut out, &mut nodes, &mut clusters);
+    }
+
+    for v in &ast.variables {
+        let label = format!("{} {}", if v.constant { "const" } else { "let" }, v.name);
+        cluster(&label, &[&v.definition], &mut out, &mut nodes, &mut clusters);
+    }
+
+    for (i, expr) in ast.loose_expressions.iter().enumerate() {
+        cluster(&format!("expression {}", i + 1), &[expr], &mut out, &mut nodes, &mut clusters);
+    }
+
+    out.push_str("}\n");
+
+    out
+}
+
+fn cluster(label: &str, roots: &[&Expression], out: &mut String, nodes: &mut usize, clusters: &mut usize) {
+    out.push_str(&format!("    subgraph cluster_{} {{\n        label=\"{}\";\n", clusters, escape(label)));
+
+    *clusters += 1;
+
+    for root in roots {
+        node(root, out, nodes);
+    }
+
+    out.push_str("    }\n");
+}
+
+fn node(expr: &Expression, out: &mut String, nodes: &mut usize) -> usize {
+    let id = *nodes;
+    let (label, children) = match expr {
+        Expression::None => ("none".to_owned(), vec![]),
+        Expression::External => ("external".to_owned(), vec![]),
+        Expression::NumberValue { value } => (value.to_string(), vec![]),
+        Expression::Value { value: Value::String(value) } => (format!("{:?}", value), vec![]),
+        Expression::Value { value } => (value.to_string(), vec![]),
+        Expression::VariableAccess { variable } => (variable.to_owned(), vec![]),
+        Expression::Negate { value, .. } => ("-".to_owned(), vec![value.as_ref()]),
+        Expression::Conditional { condition, then, otherwise } => ("?:".to_owned(), vec![condition.as_ref(), then.as_ref(), otherwise.as_ref()]),
+        Expression::Math { var1, var2, math, .. } => (math.operator(), vec![var1.as_ref(), var2.as_ref()]),
+        Expression::FunctionInvocation { function, arguments, .. } => (format!("{}()", function), arguments.iter().collect()),
+        Expression::VariableAssignment { variable, value } => (format!("{} =", variable), vec![value.as_ref()]),
+        Expression::Pointer { to } => (format!("{}*", to), vec![]),
+        Expression::ResolvedVariable { variable, .. } => (variable.to_owned(), vec![]),
+        Expression::ResolvedInvocation { function, arguments, .. } => (format!("{}()", function), arguments.iter().collect()),
+        Expression::Match { value, arms } => ("match".to_owned(), std::iter::once(value.as_ref()).chain(arms.iter().flat_map(|arm| arm.pattern.iter().chain(std::iter::once(&arm.value)))).collect()),
+        Expression::Comprehension { value, variable, source, condition } => (format!("for {}", variable), vec![value.as_ref(), source.as_ref()].into_iter().chain(condition.as_deref()).collect()),
+        Expression::Where { variable, value, body } => (format!("where {}", variable), vec![value.as_ref(), body.as_ref()]),
+        Expression::Block { expressions } => (";".to_owned(), expressions.iter().collect()),
+        Expression::Index { value, index, .. } => ("[]".to_owned(), vec![value.as_ref(), index.as_ref()]),
+        Expression::Slice { value, from, to, .. } => ("[:]".to_owned(), std::iter::once(value.as_ref()).chain(from.as_deref()).chain(to.as_deref()).collect())
+    };
+
+    *nodes += 1;
+
+    out.push_str(&format!("        n{} [label=\"{}\"];\n", id, escape(&label)));
+
+    for child in children {
+        let child = node(child, out, nodes);
+
+        out.push_str(&format!("        n{} -> n{};\n", id, child));
+    }
+
+    id
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
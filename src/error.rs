@@ -0,0 +1,345 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result};
+use std::panic::{catch_unwind, panic_any, set_hook, take_hook, AssertUnwindSafe};
+use std::any::Any;
+use crate::ast::Span;
+use crate::numeric;
+use std::cell::{Cell, RefCell};
+use std::sync::{Arc, Once};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum ErrorCode {
+    UnrecognizedToken,
+    MalformedInclude,
+    IncludeNotFound,
+    RecursiveInclude,
+    InvalidManifest,
+    UnresolvedDependency,
+    UnsupportedVersion,
+    UnexpectedToken,
+    UndefinedVariable,
+    UndefinedFunction,
+    UnbalancedParentheses,
+    ConstantReassignment,
+    InvalidAssignment,
+    InvalidMacro,
+    StrictViolation,
+    ArityMismatch,
+    TypeMismatch,
+    IndexOutOfBounds,
+    InvalidArgument,
+    InvalidInput,
+    CachedPointer,
+    ResourceLimit,
+    AssertionFailed,
+    Overflow,
+    NoMatch,
+    Internal
+}
+
+impl ErrorCode {
+    pub fn code(&self) -> &'static str {
+        match *self {
+            ErrorCode::UnrecognizedToken => "E0001",
+            ErrorCode::MalformedInclude => "E0002",
+            ErrorCode::IncludeNotFound => "E0003",
+            ErrorCode::RecursiveInclude => "E0004",
+            ErrorCode::InvalidManifest => "E0005",
+            ErrorCode::UnresolvedDependency => "E0006",
+            ErrorCode::UnsupportedVersion => "E0007",
+            ErrorCode::UnexpectedToken => "E0101",
+            ErrorCode::UndefinedVariable => "E0102",
+            ErrorCode::UndefinedFunction => "E0103",
+            ErrorCode::UnbalancedParentheses => "E0104",
+            ErrorCode::ConstantReassignment => "E0105",
+            ErrorCode::InvalidAssignment => "E0106",
+            ErrorCode::InvalidMacro => "E0107",
+            ErrorCode::StrictViolation => "E0108",
+            ErrorCode::ArityMismatch => "E0109",
+            ErrorCode::TypeMismatch => "E0201",
+            ErrorCode::IndexOutOfBounds => "E0202",
+            ErrorCode::InvalidArgument => "E0203",
+            ErrorCode::InvalidInput => "E0204",
+            ErrorCode::CachedPointer => "E0205",
+            ErrorCode::ResourceLimit => "E0206",
+            ErrorCode::AssertionFailed => "E0207",
+            ErrorCode::Overflow => "E0208",
+            ErrorCode::NoMatch => "E0209",
+            ErrorCode::Internal => "E0900"
+        }
+    }
+
+    pub fn summary(&self) -> &'static str {
+        match *self {
+            ErrorCode::UnrecognizedToken => "unrecognized token",
+            ErrorCode::MalformedInclude => "malformed include directive",
+            ErrorCode::IncludeNotFound => "included file not found",
+            ErrorCode::RecursiveInclude => "recursive include",
+            ErrorCode::InvalidManifest => "invalid project manifest",
+            ErrorCode::UnresolvedDependency => "unresolved dependency",
+            ErrorCode::UnsupportedVersion => "unsupported language version",
+            ErrorCode::UnexpectedToken => "syntax error",
+            ErrorCode::UndefinedVariable => "undefined variable",
+            ErrorCode::UndefinedFunction => "undefined function",
+            ErrorCode::UnbalancedParentheses => "unbalanced parentheses",
+            ErrorCode::ConstantReassignment => "reassignment of a constant",
+            ErrorCode::InvalidAssignment => "invalid assignment target",
+            ErrorCode::InvalidMacro => "invalid macro invocation",
+            ErrorCode::StrictViolation => "strict mode violation",
+            ErrorCode::ArityMismatch => "wrong number of arguments",
+            ErrorCode::TypeMismatch => "type mismatch",
+            ErrorCode::IndexOutOfBounds => "index out of bounds",
+            ErrorCode::InvalidArgument => "invalid argument",
+            ErrorCode::InvalidInput => "invalid input",
+            ErrorCode::CachedPointer => "pointer passed to a cached function",
+            ErrorCode::ResourceLimit => "resource limit exceeded",
+            ErrorCode::AssertionFailed => "assertion failed",
+            ErrorCode::Overflow => "arithmetic overflow",
+            ErrorCode::NoMatch => "no matching arm",
+            ErrorCode::Internal => "internal error"
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match *self {
+            ErrorCode::UnrecognizedToken => "The lexer found characters that don't belong to any token. Every occurrence is reported before parsing starts.",
+            ErrorCode::MalformedInclude => "An #include directive has to be followed by a file name in double quotes.",
+            ErrorCode::IncludeNotFound => "The file named by an #include directive doesn't exist or can't be read. Paths are resolved relative to the including file.",
+            ErrorCode::RecursiveInclude => "A file includes itself, either directly or through other included files.",
+            ErrorCode::InvalidManifest => "The math.toml of the project can't be read, isn't valid TOML or contains unsupported settings.",
+            ErrorCode::UnresolvedDependency => "A dependency in math.toml can't be fetched, either because its source is unreachable, it isn't in the registry or two packages require it from different sources.",
+            ErrorCode::UnsupportedVersion => "The #lang pragma of a file is malformed, targets a newer language version than this interpreter supports, or the file uses syntax that the version it targets doesn't have yet.",
+            ErrorCode::UnexpectedToken => "The parser found a token that can't appear at this position, for example a missing = in a definition or a stray operator.",
+            ErrorCode::UndefinedVariable => "A name is used that isn't a variable in scope. Function parameters are only visible inside their own function.",
+            ErrorCode::UndefinedFunction => "A function is invoked that doesn't exist, or that doesn't take the supplied number of arguments.",
+            ErrorCode::UnbalancedParentheses => "An opening parenthesis has no matching closing parenthesis.",
+            ErrorCode::ConstantReassignment => "A variable declared with const is assigned a new value.",
+            ErrorCode::InvalidAssignment => "The left side of = has to be a variable.",
+            ErrorCode::InvalidMacro => "A macro is invoked with the wrong number of arguments or expands into itself endlessly.",
+            ErrorCode::StrictViolation => "Strict mode (--strict or #pragma strict) rejects discarded values, functions that use non constant globals, parameters that shadow variables and definitions that are defined twice.",
+            ErrorCode::ArityMismatch => "A function is called with a number of arguments that none of its definitions takes.",
+            ErrorCode::TypeMismatch => "A value of the wrong type is used, for example a list where a number is expected.",
+            ErrorCode::IndexOutOfBounds => "A list is indexed with a position that is negative or not smaller than its length.",
+            ErrorCode::InvalidArgument => "A builtin received an argument outside of its supported range, for example a negative exponent.",
+            ErrorCode::InvalidInput => "Text read from standard input couldn't be interpreted as the expected value.",
+            ErrorCode::CachedPointer => "Cached functions remember results by their arguments, so they can't be called with a pointer (name*).",
+            ErrorCode::ResourceLimit => "The program exceeded a limit set in the project manifest, like the maximum call depth or the time limit.",
+            ErrorCode::AssertionFailed => "An assert, assert_eq or assert_near call found that its condition doesn't hold. The message shows the asserted expressions and their values.",
+            ErrorCode::Overflow => "A result doesn't fit in the numeric backend's range, for example with the checked 64-bit backend (numeric = \"checked\").",
+            ErrorCode::NoMatch => "None of the arms of a match expression matched the value and there's no _ arm.",
+            ErrorCode::Internal => "The interpreter itself failed. This is a bug, please report it together with the program that caused it."
+        }
+    }
+
+    pub fn example(&self) -> &'static str {
+        match *self {
+            ErrorCode::UnrecognizedToken => "let x = 1 $ 2",
+            ErrorCode::MalformedInclude => "#include lib.math",
+            ErrorCode::IncludeNotFound => "#include \"missing.math\"",
+            ErrorCode::RecursiveInclude => "#include \"self.math\" # inside self.math",
+            ErrorCode::InvalidManifest => "[package]\nentry = \"main.math\"\nnumeric = \"float\"",
+            ErrorCode::UnresolvedDependency => "[dependencies]\nprimes = { path = \"../does-not-exist\" }",
+            ErrorCode::UnsupportedVersion => "#lang math 0.1\ndefine sum(xs...) = xs",
+            ErrorCode::UnexpectedToken => "let x 1",
+            ErrorCode::UndefinedVariable => "let x = 1\nprintln(y)",
+            ErrorCode::UndefinedFunction => "define f(a) = a\nprintln(f(1, 2))",
+            ErrorCode::UnbalancedParentheses => "let x = (1 + 2",
+            ErrorCode::ConstantReassignment => "const x = 1\nx = 2",
+            ErrorCode::InvalidAssignment => "1 = 2",
+            ErrorCode::InvalidMacro => "macro twice(x) = x + x\nprintln(twice(1, 2))",
+            ErrorCode::StrictViolation => "#pragma strict\nlet x = 1\n1 + x",
+            ErrorCode::ArityMismatch => "define f(x) = x\nprintln(f(1, 2))",
+            ErrorCode::TypeMismatch => "println(list(1, 2) + 1)",
+            ErrorCode::IndexOutOfBounds => "println(get(list(1, 2), 2))",
+            ErrorCode::InvalidArgument => "println(2 ^ -1)",
+            ErrorCode::InvalidInput => "let x = input() # answered with 'abc'",
+            ErrorCode::CachedPointer => "define cache f(a) = a\nlet x = 1\nprintln(f(x*))",
+            ErrorCode::ResourceLimit => "define down(n) = if(n == 0, 0, down(n - 1))\nprintln(down(100000)) # with [limits] max_depth = 1000",
+            ErrorCode::AssertionFailed => "let x = 2 + 2\nassert_eq(x, 5)",
+            ErrorCode::Overflow => "println(9223372036854775807 + 1)",
+            ErrorCode::NoMatch => "match 3 | 1 -> \"one\" | 2 -> \"two\"",
+            ErrorCode::Internal => ""
+        }
+    }
+
+    pub fn fix(&self) -> String {
+        let fix = match *self {
+            ErrorCode::UnrecognizedToken => "Remove the characters or replace them with a supported operator.",
+            ErrorCode::MalformedInclude => "Quote the file name: #include \"lib.math\"",
+            ErrorCode::IncludeNotFound => "Check the spelling of the path and that it is relative to the including file.",
+            ErrorCode::RecursiveInclude => "Remove the include that closes the cycle, or move the shared definitions into a separate file.",
+            ErrorCode::InvalidManifest => return format!("Fix the setting named in the message. numeric is one of: {}; division is one of: {} (floor and exact only with integer and checked).", numeric::MODES, numeric::DIVISIONS),
+            ErrorCode::UnresolvedDependency => "Check the git URL, revision or path of the dependency, or set MATH_REGISTRY to a registry that contains it.",
+            ErrorCode::UnsupportedVersion => "Update the interpreter, or raise the version in the #lang pragma if the file is meant to use newer syntax.",
+            ErrorCode::UnexpectedToken => "Follow the hint at the caret, e.g. let x = 1",
+            ErrorCode::UndefinedVariable => "Declare the variable with let or const before using it, or fix the spelling.",
+            ErrorCode::UndefinedFunction => "Define the function, fix its name, or pass as many arguments as it has parameters.",
+            ErrorCode::UnbalancedParentheses => "Add the missing closing parenthesis: let x = (1 + 2)",
+            ErrorCode::ConstantReassignment => "Declare the variable with let if it needs to change.",
+            ErrorCode::InvalidAssignment => "Assign to a variable instead: let x = 2",
+            ErrorCode::InvalidMacro => "Pass one argument per macro parameter and make sure macros don't expand into themselves.",
+            ErrorCode::StrictViolation => "Use the value (e.g. println(1 + x)), pass globals as parameters or make them const, and rename shadowing definitions.",
+            ErrorCode::ArityMismatch => "Pass as many arguments as one of the definitions listed in the message takes, or define an overload for this number.",
+            ErrorCode::TypeMismatch => "Convert or unpack the value first, e.g. get(list(1, 2), 0) + 1",
+            ErrorCode::IndexOutOfBounds => "Compare the index against len(list) before accessing it. Indices start at 0.",
+            ErrorCode::InvalidArgument => "Pass an argument within the documented range of the builtin.",
+            ErrorCode::InvalidInput => "Enter a whole number.",
+            ErrorCode::CachedPointer => "Pass the value instead of a pointer, or remove cache from the definition.",
+            ErrorCode::ResourceLimit => "Raise the limit in math.toml or make the program do less work, e.g. by caching recursive functions.",
+            ErrorCode::AssertionFailed => "Fix the program or the expected value in the assertion.",
+            ErrorCode::Overflow => "Use the default integer backend, which switches to arbitrary precision on overflow.",
+            ErrorCode::NoMatch => "Add a last arm '| _ -> value' for everything else.",
+            ErrorCode::Internal => "There is nothing wrong with your program."
+        };
+
+        fix.to_owned()
+    }
+
+    pub fn explain(&self) -> String {
+        let mut out = format!("{}: {}\n\n{}\n", self.code(), self.summary(), self.description());
+
+        if !self.example().is_empty() {
+            out.push_str(&format!("\nErroneous example:\n\n{}", self.example().lines().map(|l| format!("    {}\n", l)).collect::<String>()));
+        }
+
+        out.push_str(&format!("\nFix:\n\n{}\n", self.fix()));
+
+        out
+    }
+
+    pub fn entries() -> Vec<ErrorCode> {
+        vec![ErrorCode::UnrecognizedToken, ErrorCode::MalformedInclude, ErrorCode::IncludeNotFound, ErrorCode::RecursiveInclude, ErrorCode::InvalidManifest, ErrorCode::UnresolvedDependency, ErrorCode::UnsupportedVersion, ErrorCode::UnexpectedToken, ErrorCode::UndefinedVariable, ErrorCode::UndefinedFunction, ErrorCode::UnbalancedParentheses, ErrorCode::ConstantReassignment, ErrorCode::InvalidAssignment, ErrorCode::InvalidMacro, ErrorCode::StrictViolation, ErrorCode::ArityMismatch, ErrorCode::TypeMismatch, ErrorCode::IndexOutOfBounds, ErrorCode::InvalidArgument, ErrorCode::InvalidInput, ErrorCode::CachedPointer, ErrorCode::ResourceLimit, ErrorCode::AssertionFailed, ErrorCode::Overflow, ErrorCode::NoMatch, ErrorCode::Internal]
+    }
+
+    pub fn of(code: &str) -> Option<ErrorCode> {
+        ErrorCode::entries().into_iter().find(|c| c.code().eq_ignore_ascii_case(code))
+    }
+}
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MathError {
+    code: ErrorCode,
+    message: String,
+    location: Option<String> // rendered source snippet pointing at the offending token
+}
+
+impl MathError {
+    pub fn new(code: ErrorCode, message: &str) -> Self {
+        MathError {
+            code,
+            message: message.to_owned(),
+            location: None
+        }
+    }
+
+    pub fn at(code: ErrorCode, message: &str, location: String) -> Self {
+        MathError {
+            code,
+            message: message.to_owned(),
+            location: Some(location)
+        }
+    }
+
+    // anything that isn't a MathError is a bug in the interpreter itself
+    pub fn from_panic(payload: &(dyn Any + Send)) -> Self {
+        if let Some(error) = payload.downcast_ref::<MathError>() {
+            return error.clone();
+        }
+
+        MathError::new(ErrorCode::Internal, payload.downcast_ref::<String>().map(String::as_str).or_else(|| payload.downcast_ref::<&str>().copied()).unwrap_or("Unknown error"))
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        self.code
+    }
+
+    pub fn message(&self) -> &String {
+        &self.message
+    }
+
+    pub fn location(&self) -> &Option<String> {
+        &self.location
+    }
+}
+
+impl Display for MathError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "error[{}]: {}", self.code, self.message)?;
+
+        if let Some(location) = &self.location {
+            write!(f, "{}", location)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Error for MathError {}
+
+// errors unwind to the nearest boundary (the panic hook, the REPL loop, ...) carrying a MathError,
+// one raised while an expression with a span is evaluated points at the innermost one
+pub fn raise(code: ErrorCode, message: &str) -> ! {
+    match SPANS.with(|spans| spans.borrow().last().cloned()) {
+        Some(span) => raise_at(code, message, span.diagnostic(message, 0)),
+        None => panic_any(MathError::new(code, message))
+    }
+}
+
+// evaluates f as the expression written at the span (see raise)
+pub fn located<T>(span: &Arc<Span>, f: impl FnOnce() -> T) -> T {
+    struct Pop; // also leaves when unwinding
+
+    impl Drop for Pop {
+        fn drop(&mut self) {
+            SPANS.with(|spans| spans.borrow_mut().pop());
+        }
+    }
+
+    SPANS.with(|spans| spans.borrow_mut().push(span.clone()));
+
+    let _pop = Pop;
+
+    f()
+}
+
+pub fn raise_at(code: ErrorCode, message: &str, location: String) -> ! {
+    panic_any(MathError::at(code, message, location))
+}
+
+// an error that was caught somewhere else (e.g. on another thread) continues unwinding here
+pub fn rethrow(error: MathError) -> ! {
+    panic_any(error)
+}
+
+thread_local! {
+    static CATCHING: Cell<usize> = const { Cell::new(0) }; // nested catch calls on this thread
+    static SPANS: RefCell<Vec<Arc<Span>>> = const { RefCell::new(Vec::new()) }; // of the expressions being evaluated on this thread, innermost last
+}
+
+static SILENCE: Once = Once::new();
+
+// the boundary for callers that handle errors themselves, so they aren't reported by the panic hook
+// the hook is wrapped once instead of being swapped on every call, swapping would race with other threads doing the same
+pub fn catch<T>(f: impl FnOnce() -> T) -> std::result::Result<T, MathError> {
+    SILENCE.call_once(|| {
+        let hook = take_hook();
+
+        set_hook(Box::new(move |info| if CATCHING.with(Cell::get) == 0 {
+            hook(info)
+        }));
+    });
+
+    CATCHING.with(|c| c.set(c.get() + 1));
+
+    let result = catch_unwind(AssertUnwindSafe(f));
+
+    CATCHING.with(|c| c.set(c.get() - 1));
+
+    result.map_err(|payload| MathError::from_panic(payload.as_ref()))
+}
@@ -0,0 +1,99 @@
+use crate::lexer::{comment_pragma, spans, LexerData};
+
+// tokens after which a - is an operator instead of a sign (keywords like step aside)
+const OPERANDS: [&str; 9] = ["IDENTIFIER", "NUMBER", "STRING", "NONE", "WILDCARD", "CLOSE_PARENTHESIS", "CLOSE_BRACKET", "CLOSE_BRACE", "ELLIPSIS"];
+
+// the canonical layout of a source, line by line: indentation, line breaks and comments are kept, the tokens of a line are
+// separated by single spaces (except around brackets, dots, ranges and colons), runs of blank lines become one,
+// lines that don't lex are left alone
+pub fn format(source: &str, comment: &str, data: &LexerData) -> String {
+    let comment = comment_pragma(source).unwrap_or_else(|| comment.to_owned());
+    let mut lines = Vec::<String>::new();
+
+    for line in source.lines() {
+        let formatted = if line.trim_start().starts_with(&comment) { // comments, doc comments and pragmas
+            line.trim_end().to_owned()
+        } else {
+            format_line(line, &comment, data)
+        };
+
+        if formatted.is_empty() && lines.last().is_none_or(String::is_empty) {
+            continue;
+        }
+
+        lines.push(formatted);
+    }
+
+    while lines.last().is_some_and(String::is_empty) {
+        lines.pop();
+    }
+
+    lines.iter().map(|l| format!("{}\n", l)).collect()
+}
+
+fn format_line(line: &str, comment: &str, data: &LexerData) -> String {
+    let tokens = spans(line, comment, data).into_iter().filter(|(id, _)| id.ne(&"WHITESPACE")).collect::<Vec<_>>();
+
+    if tokens.iter().any(|(id, _)| id.eq(&"ERROR")) {
+        return line.trim_end().to_owned();
+    }
+
+    let mut out = line[..line.len() - line.trim_start().len()].to_owned(); // the indentation
+    let mut previous = None::<&str>;
+    let mut operand = false; // the previous token ends an operand, so a - after it is binary
+    let mut sign = false; // the previous token was a prefix - or !
+    let mut questions = vec![0]; // the ? without their : per bracket, a : that ends one is spaced unlike the : of a slice
+    let mut ternary = false; // the previous token was such a :
+
+    for (id, range) in tokens {
+        let text = &line[range];
+
+        if id.eq("COMMENT") {
+            if previous.is_some() {
+                out.push(' ');
+            }
+
+            out.push_str(text.trim_end());
+
+            continue;
+        }
+
+        let postfix = id.eq("NOT") && operand; // n!
+        let colon = id.eq("COLON") && questions.last().is_some_and(|q| *q > 0);
+
+        match id {
+            "OPEN_PARENTHESIS" | "OPEN_BRACKET" | "OPEN_BRACE" => questions.push(0),
+            "CLOSE_PARENTHESIS" | "CLOSE_BRACKET" | "CLOSE_BRACE" if questions.len() > 1 => {
+                questions.pop();
+            },
+            "QUESTION" => *questions.last_mut().unwrap() += 1,
+            _ if colon => *questions.last_mut().unwrap() -= 1,
+            _ => {}
+        }
+
+        if let Some(previous) = previous {
+            if !sign && !postfix && (colon || ternary || spaced(previous, id)) {
+                out.push(' ');
+            }
+        }
+
+        ternary = colon;
+
+        sign = (id.eq("MINUS") || id.eq("NOT")) && !operand;
+        operand = postfix || (OPERANDS.contains(&id) && !(id.eq("IDENTIFIER") && data.contextual(text).is_some()));
+        previous = Some(id);
+        out.push_str(text);
+    }
+
+    out
+}
+
+fn spaced(previous: &str, next: &str) -> bool {
+    match (previous, next) {
+        ("OPEN_PARENTHESIS" | "OPEN_BRACKET" | "OPEN_BRACE" | "DOT" | "RANGE" | "COLON", _) => false,
+        (_, "CLOSE_PARENTHESIS" | "CLOSE_BRACKET" | "CLOSE_BRACE" | "COMMA" | "SEMICOLON" | "DOT" | "RANGE" | "COLON" | "ELLIPSIS") => false,
+        ("IDENTIFIER", "OPEN_PARENTHESIS") => false, // calls and definitions
+        ("IDENTIFIER" | "STRING" | "CLOSE_PARENTHESIS" | "CLOSE_BRACKET", "OPEN_BRACKET") => false, // indexing
+        _ => true
+    }
+}
@@ -0,0 +1,123 @@
+use crate::default_externals;
+use crate::error::catch;
+use crate::interpreter::run_at;
+use crate::interpreter::runtime::RuntimeAST;
+use crate::io::Streams;
+use crate::lexer::{full_lex, LexerData};
+use crate::parser::parse;
+use crate::style;
+use crate::vfs::MemoryFs;
+use std::fs::{read_dir, read_to_string};
+use std::io::Cursor;
+use std::mem::take;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+// every example.math in the directory is run with example.stdin as its input (if present) an empty in-memory filesystem and a clock that stands still, its output has to match example.expected
+pub fn golden(dir: &Path) -> bool {
+    let mut examples = read_dir(dir).expect("Error while reading directory").map(|e| e.unwrap().path()).filter(|p| p.extension().is_some_and(|e| e.eq("math"))).collect::<Vec<PathBuf>>();
+    let mut failed = Vec::<PathBuf>::new();
+    let styled = style::enabled();
+    let ok = if styled { style::color("ok", 32) } else { "ok".to_owned() };
+    let failed_label = if styled { style::bold(&style::color("FAILED", 31)) } else { "FAILED".to_owned() };
+
+    examples.sort();
+
+    for example in &examples {
+        let expected = read_to_string(example.with_extension("expected"));
+
+        if expected.is_err() {
+            println!("test {} ... {} (no {})", example.display(), failed_label, example.with_extension("expected").display());
+
+            failed.push(example.clone());
+
+            continue;
+        }
+
+        let expected = expected.unwrap();
+        let actual = run(example);
+
+        if actual.eq(&expected) {
+            println!("test {} ... {}", example.display(), ok);
+        } else {
+            println!("test {} ... {}", example.display(), failed_label);
+            print!("{}", diff(&expected, &actual));
+
+            failed.push(example.clone());
+        }
+    }
+
+    println!();
+    println!("test result: {}. {} passed; {} failed", if failed.is_empty() { &ok } else { &failed_label }, examples.len() - failed.len(), failed.len());
+
+    failed.is_empty()
+}
+
+fn run(example: &Path) -> String {
+    let content = read_to_string(example).expect("Error while reading file");
+    let stdin = read_to_string(example.with_extension("stdin")).unwrap_or_default();
+    let output = Arc::new(Mutex::new(Vec::<u8>::new()));
+    let sink = output.clone();
+    // diagnostics are part of the output, so they mustn't go to the terminal
+    let result = catch(|| {
+        let mut ast = parse(full_lex(content, example.to_str().unwrap().to_owned(), "#".to_owned(), &LexerData::default()), default_externals());
+        let loose_expressions = take(&mut ast.loose_expressions);
+        let locations = take(&mut ast.locations);
+        let mut runtime = RuntimeAST::create_with_io(ast, default_externals(), Arc::new(Streams::new(Arc::new(Mutex::new(Cursor::new(stdin.into_bytes()))), sink)));
+
+        runtime.fs = Arc::new(MemoryFs::new()); // files written by an example don't end up on disk
+        runtime.deterministic = true;
+
+        run_at(&mut runtime, &loose_expressions, &locations);
+    });
+    let mut output = String::from_utf8_lossy(&output.lock().unwrap()).into_owned();
+
+    if let Err(err) = result {
+        output.push_str(&format!("{}\n", err));
+    }
+
+    output
+}
+
+// line based diff over the longest common subsequence, - lines are expected but missing, + lines are unexpected
+fn diff(expected: &str, actual: &str) -> String {
+    let a = expected.lines().collect::<Vec<&str>>();
+    let b = actual.lines().collect::<Vec<&str>>();
+    let mut lengths = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i][j] = if a[i].eq(b[j]) {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = String::new();
+    let (mut i, mut j) = (0, 0);
+
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i].eq(b[j]) {
+            out.push_str(&format!("      {}\n", a[i]));
+
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || lengths[i + 1][j] >= lengths[i][j + 1]) {
+            out.push_str(&format!("    - {}\n", a[i]));
+
+            i += 1;
+        } else {
+            out.push_str(&format!("    + {}\n", b[j]));
+
+            j += 1;
+        }
+    }
+
+    if out.is_empty() || expected.ends_with('\n') != actual.ends_with('\n') {
+        out.push_str("    (trailing newline differs)\n");
+    }
+
+    out
+}
@@ -0,0 +1,249 @@
+use crate::ast::{AST, Expression, Function};
+
+const MAIN: &str = "<main>";
+
+pub struct CallGraph {
+    pub nodes: Vec<CallNode>,
+    pub calls: Vec<(usize, usize)>, // indices into nodes, caller -> callee
+    pub cycles: Vec<Vec<usize>> // groups of functions that (mutually) recurse
+}
+
+pub struct CallNode {
+    pub name: String,
+    pub external: bool
+}
+
+// functions are identified by name and arity since they can be overloaded, the loose expressions form <main>
+pub fn call_graph(ast: &AST) -> CallGraph {
+    let mut nodes = vec![CallNode {
+        name: MAIN.to_owned(),
+        external: false
+    }];
+    let mut calls = Vec::<(usize, usize)>::new();
+
+    nodes.extend(ast.functions.iter().map(|f| CallNode {
+        name: node_name(f),
+        external: Expression::External == f.definition
+    }));
+
+    for (i, f) in ast.functions.iter().enumerate() {
+        let mut invoked = Vec::<(String, usize)>::new();
+
+        invocations(&f.definition, &mut invoked);
+
+        calls.extend(resolve(ast, i + 1, invoked));
+    }
+
+    let mut invoked = Vec::<(String, usize)>::new();
+
+    ast.loose_expressions.iter().for_each(|expr| invocations(expr, &mut invoked));
+
+    calls.extend(resolve(ast, 0, invoked));
+    calls.sort();
+    calls.dedup();
+
+    let cycles = cycles(nodes.len(), &calls);
+
+    CallGraph {
+        nodes,
+        calls,
+        cycles
+    }
+}
+
+fn node_name(f: &Function) -> String {
+    format!("{}/{}{}", f.name, if f.variadic { f.parameters.len() - 1 } else { f.parameters.len() }, if f.variadic { "+" } else { "" })
+}
+
+fn invocations(expr: &Expression, out: &mut Vec<(String, usize)>) {
+    match expr {
+        Expression::Negate { value, .. } => invocations(value, out),
+        Expression::Conditional { condition, then, otherwise } => {
+            invocations(condition, out);
+            invocations(then, out);
+            invocations(otherwise, out);
+        },
+        Expression::Math { var1, var2, .. } => {
+            invocations(var1, out);
+            invocations(var2, out);
+        },
+        Expression::FunctionInvocation { function, arguments, .. } | Expression::ResolvedInvocation { function, arguments, .. } => {
+            out.push((function.to_owned(), arguments.len()));
+
+            arguments.iter().for_each(|arg| invocations(arg, out));
+        },
+        Expression::VariableAssignment { value, .. } => invocations(value, out),
+        Expression::Match { value, arms } => {
+            invocations(value, out);
+
+            for arm in arms {
+                arm.pattern.iter().for_each(|pattern| invocations(pattern, out));
+                invocations(&arm.value, out);
+            }
+        },
+        Expression::Comprehension { value, source, condition, .. } => {
+            invocations(value, out);
+            invocations(source, out);
+            condition.iter().for_each(|c| invocations(c, out));
+        },
+        Expression::Where { value, body, .. } => {
+            invocations(value, out);
+            invocations(body, out);
+        },
+        Expression::Block { expressions } => expressions.iter().for_each(|expr| invocations(expr, out)),
+        Expression::Index { value, index, .. } => {
+            invocations(value, out);
+            invocations(index, out);
+        },
+        Expression::Slice { value, from, to, .. } => {
+            invocations(value, out);
+            from.iter().chain(to).for_each(|bound| invocations(bound, out));
+        },
+        _ => {}
+    }
+}
+
+// exact arity matches win over variadic ones, the same way the interpreter picks them
+fn resolve(ast: &AST, caller: usize, invoked: Vec<(String, usize)>) -> Vec<(usize, usize)> {
+    invoked.into_iter().filter_map(|(name, arguments)| {
+        ast.functions.iter().position(|f| f.name.eq(&name) && !f.variadic && f.parameters.len() == arguments)
+            .or_else(|| ast.functions.iter().position(|f| f.name.eq(&name) && f.accepts(arguments)))
+            .map(|callee| (caller, callee + 1))
+    }).collect()
+}
+
+// strongly connected components (Tarjan), a component is a cycle if it has more than one function or calls itself
+fn cycles(nodes: usize, calls: &[(usize, usize)]) -> Vec<Vec<usize>> {
+    struct State<'a> {
+        calls: &'a [(usize, usize)],
+        index: usize,
+        indices: Vec<Option<usize>>,
+        lowlinks: Vec<usize>,
+        stack: Vec<usize>,
+        on_stack: Vec<bool>,
+        components: Vec<Vec<usize>>
+    }
+
+    fn connect(v: usize, state: &mut State) {
+        state.indices[v] = Some(state.index);
+        state.lowlinks[v] = state.index;
+        state.index += 1;
+        state.stack.push(v);
+        state.on_stack[v] = true;
+
+        for w in state.calls.iter().filter(|(from, _)| *from == v).map(|(_, to)| *to).collect::<Vec<usize>>() {
+            if state.indices[w].is_none() {
+                connect(w, state);
+
+                state.lowlinks[v] = state.lowlinks[v].min(state.lowlinks[w]);
+            } else if state.on_stack[w] {
+                state.lowlinks[v] = state.lowlinks[v].min(state.indices[w].unwrap());
+            }
+        }
+
+        if Some(state.lowlinks[v]) == state.indices[v] {
+            let mut component = Vec::<usize>::new();
+
+            loop {
+                let w = state.stack.pop().unwrap();
+
+                state.on_stack[w] = false;
+                component.push(w);
+
+                if w == v {
+                    break;
+                }
+            }
+
+            component.sort();
+            state.components.push(component);
+        }
+    }
+
+    let mut state = State {
+        calls,
+        index: 0,
+        indices: vec![None; nodes],
+        lowlinks: vec![0; nodes],
+        stack: vec![],
+        on_stack: vec![false; nodes],
+        components: vec![]
+    };
+
+    for v in 0..nodes {
+        if state.indices[v].is_none() {
+            connect(v, &mut state);
+        }
+    }
+
+    let mut cycles = state.components.into_iter().filter(|c| c.len() > 1 || calls.contains(&(c[0], c[0]))).collect::<Vec<Vec<usize>>>();
+
+    cycles.sort();
+
+    cycles
+}
+
+impl CallGraph {
+    pub fn recursive(&self, node: usize) -> bool {
+        self.cycles.iter().any(|c| c.contains(&node))
+    }
+
+    fn same_cycle(&self, from: usize, to: usize) -> bool {
+        self.cycles.iter().any(|c| c.contains(&from) && c.contains(&to))
+    }
+
+    // externals that are never called are left out
+    fn shown(&self, node: usize) -> bool {
+        !self.nodes[node].external || self.calls.iter().any(|(_, to)| *to == node)
+    }
+
+    pub fn dot(&self) -> String {
+        let mut out = "digraph calls {\n    node [fontname=\"monospace\"];\n".to_owned();
+
+        for (i, node) in self.nodes.iter().enumerate().filter(|(i, _)| self.shown(*i)) {
+            let style = if node.external {
+                ", shape=ellipse, style=dashed"
+            } else if self.recursive(i) {
+                ", shape=box, color=red"
+            } else {
+                ", shape=box"
+            };
+
+            out.push_str(&format!("    n{} [label=\"{}\"{}];\n", i, escape(&node.name), style));
+        }
+
+        for (from, to) in &self.calls {
+            out.push_str(&format!("    n{} -> n{}{};\n", from, to, if self.same_cycle(*from, *to) { " [color=red]" } else { "" }));
+        }
+
+        out.push_str("}\n");
+
+        out
+    }
+
+    pub fn json(&self) -> String {
+        let functions = self.nodes.iter().enumerate().filter(|(i, _)| self.shown(*i))
+            .map(|(i, n)| format!("    {{\"name\": \"{}\", \"external\": {}, \"recursive\": {}}}", escape(&n.name), n.external, self.recursive(i)))
+            .collect::<Vec<String>>();
+        let calls = self.calls.iter()
+            .map(|(from, to)| format!("    {{\"from\": \"{}\", \"to\": \"{}\"}}", escape(&self.nodes[*from].name), escape(&self.nodes[*to].name)))
+            .collect::<Vec<String>>();
+        let cycles = self.cycles.iter()
+            .map(|c| format!("    [{}]", c.iter().map(|n| format!("\"{}\"", escape(&self.nodes[*n].name))).collect::<Vec<String>>().join(", ")))
+            .collect::<Vec<String>>();
+
+        format!("{{\n  \"functions\": {},\n  \"calls\": {},\n  \"cycles\": {}\n}}\n", array(functions), array(calls), array(cycles))
+    }
+}
+
+fn array(items: Vec<String>) -> String {
+    if items.is_empty() {
+        "[]".to_owned()
+    } else {
+        format!("[\n{}\n  ]", items.join(",\n"))
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -1,62 +1,193 @@
-use crate::ast::{AST, Expression, MathType, Function, Variable};
-use num_bigint::BigInt;
-use std::ops::{Add, Sub, Mul, Div};
-use crate::interpreter::runtime::{RuntimeAST, RuntimeExpression, Tuple, RuntimeFunction, RuntimeVariable, ExternalRuntimeFunction};
-
+use crate::ast::{AST, Expression, MathType, MatchArm, Function, Variable, Slot, CallTarget, Location, Span, arity_mismatch};
+use crate::interpreter::runtime::{RuntimeAST, RuntimeExpression, RuntimeFunction, RuntimeVariable, ExternalRuntimeFunction, FnId, Limits, Hooks, Eval, Invocation};
+use crate::value::Value;
+use crate::numeric::{Division, Integer};
+use crate::style;
+use crate::io::{Io, StdIo};
+use crate::vfs::RealFs;
+use log::{debug, log_enabled, trace, Level};
+use crate::error::{ErrorCode, MathError, catch, located, raise, rethrow};
+use std::sync::Arc;
+use std::collections::HashMap;
+use std::mem::take;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use std::convert::TryFrom;
+use num_bigint::{BigInt, Sign};
+
+pub mod profile;
 pub mod runtime;
+pub mod steps;
 
 pub fn interpret(ast: AST, external_functions: Vec<ExternalRuntimeFunction>) {
-    let mut runtime = RuntimeAST::create(ast.clone(), external_functions);
-    let exprs = ast.loose_expressions.clone().into_iter().map(|expr| RuntimeExpression::from(expr, &runtime)).collect::<Vec<RuntimeExpression>>();
+    interpret_with_io(ast, external_functions, Arc::new(StdIo))
+}
+
+pub fn interpret_with_io(mut ast: AST, external_functions: Vec<ExternalRuntimeFunction>, io: Arc<dyn Io>) {
+    let loose_expressions = take(&mut ast.loose_expressions); // the runtime takes over the definitions, the expressions are only borrowed
+    let locations = take(&mut ast.locations);
+    let mut runtime = RuntimeAST::create_with_io(ast, external_functions, io);
+
+    run_at(&mut runtime, &loose_expressions, &locations);
+}
+
+// returns the value of the last expression
+pub fn run(runtime: &mut RuntimeAST, loose_expressions: &[Expression]) -> Option<Value> {
+    run_at(runtime, loose_expressions, &[])
+}
+
+// the locations are only used for JSON records, expressions without one keep the previous location
+pub fn run_at(runtime: &mut RuntimeAST, loose_expressions: &[Expression], locations: &[Location]) -> Option<Value> {
+    let mut last = None;
+
+    runtime.fold_constants();
+
+    for (i, expr) in loose_expressions.iter().enumerate() {
+        let expr = runtime.resolve(expr, &[]);
+
+        if let Some(location) = locations.get(i) {
+            runtime.location = location.clone();
+        }
+
+        runtime.written = false;
+        runtime.before_eval(&Eval::Expression(&expr));
 
-    for expr in exprs {
-        expr.execute(&mut runtime);
+        let value = if runtime.explain {
+            steps::explain(&expr, runtime)
+        } else {
+            RuntimeExpression::execute_expr(&expr, runtime)
+        };
+
+        runtime.after_eval(&Eval::Expression(&expr), &value);
+
+        // assignments and expressions that printed something themselves (like println(x)) aren't echoed
+        if runtime.echo && !runtime.written && !matches!(expr, Expression::VariableAssignment { .. }) {
+            if runtime.json {
+                runtime.record("value", &value);
+            } else {
+                runtime.write(&format!("{}\n", value));
+            }
+        }
+
+        last = Some(value);
     }
+
+    last
 }
 
 impl RuntimeAST {
     pub fn create(ast: AST, external_functions: Vec<ExternalRuntimeFunction>) -> Self {
-        RuntimeAST {
-            variables: ast.variables.into_iter().map(|v| RuntimeVariable::from_raw(v)).collect::<Vec<RuntimeVariable>>(),
-            functions: ast.functions.into_iter().filter(|f| Expression::External != f.definition).map(|f| RuntimeFunction::from(f)).collect::<Vec<RuntimeFunction>>(),
-            external_functions
+        let mut runtime = RuntimeAST::create_with_io(ast, external_functions, Arc::new(StdIo));
+
+        runtime.styled = style::enabled();
+
+        runtime
+    }
+
+    pub fn create_with_io(ast: AST, external_functions: Vec<ExternalRuntimeFunction>, io: Arc<dyn Io>) -> Self {
+        let functions = ast.functions.into_iter().filter(|f| Expression::External != f.definition).map(|f| Arc::new(RuntimeFunction::from(f))).collect::<Vec<Arc<RuntimeFunction>>>();
+        let mut runtime = RuntimeAST {
+            variables: ast.variables.into_iter().map(RuntimeVariable::from_raw).collect::<Vec<RuntimeVariable>>(),
+            function_names: index_names(functions.iter().map(|f| &f.name)),
+            external_names: index_names(external_functions.iter().map(|f| &f.name)),
+            functions,
+            external_functions,
+            frame: 0,
+            io,
+            fs: Arc::new(RealFs),
+            profiler: None,
+            memo: HashMap::new(),
+            dependents: HashMap::new(),
+            limits: Limits::default(),
+            depth: 0,
+            echo: false,
+            written: false,
+            json: false,
+            styled: false,
+            deterministic: false,
+            explain: false,
+            progress: None,
+            location: Location::default(),
+            numeric: Arc::new(Integer),
+            division: Division::Truncate,
+            hooks: Hooks::default()
+        };
+
+        runtime.resolve_definitions();
+
+        runtime
+    }
+
+    // in JSON mode text is wrapped in an output record
+    pub fn write(&mut self, s: &str) {
+        if self.json {
+            self.record("output", &Value::String(s.to_owned()))
+        } else {
+            self.emit(s)
         }
     }
 
-    pub fn function_ast(mut orig: RuntimeAST, func: RuntimeFunction, args: Vec<RuntimeExpression>) -> RuntimeAST {
-        let mut vars = orig.variables.clone().into_iter().filter(|v| !v.function_argument).collect::<Vec<RuntimeVariable>>().clone();
-        let mut ptr = 0;
+    // what print and println write, in JSON mode the value keeps its structure
+    pub fn print(&mut self, value: &Value, end: &str) {
+        if self.json {
+            self.record("print", value)
+        } else {
+            self.emit(&format!("{}{}", value, end))
+        }
+    }
 
-        for param in func.parameters {
-            let arg = args.get(ptr).unwrap().clone();
+    // {"kind": "print", "value": [1, 2], "file": "main.math", "line": 3, "timestamp": 1700000000000}, the timestamp is in milliseconds since the epoch
+    pub fn record(&mut self, kind: &str, value: &Value) {
+        let timestamp = if self.deterministic {
+            0
+        } else {
+            SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_millis()
+        };
 
-            vars.push(RuntimeVariable {
-                name: param,
-                definition: RuntimeExpression {
-                    orig: if !arg.is_pointer {
-                        Expression::NumberValue {
-                            value: arg.execute(&mut orig)
-                        }
-                    } else {
-                        Expression::None
-                    },
-                    is_pointer: arg.is_pointer,
-                    pointer_to: arg.pointer_to
-                },
-                function_argument: true
-            });
+        self.emit(&format!("{{\"kind\": \"{}\", \"value\": {}, \"file\": {}, \"line\": {}, \"timestamp\": {}}}\n", kind, value.json(), Value::String(self.location.file.clone()).json(), self.location.line + 1, timestamp));
+    }
 
-            ptr += 1;
-        }
+    fn emit(&mut self, s: &str) {
+        self.written = true;
+
+        self.io.write(s);
+        self.io.flush(); // flush so it gets printed
+    }
+
+    pub fn function_ast(mut orig: RuntimeAST, func: RuntimeFunction, args: Vec<RuntimeExpression>) -> RuntimeAST {
+        let mut vars = orig.variables.clone().into_iter().filter(|v| !v.function_argument).collect::<Vec<RuntimeVariable>>();
+        let frame = vars.len();
+
+        vars.extend(func.bind_arguments(args, &mut orig));
 
         RuntimeAST {
             variables: vars,
             functions: orig.functions.clone(),
-            external_functions: orig.external_functions
+            external_functions: orig.external_functions,
+            frame,
+            io: orig.io,
+            fs: orig.fs,
+            profiler: orig.profiler,
+            memo: orig.memo,
+            dependents: orig.dependents,
+            function_names: orig.function_names,
+            external_names: orig.external_names,
+            limits: orig.limits,
+            depth: orig.depth,
+            echo: orig.echo,
+            written: orig.written,
+            json: orig.json,
+            styled: orig.styled,
+            deterministic: orig.deterministic,
+            explain: orig.explain,
+            progress: orig.progress,
+            location: orig.location,
+            numeric: orig.numeric,
+            division: orig.division,
+            hooks: orig.hooks
         }
     }
 
-    pub fn get_functions(&self) -> &Vec<RuntimeFunction> {
+    pub fn get_functions(&self) -> &Vec<Arc<RuntimeFunction>> {
         &self.functions
     }
 
@@ -64,96 +195,747 @@ impl RuntimeAST {
         &self.variables
     }
 
+    pub fn add_function(&mut self, function: RuntimeFunction) {
+        self.function_names.entry(function.name.clone()).or_default().push(self.functions.len());
+        self.functions.push(Arc::new(function));
+    }
+
+    pub fn add_external_function(&mut self, function: ExternalRuntimeFunction) {
+        self.external_names.entry(function.name.clone()).or_default().push(self.external_functions.len());
+        self.external_functions.push(function);
+    }
+
     pub fn delete_function(&mut self, name: &str, params: usize) {
-        self.functions.retain(|f| f.name.ne(name) && f.parameters.len() != params);
+        self.functions.retain(|f| f.name.ne(name) || f.parameters.len() != params);
+        self.function_names = index_names(self.functions.iter().map(|f| &f.name));
+        self.memo.clear(); // function ids shift
     }
 
     pub fn delete_variable(&mut self, name: &str) {
+        self.invalidate(name);
         self.variables.retain(|v| v.name.ne(name));
     }
 
+    // arguments of the current call shadow globals, arguments of outer calls aren't visible
+    fn variable_index(&self, name: &str) -> usize {
+        (self.frame..self.variables.len()).rev().find(|i| self.variables[*i].function_argument && self.variables[*i].name.eq(name))
+            .or_else(|| self.variables.iter().position(|v| !v.function_argument && v.name.eq(name)))
+            .unwrap_or_else(|| raise(ErrorCode::UndefinedVariable, &format!("Variable not found ('{}')", name)))
+    }
+
     pub fn lookup_variable(&self, name: &str) -> RuntimeVariable {
-        self.variables.clone().into_iter().find(|v| v.name.eq(name)).unwrap()
+        self.variables[self.variable_index(name)].clone()
     }
 
-    pub fn lookup_function(&self, name: &str, params: usize) -> RuntimeFunction {
-        self.functions.clone().into_iter().find(|f| f.name.eq(name) && f.parameters.len() == params).unwrap()
+    pub fn lookup_function(&self, name: &str, params: usize) -> Arc<RuntimeFunction> {
+        self.functions[self.function_index(name, params).unwrap()].clone()
+    }
+
+    // only the overloads with the right name are looked at, exact matches win over variadic ones
+    pub fn function_index(&self, name: &str, params: usize) -> Option<FnId> {
+        let overloads = self.function_names.get(name)?;
+
+        overloads.iter().find(|i| !self.functions[**i].variadic && self.functions[**i].parameters.len() == params)
+            .or_else(|| overloads.iter().find(|i| self.functions[**i].accepts(params)))
+            .copied()
+    }
+
+    pub fn external_function_index(&self, name: &str, params: usize) -> Option<usize> {
+        let overloads = self.external_names.get(name)?;
+
+        overloads.iter().find(|i| !self.external_functions[**i].variadic && self.external_functions[**i].parameters == params)
+            .or_else(|| overloads.iter().find(|i| self.external_functions[**i].accepts(params)))
+            .copied()
     }
 
     pub fn lookup_external_function(&self, name: &str, params: usize) -> ExternalRuntimeFunction {
-        self.external_functions.clone().into_iter().find(|f| f.name.eq(name) && f.parameters == params).unwrap()
+        self.external_functions[self.external_function_index(name, params).unwrap()].clone()
     }
 
     pub fn function_exists(&self, name: &str, params: usize) -> bool {
-        self.functions.clone().into_iter().find(|f| f.name.eq(name) && f.parameters.len() == params).is_some()
+        self.function_index(name, params).is_some()
     }
 
     pub fn external_function_exists(&self, name: &str, params: usize) -> bool {
-        self.external_functions.clone().into_iter().find(|f| f.name.eq(name) && f.parameters == params).is_some()
+        self.external_function_index(name, params).is_some()
     }
 
-    pub fn invoke_function(&mut self, name: &str, args: Vec<RuntimeExpression>) -> BigInt {
-        return if self.function_exists(name, args.len()) {
-            let mut fun = self.lookup_function(name, args.len());
-            let result = fun.invoke(args.clone(), self);
-            let cache = fun.cache;
+    pub fn call_target(&self, name: &str, params: usize) -> Option<CallTarget> {
+        self.function_index(name, params).map(CallTarget::Function).or_else(|| self.external_function_index(name, params).map(CallTarget::External))
+    }
 
-            self.functions = self.functions.clone().into_iter().map(|mut f| if f.name.eq(name) && f.parameters.len() == args.len() {
-                f.cache = cache.clone();
+    pub fn invoke_function(&mut self, name: &str, args: Vec<RuntimeExpression>) -> Value {
+        let target = self.target(name, args.len());
 
-                f
-            } else {
-                f
-            }).collect::<Vec<RuntimeFunction>>();
+        self.invoke_target(target, args)
+    }
 
-            result
-        } else if self.external_function_exists(name, args.len()) {
-            (self.lookup_external_function(name, args.len()).invoke)(args, self)
-        } else {
-            panic!("Something went wrong (FUNCTION NOT FOUND)")
+    // like call_target, but a function that doesn't exist is an error
+    pub fn target(&self, name: &str, arguments: usize) -> CallTarget {
+        self.call_target(name, arguments).unwrap_or_else(|| {
+            let functions = self.function_names.get(name).into_iter().flatten().map(|id| (self.functions[*id].parameters.len(), self.functions[*id].variadic));
+            let externals = self.external_names.get(name).into_iter().flatten().map(|index| (self.external_functions[*index].parameters, self.external_functions[*index].variadic));
+            let definitions = functions.chain(externals).collect::<Vec<(usize, bool)>>();
+
+            if !definitions.is_empty() {
+                raise(ErrorCode::ArityMismatch, &arity_mismatch(name, &definitions, arguments));
+            }
+
+            raise(ErrorCode::UndefinedFunction, &format!("Function not found ('{}' with {} argument(s))", name, arguments))
+        })
+    }
+
+    pub fn invoke_target(&mut self, target: CallTarget, args: Vec<RuntimeExpression>) -> Value {
+        if self.hooks.before_eval.is_none() && self.hooks.after_eval.is_none() {
+            return self.invoke_unhooked(target, args);
         }
+
+        let function = match target {
+            CallTarget::Function(id) => self.functions[id].name.clone(),
+            CallTarget::External(index) => self.external_functions[index].name.clone()
+        };
+        let arguments = args.clone();
+        let eval = Eval::Call {
+            function: &function,
+            arguments: &arguments
+        };
+
+        self.before_eval(&eval);
+
+        let value = self.invoke_unhooked(target, args);
+
+        self.after_eval(&eval, &value);
+
+        value
     }
 
-    pub fn reassign_variable(&mut self, var: RuntimeVariable, val: BigInt) -> BigInt {
-        let name = var.name;
-        let found = self.variables.clone().into_iter().find(|v| v.name.eq(&name)).unwrap();
-        let definition = found.definition.clone();
+    fn before_eval(&self, eval: &Eval) {
+        if let Some(hook) = &self.hooks.before_eval {
+            hook(eval).unwrap_or_else(|e| rethrow(e));
+        }
+    }
 
-        if definition.is_pointer {
-            self.reassign_variable(definition.pointer_to.clone().unwrap(), val.clone());
-        } else {
-            self.variables = self.variables.clone().into_iter().map(|v| {
-                if v.name.eq(&found.name) {
-                    RuntimeVariable {
-                        name: v.name,
-                        definition: RuntimeExpression {
-                            orig: Expression::NumberValue {
-                                value: val.clone()
-                            },
-                            is_pointer: false,
-                            pointer_to: Box::new(None)
-                        },
-                        function_argument: v.function_argument
+    fn after_eval(&self, eval: &Eval, value: &Value) {
+        if let Some(hook) = &self.hooks.after_eval {
+            hook(eval, value);
+        }
+    }
+
+    fn invoke_unhooked(&mut self, target: CallTarget, args: Vec<RuntimeExpression>) -> Value {
+        match target {
+            CallTarget::Function(id) => {
+                let fun = self.functions[id].clone();
+
+                if let Some(profiler) = &self.profiler {
+                    profiler.lock().unwrap().enter(&fun.name);
+                }
+
+                if log_enabled!(Level::Trace) {
+                    trace!("{:indent$}{}({})", "", fun.name, args.iter().map(|arg| RuntimeExpression::expr_to_string(arg.orig())).collect::<Vec<String>>().join(", "), indent = self.depth * 2);
+                }
+
+                let result = fun.invoke(id, args, self);
+
+                trace!("{:indent$}{} = {}", "", fun.name, result, indent = self.depth * 2);
+
+                if let Some(profiler) = &self.profiler {
+                    profiler.lock().unwrap().exit();
+                }
+
+                result
+            },
+            CallTarget::External(index) => {
+                let invoke = self.external_functions[index].invoke;
+
+                invoke(args, self)
+            }
+        }
+    }
+
+    // rewrites variable accesses and invocations into slots and function ids so that evaluating them doesn't look up names,
+    // names that can't be resolved (yet) are left as they are and looked up when they're evaluated
+    pub fn resolve(&self, expr: &Expression, parameters: &[String]) -> Expression {
+        match expr {
+            Expression::VariableAccess { variable } => {
+                let slot = parameters.iter().rposition(|p| p.eq(variable)).map(Slot::Argument)
+                    .or_else(|| self.variables.iter().position(|v| !v.function_argument && v.name.eq(variable)).map(Slot::Global));
+
+                match slot {
+                    Some(slot) => Expression::ResolvedVariable {
+                        variable: variable.to_owned(),
+                        slot
+                    },
+                    None => expr.clone()
+                }
+            },
+            Expression::Negate { value, span } => Expression::Negate {
+                value: Arc::new(self.resolve(value, parameters)),
+                span: span.clone()
+            },
+            Expression::Conditional { condition, then, otherwise } => Expression::Conditional {
+                condition: Arc::new(self.resolve(condition, parameters)),
+                then: Arc::new(self.resolve(then, parameters)),
+                otherwise: Arc::new(self.resolve(otherwise, parameters))
+            },
+            Expression::Math { var1, var2, math, span } => Expression::Math {
+                var1: Arc::new(self.resolve(var1, parameters)),
+                var2: Arc::new(self.resolve(var2, parameters)),
+                math: math.clone(),
+                span: span.clone()
+            },
+            Expression::FunctionInvocation { function, arguments, span } => {
+                let arguments = arguments.iter().map(|arg| self.resolve(arg, parameters)).collect::<Vec<Expression>>();
+
+                match self.call_target(function, arguments.len()) {
+                    Some(target) => Expression::ResolvedInvocation {
+                        function: function.to_owned(),
+                        target,
+                        arguments,
+                        span: span.clone()
+                    },
+                    None => Expression::FunctionInvocation {
+                        function: function.to_owned(),
+                        arguments,
+                        span: span.clone()
                     }
-                } else {
-                    v
                 }
-            }).collect::<Vec<RuntimeVariable>>();
+            },
+            Expression::VariableAssignment { variable, value } => Expression::VariableAssignment {
+                variable: variable.to_owned(),
+                value: Arc::new(self.resolve(value, parameters))
+            },
+            Expression::Match { value, arms } => Expression::Match {
+                value: Arc::new(self.resolve(value, parameters)),
+                arms: arms.iter().map(|arm| MatchArm {
+                    pattern: arm.pattern.as_ref().map(|pattern| self.resolve(pattern, parameters)),
+                    value: self.resolve(&arm.value, parameters)
+                }).collect()
+            },
+            Expression::Comprehension { value, variable, source, condition } => {
+                let scope = parameters.iter().cloned().chain(Some(variable.clone())).collect::<Vec<String>>(); // see with_argument
+
+                Expression::Comprehension {
+                    value: Arc::new(self.resolve(value, &scope)),
+                    variable: variable.to_owned(),
+                    source: Arc::new(self.resolve(source, parameters)),
+                    condition: condition.as_ref().map(|c| Arc::new(self.resolve(c, &scope)))
+                }
+            },
+            Expression::Where { variable, value, body } => {
+                let scope = parameters.iter().cloned().chain(Some(variable.clone())).collect::<Vec<String>>(); // see with_argument
+
+                Expression::Where {
+                    variable: variable.to_owned(),
+                    value: Arc::new(self.resolve(value, parameters)),
+                    body: Arc::new(self.resolve(body, &scope))
+                }
+            },
+            Expression::Block { expressions } => Expression::Block {
+                expressions: expressions.iter().map(|expr| self.resolve(expr, parameters)).collect()
+            },
+            Expression::Index { value, index, span } => Expression::Index {
+                value: Arc::new(self.resolve(value, parameters)),
+                index: Arc::new(self.resolve(index, parameters)),
+                span: span.clone()
+            },
+            Expression::Slice { value, from, to, span } => Expression::Slice {
+                value: Arc::new(self.resolve(value, parameters)),
+                from: from.as_ref().map(|from| Arc::new(self.resolve(from, parameters))),
+                to: to.as_ref().map(|to| Arc::new(self.resolve(to, parameters))),
+                span: span.clone()
+            },
+            _ => expr.clone()
+        }
+    }
+
+    // resolves the definitions of all functions and global variables, has to run again when definitions are added
+    pub fn resolve_definitions(&mut self) {
+        let functions = self.functions.iter().map(|f| Arc::new(RuntimeFunction {
+            definition: Arc::new(RuntimeExpression {
+                orig: self.resolve(&f.definition.orig, &f.parameters),
+                is_pointer: f.definition.is_pointer,
+                pointer_to: f.definition.pointer_to.clone()
+            }),
+            ..f.as_ref().clone()
+        })).collect::<Vec<Arc<RuntimeFunction>>>();
+        let variables = self.variables.iter().map(|v| RuntimeVariable {
+            definition: Arc::new(RuntimeExpression {
+                orig: self.resolve(&v.definition.orig, &[]),
+                is_pointer: v.definition.is_pointer,
+                pointer_to: v.definition.pointer_to.clone()
+            }),
+            ..v.clone()
+        }).collect::<Vec<RuntimeVariable>>();
+
+        self.functions = functions;
+        self.variables = variables;
+        self.dependents = self.cache_dependents();
+    }
+
+    // the globals each cached function reads, also through the functions it calls and the definitions of the globals it reads,
+    // functions that are only called by name through externals (like plot("f", ...)) aren't seen
+    fn cache_dependents(&self) -> HashMap<String, Vec<FnId>> {
+        let mut dependents = HashMap::<String, Vec<FnId>>::new();
+
+        for (id, f) in self.functions.iter().enumerate().filter(|(_, f)| f.cached) {
+            let mut globals = Vec::<String>::new();
+            let mut functions = vec![id];
+
+            self.reads(&f.definition.orig, &f.parameters, &mut globals, &mut functions);
+
+            for global in globals {
+                dependents.entry(global).or_default().push(id);
+            }
+        }
+
+        dependents
+    }
+
+    fn reads(&self, expr: &Expression, parameters: &[String], globals: &mut Vec<String>, functions: &mut Vec<FnId>) {
+        match expr {
+            Expression::VariableAccess { variable } | Expression::Pointer { to: variable } if !parameters.contains(variable) => self.reads_global(variable, globals, functions),
+            Expression::ResolvedVariable { variable, slot: Slot::Global(_) } => self.reads_global(variable, globals, functions),
+            Expression::Negate { value, .. } => self.reads(value, parameters, globals, functions),
+            Expression::Conditional { condition, then, otherwise } => {
+                self.reads(condition, parameters, globals, functions);
+                self.reads(then, parameters, globals, functions);
+                self.reads(otherwise, parameters, globals, functions);
+            },
+            Expression::Math { var1, var2, .. } => {
+                self.reads(var1, parameters, globals, functions);
+                self.reads(var2, parameters, globals, functions);
+            },
+            Expression::FunctionInvocation { function, arguments, .. } | Expression::ResolvedInvocation { function, arguments, .. } => {
+                arguments.iter().for_each(|arg| self.reads(arg, parameters, globals, functions));
+
+                // every overload, the one that's called can depend on the arguments
+                for id in self.function_names.get(function).cloned().unwrap_or_default() {
+                    if !functions.contains(&id) {
+                        let f = &self.functions[id];
+
+                        functions.push(id);
+                        self.reads(&f.definition.orig, &f.parameters, globals, functions);
+                    }
+                }
+            },
+            Expression::VariableAssignment { value, .. } => self.reads(value, parameters, globals, functions),
+            Expression::Match { value, arms } => {
+                self.reads(value, parameters, globals, functions);
+
+                for arm in arms {
+                    arm.pattern.iter().for_each(|pattern| self.reads(pattern, parameters, globals, functions));
+                    self.reads(&arm.value, parameters, globals, functions);
+                }
+            },
+            Expression::Comprehension { value, variable, source, condition } => {
+                let scope = parameters.iter().cloned().chain(Some(variable.clone())).collect::<Vec<String>>();
+
+                self.reads(source, parameters, globals, functions);
+                self.reads(value, &scope, globals, functions);
+                condition.iter().for_each(|c| self.reads(c, &scope, globals, functions));
+            },
+            Expression::Where { variable, value, body } => {
+                let scope = parameters.iter().cloned().chain(Some(variable.clone())).collect::<Vec<String>>();
+
+                self.reads(value, parameters, globals, functions);
+                self.reads(body, &scope, globals, functions);
+            },
+            Expression::Block { expressions } => expressions.iter().for_each(|expr| self.reads(expr, parameters, globals, functions)),
+            Expression::Index { value, index, .. } => {
+                self.reads(value, parameters, globals, functions);
+                self.reads(index, parameters, globals, functions);
+            },
+            Expression::Slice { value, from, to, .. } => {
+                self.reads(value, parameters, globals, functions);
+                from.iter().chain(to).for_each(|bound| self.reads(bound, parameters, globals, functions));
+            },
+            _ => {}
+        }
+    }
+
+    // global definitions are evaluated whenever they're read, so whatever they read is read as well
+    fn reads_global(&self, name: &str, globals: &mut Vec<String>, functions: &mut Vec<FnId>) {
+        if globals.iter().any(|g| g.eq(name)) {
+            return;
+        }
+
+        globals.push(name.to_owned());
+
+        if let Some(v) = self.variables.iter().find(|v| !v.function_argument && v.name.eq(name)) {
+            if let Some(to) = v.definition.pointer_to.as_ref() {
+                self.reads_global(&to.name, globals, functions);
+            }
+
+            self.reads(&v.definition.orig, &[], globals, functions);
+        }
+    }
+
+    // evaluates the globals whose value can't change once (numbers and constants computed from them) so that reading them doesn't walk
+    // their definition again, it runs before evaluating anything because the result depends on the numeric backend,
+    // definitions that fail to evaluate are left to report the error where they're read
+    pub fn fold_constants(&mut self) -> usize {
+        let mut folded = 0;
+
+        loop {
+            let foldable = (0..self.variables.len()).filter(|i| {
+                let v = &self.variables[*i];
+
+                !v.function_argument && !v.definition.is_pointer && !matches!(v.definition.orig, Expression::NumberValue { .. }) && self.constant(&v.definition.orig)
+            }).collect::<Vec<usize>>();
+            let before = folded;
+
+            for i in foldable {
+                let definition = self.variables[i].definition.clone();
+
+                if let Ok(value @ (Value::Small(_) | Value::Number(_))) = self.guarded(|ast| definition.execute(ast)) {
+                    self.variables[i].definition = Arc::new(RuntimeExpression {
+                        orig: Expression::NumberValue {
+                            value: value.number()
+                        },
+                        is_pointer: false,
+                        pointer_to: Box::new(None)
+                    });
+                    folded += 1;
+                }
+            }
+
+            // constants computed from the ones that were just folded are foldable now
+            if folded == before {
+                break;
+            }
+        }
+
+        if folded > 0 {
+            debug!("Folded {} constant(s)", folded);
+        }
+
+        folded
+    }
+
+    // only reads constants that are already folded, variables defined with let can be reassigned
+    fn constant(&self, expr: &Expression) -> bool {
+        match expr {
+            Expression::NumberValue { .. } => true,
+            Expression::Negate { value, .. } => self.constant(value),
+            Expression::Math { var1, var2, .. } => self.constant(var1) && self.constant(var2),
+            Expression::ResolvedVariable { slot: Slot::Global(i), .. } => self.variables[*i].constant && matches!(self.variables[*i].definition.orig, Expression::NumberValue { .. }),
+            _ => false
+        }
+    }
+
+    // pure functions don't assign variables and only call pure functions and externals, by function id,
+    // calling one again with the same arguments gives the same result as long as the globals it reads don't change
+    pub fn pure_functions(&self) -> Vec<bool> {
+        let calls = self.functions.iter().map(|f| {
+            let mut calls = Vec::<CallTarget>::new();
+
+            self.calls(&f.definition.orig, &mut calls).then_some(calls)
+        }).collect::<Vec<Option<Vec<CallTarget>>>>();
+        let mut pure = calls.iter().map(|c| c.as_ref().is_some_and(|c| c.iter().all(|t| match t {
+            CallTarget::External(index) => self.external_functions[*index].pure,
+            CallTarget::Function(_) => true
+        }))).collect::<Vec<bool>>();
+
+        // functions calling impure ones are impure as well, until nothing changes (recursion keeps a function pure)
+        loop {
+            let next = (0..pure.len()).map(|id| pure[id] && calls[id].iter().flatten().all(|t| match t {
+                CallTarget::Function(callee) => pure[*callee],
+                CallTarget::External(_) => true
+            })).collect::<Vec<bool>>();
+
+            if next == pure {
+                return pure;
+            }
+
+            pure = next;
+        }
+    }
+
+    // collects what the expression calls, false if it assigns a variable or calls something that doesn't exist
+    fn calls(&self, expr: &Expression, out: &mut Vec<CallTarget>) -> bool {
+        match expr {
+            Expression::Negate { value, .. } => self.calls(value, out),
+            Expression::Conditional { condition, then, otherwise } => self.calls(condition, out) && self.calls(then, out) && self.calls(otherwise, out),
+            Expression::Math { var1, var2, .. } => self.calls(var1, out) && self.calls(var2, out),
+            Expression::FunctionInvocation { function, arguments, .. } => match self.call_target(function, arguments.len()) {
+                Some(target) => {
+                    out.push(target);
+
+                    arguments.iter().all(|arg| self.calls(arg, out))
+                },
+                None => false
+            },
+            Expression::ResolvedInvocation { target, arguments, .. } => {
+                out.push(*target);
+
+                arguments.iter().all(|arg| self.calls(arg, out))
+            },
+            Expression::VariableAssignment { .. } => false,
+            Expression::Match { value, arms } => self.calls(value, out) && arms.iter().all(|arm| arm.pattern.iter().all(|pattern| self.calls(pattern, out)) && self.calls(&arm.value, out)),
+            Expression::Comprehension { value, source, condition, .. } => self.calls(value, out) && self.calls(source, out) && condition.iter().all(|c| self.calls(c, out)),
+            Expression::Where { value, body, .. } => self.calls(value, out) && self.calls(body, out),
+            Expression::Block { expressions } => expressions.iter().all(|expr| self.calls(expr, out)),
+            Expression::Index { value, index, .. } => self.calls(value, out) && self.calls(index, out),
+            Expression::Slice { value, from, to, .. } => self.calls(value, out) && from.iter().chain(to).all(|bound| self.calls(bound, out)),
+            _ => true
+        }
+    }
+
+    // caches every pure function as if it was defined with cache, returns how many weren't cached already
+    pub fn auto_cache(&mut self) -> usize {
+        let pure = self.pure_functions();
+        let mut cached = 0;
+
+        for (id, f) in self.functions.iter_mut().enumerate() {
+            if pure[id] && !f.cached {
+                *f = Arc::new(RuntimeFunction {
+                    cached: true,
+                    ..f.as_ref().clone()
+                });
+                cached += 1;
+            }
+        }
+
+        self.dependents = self.cache_dependents();
+
+        cached
+    }
+
+    // drops the results of cached functions that were computed with the previous value of the global
+    fn invalidate(&mut self, name: &str) {
+        if self.memo.is_empty() {
+            return;
+        }
+
+        if let Some(ids) = self.dependents.get(name) {
+            self.memo.retain(|(f, _), _| !ids.contains(f));
+        }
+    }
+
+    // adds or replaces a global variable, replacing keeps the slots of resolved expressions valid
+    pub fn define_variable(&mut self, variable: RuntimeVariable) {
+        self.invalidate(&variable.name);
+
+        match self.variables.iter().position(|v| !v.function_argument && v.name.eq(&variable.name)) {
+            Some(index) => self.variables[index] = variable,
+            None => self.variables.push(variable)
+        }
+    }
+
+    // adds or replaces a function with the same name and parameters, replacing keeps resolved function ids valid
+    pub fn define_function(&mut self, function: RuntimeFunction) {
+        match self.functions.iter().position(|f| f.name.eq(&function.name) && f.parameters.len() == function.parameters.len() && f.variadic == function.variadic) {
+            Some(id) => {
+                self.functions[id] = Arc::new(function);
+                self.memo.retain(|(f, _), _| *f != id);
+            },
+            None => self.add_function(function)
+        }
+    }
+
+    // forgets the results of all cached functions, returns how many were dropped
+    pub fn clear_cache(&mut self) -> usize {
+        let cleared = self.memo.len();
+
+        self.memo.clear();
+
+        cleared
+    }
+
+    // only the results of the functions with this name (every overload)
+    pub fn clear_function_cache(&mut self, name: &str) -> usize {
+        let ids = self.function_names.get(name).cloned().unwrap_or_default();
+        let before = self.memo.len();
+
+        self.memo.retain(|(f, _), _| !ids.contains(f));
+
+        before - self.memo.len()
+    }
+
+    pub fn reassign_variable(&mut self, name: &str, val: Value) -> Value {
+        let index = self.variable_index(name);
+        let definition = self.variables[index].definition.clone();
+
+        if definition.is_pointer {
+            self.reassign_variable(&definition.pointer_to.as_ref().as_ref().unwrap().name, val.clone());
+        } else {
+            if !self.variables[index].function_argument {
+                self.invalidate(name);
+            }
+
+            self.variables[index].definition = Arc::new(RuntimeExpression {
+                orig: Expression::Value {
+                    value: val.clone()
+                },
+                is_pointer: false,
+                pointer_to: Box::new(None)
+            });
         }
 
         val
     }
+
+    // evaluates the expression and turns errors into values instead of aborting, the call stack is unwound to where it was
+    pub fn attempt(&mut self, expr: &RuntimeExpression) -> Result<Value, MathError> {
+        self.guarded(|ast| expr.execute(ast))
+    }
+
+    pub fn guarded<T>(&mut self, f: impl FnOnce(&mut RuntimeAST) -> T) -> Result<T, MathError> {
+        let (variables, frame, depth) = (self.variables.len(), self.frame, self.depth);
+
+        catch(|| f(self)).inspect_err(|_| {
+            self.variables.truncate(variables);
+            self.frame = frame;
+            self.depth = depth;
+        })
+    }
+
+    // runs f with the arguments of the current call and one more (the variable of a list comprehension) as a new frame,
+    // so the argument gets the slot after the ones of the call
+    pub fn with_argument<T>(&mut self, argument: RuntimeVariable, f: impl FnOnce(&mut RuntimeAST) -> T) -> T {
+        let frame = self.frame;
+        let length = self.variables.len();
+        let mut arguments = self.variables[frame..].iter().filter(|v| v.function_argument).cloned().collect::<Vec<RuntimeVariable>>();
+
+        arguments.push(argument);
+        self.variables.extend(arguments);
+        self.frame = length;
+
+        let result = f(self);
+
+        self.variables.truncate(length);
+        self.frame = frame;
+
+        result
+    }
+
+    // runs the given function with a fresh frame for its arguments
+    pub fn call(&mut self, vars: Vec<RuntimeVariable>, definition: &RuntimeExpression) -> Value {
+        let frame = self.frame;
+        let length = self.variables.len();
+
+        if self.limits.max_depth.is_some_and(|max| self.depth >= max) {
+            raise(ErrorCode::ResourceLimit, &format!("Maximum call depth exceeded ({})", self.depth));
+        }
+
+        if self.limits.deadline.is_some_and(|deadline| Instant::now() > deadline) {
+            raise(ErrorCode::ResourceLimit, "Time limit exceeded");
+        }
+
+        self.variables.extend(vars);
+        self.frame = length;
+        self.depth += 1;
+
+        let result = definition.execute(self);
+
+        self.variables.truncate(length);
+        self.frame = frame;
+        self.depth -= 1;
+
+        result
+    }
+}
+
+fn index_names<'a>(names: impl Iterator<Item = &'a String>) -> HashMap<String, Vec<usize>> {
+    let mut index = HashMap::<String, Vec<usize>>::new();
+
+    for (i, name) in names.enumerate() {
+        index.entry(name.clone()).or_default().push(i);
+    }
+
+    index
+}
+
+// value[index] of a list, tuple, string (a character) or range (which isn't expanded)
+fn element(value: &Value, index: &Value, span: &Span) -> Value {
+    let length = length(value, span);
+    let i = position(index, span).filter(|i| i < &length).unwrap_or_else(|| span.raise(ErrorCode::IndexOutOfBounds, &format!("Index out of bounds ({} for length {})", index, length)));
+
+    match value {
+        Value::String(s) => Value::String(s.chars().nth(usize::try_from(&i).unwrap()).unwrap().to_string()),
+        Value::Range(from, _, step) => Value::from(from + i * step),
+        Value::Tuple(values) => values[usize::try_from(&i).unwrap()].clone(),
+        _ => value.list()[usize::try_from(&i).unwrap()].clone()
+    }
+}
+
+// value[from:to], a slice of a range is a range again
+fn slice(value: &Value, from: Option<&Value>, to: Option<&Value>, span: &Span) -> Value {
+    let length = length(value, span);
+    let start = from.map_or(Some(BigInt::from(0)), |from| position(from, span));
+    let end = to.map_or(Some(length.clone()), |to| position(to, span));
+    let (start, end) = start.zip(end).filter(|(start, end)| start <= end && end <= &length).unwrap_or_else(|| {
+        let bound = |b: Option<&Value>| b.map(|b| b.to_string()).unwrap_or_default();
+
+        span.raise(ErrorCode::IndexOutOfBounds, &format!("Slice out of bounds ({}:{} for length {})", bound(from), bound(to), length))
+    });
+
+    if let Value::Range(from, _, step) = value {
+        return Value::Range(from + start * step, from + (end - 1) * step, step.clone());
+    }
+
+    let (start, end) = (usize::try_from(&start).unwrap(), usize::try_from(&end).unwrap());
+
+    match value {
+        Value::String(s) => Value::String(s.chars().skip(start).take(end - start).collect()),
+        Value::Tuple(values) => Value::Tuple(values[start..end].to_vec()),
+        _ => Value::List(value.list()[start..end].to_vec())
+    }
+}
+
+// ranges are counted without making their numbers
+fn length(value: &Value, span: &Span) -> BigInt {
+    match value {
+        Value::List(values) | Value::Tuple(values) => BigInt::from(values.len()),
+        Value::String(s) => BigInt::from(s.chars().count()),
+        Value::Range(from, to, step) => {
+            if (step.sign() == Sign::Plus && to < from) || (step.sign() == Sign::Minus && to > from) {
+                BigInt::from(0)
+            } else {
+                (to - from) / step + 1
+            }
+        },
+        _ => span.raise(ErrorCode::TypeMismatch, &format!("Expected a list, tuple, range or string but got {} ('{}')", value.type_name(), value))
+    }
+}
+
+// None for negative indices
+fn position(index: &Value, span: &Span) -> Option<BigInt> {
+    let index = match index {
+        Value::Small(_) | Value::Number(_) => index.number(),
+        _ => span.raise(ErrorCode::TypeMismatch, &format!("Expected a whole number as index but got {} ('{}')", index.type_name(), index))
+    };
+
+    (index.sign() != Sign::Minus).then_some(index)
 }
 
 impl ExternalRuntimeFunction {
-    pub fn create(name: &'static str, parameters: usize, invoke: fn(Vec<RuntimeExpression>, &mut RuntimeAST) -> BigInt) -> ExternalRuntimeFunction {
+    pub fn create(name: &'static str, parameters: usize, invoke: fn(Vec<RuntimeExpression>, &mut RuntimeAST) -> Value) -> ExternalRuntimeFunction {
+        ExternalRuntimeFunction {
+            name: name.to_owned(),
+            parameters,
+            variadic: false,
+            pure: true,
+            invoke
+        }
+    }
+
+    pub fn create_variadic(name: &'static str, parameters: usize, invoke: fn(Vec<RuntimeExpression>, &mut RuntimeAST) -> Value) -> ExternalRuntimeFunction {
         ExternalRuntimeFunction {
             name: name.to_owned(),
             parameters,
+            variadic: true,
+            pure: true,
             invoke
         }
     }
 
+    pub fn impure(mut self) -> ExternalRuntimeFunction {
+        self.pure = false;
+
+        self
+    }
+
     pub fn name(&self) -> &String {
         &self.name
     }
@@ -162,7 +944,19 @@ impl ExternalRuntimeFunction {
         &self.parameters
     }
 
-    pub fn invoke(&self) -> &fn(Vec<RuntimeExpression>, &mut RuntimeAST) -> BigInt {
+    pub fn variadic(&self) -> &bool {
+        &self.variadic
+    }
+
+    pub fn accepts(&self, arguments: usize) -> bool {
+        if self.variadic {
+            arguments + 1 >= self.parameters
+        } else {
+            arguments == self.parameters
+        }
+    }
+
+    pub fn invoke(&self) -> &fn(Vec<RuntimeExpression>, &mut RuntimeAST) -> Value {
         &self.invoke
     }
 }
@@ -171,171 +965,144 @@ impl RuntimeVariable {
     pub fn from(orig: Variable, ast: &RuntimeAST) -> Self {
         Self {
             name: orig.name,
-            definition: RuntimeExpression::from(orig.definition, ast),
-            function_argument: false
+            definition: Arc::new(RuntimeExpression::from(orig.definition, ast)),
+            function_argument: false,
+            constant: orig.constant
         }
     }
 
     pub fn from_raw(orig: Variable) -> Self {
         Self {
             name: orig.name,
-            definition: RuntimeExpression {
+            definition: Arc::new(RuntimeExpression {
                 orig: orig.definition,
                 is_pointer: false,
                 pointer_to: Box::new(None)
-            },
-            function_argument: false
+            }),
+            function_argument: false,
+            constant: orig.constant
         }
     }
 
-    pub fn get_value(&self, ast: &mut RuntimeAST) -> BigInt {
+    pub fn get_value(&self, ast: &mut RuntimeAST) -> Value {
         self.definition.execute(ast)
     }
 }
 
-impl<A: PartialEq, B: PartialEq> PartialEq for Tuple<A, B> {
-    fn eq(&self, other: &Self) -> bool {
-        self.a == other.a && self.b == other.b
-    }
-
-    fn ne(&self, other: &Self) -> bool {
-        !self.eq(other)
-    }
-}
-
 impl RuntimeFunction {
     pub fn from(orig: Function) -> Self {
         Self {
             name: orig.name,
-            definition: RuntimeExpression {
+            definition: Arc::new(RuntimeExpression {
                 orig: orig.definition,
                 is_pointer: false,
                 pointer_to: Box::new(None)
-            },
+            }),
             parameters: orig.parameters,
-            cached: orig.cached,
-            cache: vec![]
+            variadic: orig.variadic,
+            cached: orig.cached
         }
     }
 
-    pub fn invoke(&mut self, args: Vec<RuntimeExpression>, ast: &mut RuntimeAST) -> BigInt {
-        return if self.cached {
-            let ptr = args.clone().into_iter().find(|expr| expr.is_pointer);
-
-            if ptr.is_some() {
-                panic!("Cannot invoke cached function with pointer (TODO make this error better)");
-            }
-
-            let mut found = None;
-
-            for t in self.cache.clone() {
-                let a = t.a;
-                let r = t.b;
+    pub fn accepts(&self, arguments: usize) -> bool {
+        if self.variadic {
+            arguments + 1 >= self.parameters.len()
+        } else {
+            arguments == self.parameters.len()
+        }
+    }
 
-                if a == args {
-                    found = Some(r);
+    // evaluates the arguments in the caller's scope, a rest parameter receives the remaining ones as a list
+    pub fn bind_arguments(&self, args: Vec<RuntimeExpression>, ast: &mut RuntimeAST) -> Vec<RuntimeVariable> {
+        let mut vars = Vec::<RuntimeVariable>::new();
+        let mut args = args.into_iter();
 
-                    break;
-                }
-            }
+        for (i, param) in self.parameters.iter().enumerate() {
+            if self.variadic && i == self.parameters.len() - 1 {
+                let rest = args.by_ref().map(|arg| arg.execute(ast)).collect::<Vec<Value>>();
 
-            if None != found {
-                found.unwrap()
-            } else {
-                let mut ptr = 0;
-                let mut vars = Vec::<RuntimeVariable>::new();
-
-                for param in &self.parameters {
-                    let arg = args.get(ptr).unwrap().clone();
-
-                    vars.push(RuntimeVariable {
-                        name: param.clone(),
-                        definition: RuntimeExpression {
-                            orig: if !arg.is_pointer {
-                                Expression::NumberValue {
-                                    value: arg.execute(ast)
-                                }
-                            } else {
-                                Expression::None
-                            },
-                            is_pointer: arg.is_pointer,
-                            pointer_to: arg.pointer_to
+                vars.push(RuntimeVariable {
+                    name: param.clone(),
+                    definition: Arc::new(RuntimeExpression {
+                        orig: Expression::Value {
+                            value: Value::List(rest)
                         },
-                        function_argument: true
-                    });
-
-                    ptr += 1;
-                }
+                        is_pointer: false,
+                        pointer_to: Box::new(None)
+                    }),
+                    function_argument: true,
+                    constant: false
+                });
 
-                ast.variables.extend(vars);
+                break;
+            }
 
-                let result = self.definition.execute(ast);
+            let arg = args.next().unwrap();
 
-                ast.variables = ast.variables.clone().into_iter().filter(|v| !v.function_argument).collect::<Vec<RuntimeVariable>>();
+            vars.push(RuntimeVariable {
+                name: param.clone(),
+                definition: Arc::new(RuntimeExpression {
+                    orig: Expression::Value {
+                        value: arg.execute(ast)
+                    },
+                    is_pointer: arg.is_pointer,
+                    pointer_to: arg.pointer_to
+                }),
+                function_argument: true,
+                constant: false
+            });
+        }
 
-                let tuple = Tuple {
-                    a: args.clone(),
-                    b: result.clone()
-                };
+        vars
+    }
 
-                for fun in &ast.functions {
-                    for t in &fun.cache {
-                        if !self.cache.contains(t) {
-                            self.cache.push(t.clone());
-                        }
-                    }
-                }
+    pub fn invoke(&self, id: FnId, args: Vec<RuntimeExpression>, ast: &mut RuntimeAST) -> Value {
+        if self.cached && args.iter().any(|arg| arg.is_pointer) {
+            raise(ErrorCode::CachedPointer, &format!("Cannot pass a pointer to cached function '{}'", self.name));
+        }
 
-                self.cache.push(tuple);
+        let vars = self.bind_arguments(args, ast);
+        let arguments = ast.hooks.invoke.is_some().then(|| vars.iter().map(|v| v.definition.orig.value().clone()).collect::<Vec<Value>>());
+        let start = Instant::now();
+        let (result, cached) = self.evaluate(id, vars, ast);
+
+        if let (Some(hook), Some(arguments)) = (&ast.hooks.invoke, &arguments) {
+            hook(&Invocation {
+                function: &self.name,
+                arguments,
+                duration: start.elapsed(),
+                cached
+            });
+        }
 
-                result
-            }
-        } else {
-            let mut ptr = 0;
-            let mut vars = Vec::<RuntimeVariable>::new();
+        result
+    }
 
-            for param in &self.parameters {
-                let arg = args.get(ptr).unwrap().clone();
+    // whether the result came from the cache
+    fn evaluate(&self, id: FnId, vars: Vec<RuntimeVariable>, ast: &mut RuntimeAST) -> (Value, bool) {
+        if !self.cached {
+            return (ast.call(vars, &self.definition), false);
+        }
 
-                vars.push(RuntimeVariable {
-                    name: param.clone(),
-                    definition: RuntimeExpression {
-                        orig: if !arg.is_pointer {
-                            Expression::NumberValue {
-                                value: arg.execute(ast)
-                            }
-                        } else {
-                            Expression::NumberValue {
-                                value: arg.pointer_to.clone().unwrap().get_value(ast)
-                            }
-                        },
-                        is_pointer: arg.is_pointer,
-                        pointer_to: arg.pointer_to
-                    },
-                    function_argument: true
-                });
+        let key = (id, vars.iter().map(|v| v.definition.orig.value().clone()).collect::<Vec<Value>>());
 
-                ptr += 1;
-            }
+        if let Some(result) = ast.memo.get(&key) {
+            trace!("{:indent$}(cached)", "", indent = ast.depth * 2);
 
-            ast.variables.extend(vars);
+            return (result.clone(), true);
+        }
 
-            let result = self.definition.execute(ast);
+        let result = ast.call(vars, &self.definition);
 
-            ast.variables = ast.variables.clone().into_iter().filter(|v| !v.function_argument).collect::<Vec<RuntimeVariable>>();
+        ast.memo.insert(key, result.clone());
 
-            result
-        }
+        (result, false)
     }
 }
 
 impl PartialEq<RuntimeExpression> for RuntimeExpression {
     fn eq(&self, other: &RuntimeExpression) -> bool {
-        other.orig.eq(self.orig()) && other.is_pointer.eq(&self.is_pointer) && other.pointer_to.clone().unwrap().eq(&self.pointer_to.clone().unwrap())
-    }
-
-    fn ne(&self, other: &RuntimeExpression) -> bool {
-        !self.eq(other)
+        other.orig.eq(self.orig()) && other.is_pointer.eq(&self.is_pointer) && other.pointer_to.eq(&self.pointer_to)
     }
 }
 
@@ -343,10 +1110,6 @@ impl PartialEq<RuntimeVariable> for RuntimeVariable {
     fn eq(&self, other: &RuntimeVariable) -> bool {
         self.function_argument.eq(&other.function_argument.clone()) && self.definition.eq(&other.definition.clone()) && self.name.eq(&other.name.clone())
     }
-
-    fn ne(&self, other: &RuntimeVariable) -> bool {
-        !self.eq(other)
-    }
 }
 
 impl RuntimeExpression {
@@ -358,17 +1121,26 @@ impl RuntimeExpression {
         }
     }
 
+    pub fn value(value: Value) -> Self {
+        Self {
+            orig: Expression::Value {
+                value
+            },
+            is_pointer: false,
+            pointer_to: Box::new(None)
+        }
+    }
+
     pub fn from(orig: Expression, ast: &RuntimeAST) -> Self {
+        let pointer_to = match &orig {
+            Expression::Pointer { to } => Some(ast.lookup_variable(to)),
+            _ => None
+        };
+
         RuntimeExpression {
-            orig: orig.clone(),
-            is_pointer: match &orig {
-                Expression::Pointer { .. } => true,
-                _ => false
-            },
-            pointer_to: Box::new(match orig.clone() {
-                Expression::Pointer { to } => Some(ast.lookup_variable(&to)),
-                _ => None
-            })
+            orig,
+            is_pointer: pointer_to.is_some(),
+            pointer_to: Box::new(pointer_to)
         }
     }
 
@@ -376,47 +1148,185 @@ impl RuntimeExpression {
         &self.orig
     }
 
-    pub fn execute(&self, ast: &mut RuntimeAST) -> BigInt {
+    pub fn execute(&self, ast: &mut RuntimeAST) -> Value {
         if self.is_pointer {
-            self.pointer_to.clone().unwrap().get_value(ast)
+            self.pointer_to.as_ref().as_ref().unwrap().get_value(ast)
         } else {
             RuntimeExpression::execute_expr(&self.orig, ast)
         }
     }
 
-    pub fn execute_expr(expr: &Expression, ast: &mut RuntimeAST) -> BigInt {
+    pub fn execute_expr(expr: &Expression, ast: &mut RuntimeAST) -> Value {
         match expr {
             Expression::NumberValue { value } =>
+                Value::from(value),
+            Expression::Value { value } =>
                 value.clone(),
-            Expression::VariableAccess { variable } =>
-                ast.lookup_variable(&variable.to_owned()).get_value(ast),
-            Expression::Math { var1, var2, math } =>
-                RuntimeExpression::run_math(math.clone(), RuntimeExpression::from(*var1.clone(), ast), RuntimeExpression::from(*var2.clone(), ast), ast),
-            Expression::FunctionInvocation { function, arguments } =>
-                ast.invoke_function(&function.to_owned(), arguments.into_iter().map(|expr| RuntimeExpression::from(expr.clone(), ast)).collect::<Vec<RuntimeExpression>>()),
+            Expression::VariableAccess { variable } | Expression::Pointer { to: variable } => { // only the definition is shared, the variable itself isn't copied
+                let definition = ast.variables[ast.variable_index(variable)].definition.clone();
+
+                definition.execute(ast)
+            },
+            Expression::Conditional { condition, then, otherwise } => {
+                if RuntimeExpression::execute_expr(condition, ast).is_true() {
+                    RuntimeExpression::execute_expr(then, ast)
+                } else {
+                    RuntimeExpression::execute_expr(otherwise, ast)
+                }
+            },
+            Expression::Negate { value, span: Some(span) } =>
+                located(span, || RuntimeExpression::negate(value, ast)),
+            Expression::Negate { value, span: None } =>
+                RuntimeExpression::negate(value, ast),
+            Expression::Math { var1, var2, math, span: Some(span) } =>
+                located(span, || RuntimeExpression::run_math(math, var1, var2, ast)),
+            Expression::Math { var1, var2, math, span: None } =>
+                RuntimeExpression::run_math(math, var1, var2, ast),
+            Expression::ResolvedVariable { slot, .. } => {
+                let definition = match slot {
+                    Slot::Global(index) => ast.variables[*index].definition.clone(),
+                    Slot::Argument(index) => ast.variables[ast.frame + index].definition.clone()
+                };
+
+                definition.execute(ast)
+            },
+            Expression::FunctionInvocation { function, arguments, span } => {
+                let arguments = arguments.iter().map(|expr| RuntimeExpression::from(expr.clone(), ast)).collect::<Vec<RuntimeExpression>>();
+
+                match span {
+                    Some(span) => located(span, || ast.invoke_function(function, arguments)),
+                    None => ast.invoke_function(function, arguments)
+                }
+            },
+            Expression::ResolvedInvocation { target, arguments, span, .. } => {
+                let arguments = arguments.iter().map(|expr| RuntimeExpression::from(expr.clone(), ast)).collect::<Vec<RuntimeExpression>>();
+
+                match span {
+                    Some(span) => located(span, || ast.invoke_target(*target, arguments)),
+                    None => ast.invoke_target(*target, arguments)
+                }
+            },
             Expression::VariableAssignment { variable, value } => {
-                let val = RuntimeExpression::from(*value.clone(), ast).execute(ast);
+                let val = RuntimeExpression::execute_expr(value, ast);
+
+                ast.reassign_variable(variable, val)
+            },
+            Expression::Match { value, arms } => {
+                let value = RuntimeExpression::execute_expr(value, ast);
+                let arm = arms.iter().find(|arm| match &arm.pattern {
+                    Some(pattern) => {
+                        let pattern = RuntimeExpression::execute_expr(pattern, ast);
+
+                        RuntimeExpression::compute(&MathType::Equals, &value, &pattern, ast).is_true()
+                    },
+                    None => true
+                }).unwrap_or_else(|| raise(ErrorCode::NoMatch, &format!("No arm matches {}", value)));
+
+                RuntimeExpression::execute_expr(&arm.value, ast)
+            },
+            Expression::Comprehension { value, variable, source, condition } => {
+                let mut values = Vec::<Value>::new();
+
+                for element in RuntimeExpression::execute_expr(source, ast).elements() {
+                    let argument = RuntimeVariable {
+                        name: variable.to_owned(),
+                        definition: Arc::new(RuntimeExpression::value(element)),
+                        function_argument: true,
+                        constant: false
+                    };
+
+                    values.extend(ast.with_argument(argument, |ast| {
+                        let keep = condition.as_ref().is_none_or(|c| RuntimeExpression::execute_expr(c, ast).is_true());
+
+                        keep.then(|| RuntimeExpression::execute_expr(value, ast))
+                    }));
+                }
+
+                Value::List(values)
+            },
+            Expression::Where { variable, value, body } => {
+                let argument = RuntimeVariable {
+                    name: variable.to_owned(),
+                    definition: Arc::new(RuntimeExpression::value(RuntimeExpression::execute_expr(value, ast))),
+                    function_argument: true,
+                    constant: false
+                };
+
+                ast.with_argument(argument, |ast| RuntimeExpression::execute_expr(body, ast))
+            },
+            Expression::Block { expressions } => {
+                let mut value = Value::None;
+
+                for expr in expressions {
+                    value = RuntimeExpression::execute_expr(expr, ast);
+                }
+
+                value
+            },
+            Expression::Index { value, index, span } => {
+                let value = RuntimeExpression::execute_expr(value, ast);
+                let index = RuntimeExpression::execute_expr(index, ast);
 
-                ast.reassign_variable(ast.lookup_variable(&variable.to_owned()), val)
+                element(&value, &index, span)
             },
-            Expression::None | Expression::External | Expression::Pointer { .. } =>
-                panic!("Can not execute Expression::None | Expression::External | Expression::Pointer => {}", RuntimeExpression::expr_to_string(expr)),
+            Expression::Slice { value, from, to, span } => {
+                let value = RuntimeExpression::execute_expr(value, ast);
+                let from = from.as_ref().map(|from| RuntimeExpression::execute_expr(from, ast));
+                let to = to.as_ref().map(|to| RuntimeExpression::execute_expr(to, ast));
+
+                slice(&value, from.as_ref(), to.as_ref(), span)
+            },
+            Expression::None | Expression::External =>
+                panic!("Can not execute Expression::None | Expression::External => {}", RuntimeExpression::expr_to_string(expr)),
         }
     }
 
-    pub fn run_math(math: MathType, var1: RuntimeExpression, var2: RuntimeExpression, ast: &mut RuntimeAST) -> BigInt {
+    // 0 - value, so that it works the same way in every numeric backend
+    fn negate(value: &Expression, ast: &mut RuntimeAST) -> Value {
+        let value = RuntimeExpression::execute_expr(value, ast);
+
+        RuntimeExpression::compute(&MathType::Subtract, &Value::from(0), &value, ast)
+    }
+
+    pub fn run_math(math: &MathType, var1: &Expression, var2: &Expression, ast: &mut RuntimeAST) -> Value {
+        let val1 = RuntimeExpression::execute_expr(var1, ast);
+
+        if let Some(value) = RuntimeExpression::short_circuit(math, &val1) {
+            return value;
+        }
+
+        let val2 = RuntimeExpression::execute_expr(var2, ast);
+
+        RuntimeExpression::compute(math, &val1, &val2, ast)
+    }
+
+    // the value of a && b or a || b if a decides it, b isn't evaluated then
+    pub fn short_circuit(math: &MathType, val1: &Value) -> Option<Value> {
+        match math {
+            MathType::And if !val1.is_true() => Some(Value::bool(false)),
+            MathType::Or if val1.is_true() => Some(Value::bool(true)),
+            _ => None
+        }
+    }
+
+    fn compute(math: &MathType, val1: &Value, val2: &Value, ast: &RuntimeAST) -> Value {
         match math {
-            MathType::Add               => var1.execute(ast).add(var2.execute(ast)),
-            MathType::Subtract          => var1.execute(ast).sub(var2.execute(ast)),
-            MathType::Multiply          => var1.execute(ast).mul(var2.execute(ast)),
-            MathType::Divide            => var1.execute(ast).div(var2.execute(ast)),
-            MathType::Equals            => BigInt::from(if var1.execute(ast) == var2.execute(ast) { 1 } else { 0 }),
-            MathType::NotEquals         => BigInt::from(if var1.execute(ast) != var2.execute(ast) { 1 } else { 0 }),
-            MathType::BiggerOrEquals    => BigInt::from(if var1.execute(ast) >= var2.execute(ast) { 1 } else { 0 }),
-            MathType::Bigger            => BigInt::from(if var1.execute(ast) > var2.execute(ast) { 1 } else { 0 }),
-            MathType::SmallerOrEquals   => BigInt::from(if var1.execute(ast) <= var2.execute(ast) { 1 } else { 0 }),
-            MathType::Smaller           => BigInt::from(if var1.execute(ast) < var2.execute(ast) { 1 } else { 0 }),
-            MathType::Pow               => var1.execute(ast).pow(*var2.execute(ast).to_u32_digits().1.last().unwrap())
+            // lists and strings can only be compared with each other
+            MathType::Equals if !val1.is_numeric() || !val2.is_numeric() => Value::bool(val1 == val2),
+            MathType::NotEquals if !val1.is_numeric() || !val2.is_numeric() => Value::bool(val1 != val2),
+            MathType::Divide if ast.division == Division::Floor => ast.numeric.math(&MathType::FloorDivide, val1, val2),
+            MathType::Divide if ast.division == Division::Exact => {
+                let quotient = ast.numeric.math(math, val1, val2);
+
+                if !ast.numeric.math(&MathType::Equals, &ast.numeric.math(&MathType::Multiply, &quotient, val2), val1).is_true() {
+                    raise(ErrorCode::InvalidArgument, &format!("Inexact division ({} / {} has a remainder, // rounds down)", val1, val2));
+                }
+
+                quotient
+            },
+            MathType::And => Value::bool(val1.is_true() && val2.is_true()),
+            MathType::Or => Value::bool(val1.is_true() || val2.is_true()),
+            _ => ast.numeric.math(math, val1, val2)
         }
     }
 
@@ -425,11 +1335,23 @@ impl RuntimeExpression {
             Expression::None => "none".to_owned(),
             Expression::External => "external".to_owned(),
             Expression::NumberValue { value } => value.to_string(),
+            Expression::Value { value: Value::String(value) } => format!("{:?}", value),
+            Expression::Value { value } => value.to_string(),
             Expression::VariableAccess { variable } => variable.to_owned(),
-            Expression::Math { var1, var2, math } => format!("({}) {} ({})", RuntimeExpression::expr_to_string(var1), math.operator(), RuntimeExpression::expr_to_string(var2)),
-            Expression::FunctionInvocation { function, arguments } => format!("{}({})", function, arguments.into_iter().map(|expr| RuntimeExpression::expr_to_string(expr)).collect::<Vec<String>>().join(", ")),
+            Expression::Negate { value, .. } => format!("-({})", RuntimeExpression::expr_to_string(value)),
+            Expression::Conditional { condition, then, otherwise } => format!("({}) ? ({}) : ({})", RuntimeExpression::expr_to_string(condition), RuntimeExpression::expr_to_string(then), RuntimeExpression::expr_to_string(otherwise)),
+            Expression::Math { var1, var2, math, .. } => format!("({}) {} ({})", RuntimeExpression::expr_to_string(var1), math.operator(), RuntimeExpression::expr_to_string(var2)),
+            Expression::FunctionInvocation { function, arguments, .. } => format!("{}({})", function, arguments.iter().map(RuntimeExpression::expr_to_string).collect::<Vec<String>>().join(", ")),
             Expression::VariableAssignment { variable, value } => format!("{} = {}", variable, RuntimeExpression::expr_to_string(value)),
-            Expression::Pointer { to } => format!("*{}", to)
+            Expression::Pointer { to } => format!("*{}", to),
+            Expression::ResolvedVariable { variable, .. } => variable.to_owned(),
+            Expression::ResolvedInvocation { function, arguments, .. } => format!("{}({})", function, arguments.iter().map(RuntimeExpression::expr_to_string).collect::<Vec<String>>().join(", ")),
+            Expression::Comprehension { value, variable, source, condition } => format!("[{} for {} in {}{}]", RuntimeExpression::expr_to_string(value), variable, RuntimeExpression::expr_to_string(source), condition.as_ref().map(|c| format!(" if {}", RuntimeExpression::expr_to_string(c))).unwrap_or_default()),
+            Expression::Where { variable, value, body } => format!("({}) where {} = ({})", RuntimeExpression::expr_to_string(body), variable, RuntimeExpression::expr_to_string(value)),
+            Expression::Block { expressions } => format!("({})", expressions.iter().map(RuntimeExpression::expr_to_string).collect::<Vec<String>>().join("; ")),
+            Expression::Index { value, index, .. } => format!("({})[{}]", RuntimeExpression::expr_to_string(value), RuntimeExpression::expr_to_string(index)),
+            Expression::Slice { value, from, to, .. } => format!("({})[{}:{}]", RuntimeExpression::expr_to_string(value), from.as_ref().map(|from| RuntimeExpression::expr_to_string(from)).unwrap_or_default(), to.as_ref().map(|to| RuntimeExpression::expr_to_string(to)).unwrap_or_default()),
+            Expression::Match { value, arms } => format!("match ({}){}", RuntimeExpression::expr_to_string(value), arms.iter().map(|arm| format!(" | {} -> ({})", arm.pattern.as_ref().map(|p| format!("({})", RuntimeExpression::expr_to_string(p))).unwrap_or_else(|| "_".to_owned()), RuntimeExpression::expr_to_string(&arm.value))).collect::<String>())
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+// measures self time per call chain, the folded output ("main;f;g 123") can be fed into inferno/flamegraph.pl
+pub struct Profiler {
+    stack: Vec<Frame>,
+    folded: HashMap<String, u128>,
+    functions: HashMap<String, (usize, u128)> // calls and self time per function
+}
+
+struct Frame {
+    name: String,
+    start: Instant,
+    children: u128
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler {
+            stack: vec![],
+            folded: HashMap::new(),
+            functions: HashMap::new()
+        }
+    }
+
+    pub fn enter(&mut self, name: &str) {
+        self.stack.push(Frame {
+            name: name.to_owned(),
+            start: Instant::now(),
+            children: 0
+        });
+    }
+
+    pub fn exit(&mut self) {
+        let chain = format!("main;{}", self.stack.iter().map(|f| f.name.as_str()).collect::<Vec<&str>>().join(";"));
+        let frame = self.stack.pop().expect("Profiler frame underflow");
+        let elapsed = frame.start.elapsed().as_micros();
+        let own = elapsed.saturating_sub(frame.children);
+
+        *self.folded.entry(chain).or_insert(0) += own;
+
+        let function = self.functions.entry(frame.name).or_insert((0, 0));
+
+        function.0 += 1;
+        function.1 += own;
+
+        if let Some(parent) = self.stack.last_mut() {
+            parent.children += elapsed;
+        }
+    }
+
+    pub fn folded(&self) -> String {
+        let mut lines = self.folded.iter().filter(|(_, t)| **t > 0).map(|(chain, t)| format!("{} {}\n", chain, t)).collect::<Vec<String>>();
+
+        lines.sort();
+
+        lines.concat()
+    }
+
+    pub fn summary(&self) -> String {
+        let mut functions = self.functions.iter().collect::<Vec<(&String, &(usize, u128))>>();
+        let width = functions.iter().map(|(name, _)| name.len()).max().unwrap_or(0).max("function".len());
+
+        functions.sort_by(|a, b| (b.1).1.cmp(&(a.1).1).then(a.0.cmp(b.0)));
+
+        let mut out = format!("{:<width$}  {:>10}  {:>12}\n", "function", "calls", "self (µs)", width = width);
+
+        for (name, (calls, time)) in functions {
+            out.push_str(&format!("{:<width$}  {:>10}  {:>12}\n", name, calls, time, width = width));
+        }
+
+        out
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Profiler::new()
+    }
+}
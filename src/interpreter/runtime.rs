@@ -1,40 +1,104 @@
-use num_bigint::BigInt;
-use crate::ast::Expression;
+use crate::ast::{Expression, Location};
+use crate::value::Value;
+use crate::error::MathError;
+use crate::numeric::{Division, Numeric};
+use crate::interpreter::profile::Profiler;
+use crate::io::Io;
+use crate::vfs::Vfs;
+use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+pub type FnId = usize; // index into RuntimeAST::functions
 
 #[derive(Clone)]
 pub struct RuntimeAST {
     pub variables: Vec<RuntimeVariable>,
-    pub functions: Vec<RuntimeFunction>,
-    pub external_functions: Vec<ExternalRuntimeFunction>
+    pub functions: Vec<Arc<RuntimeFunction>>,
+    pub external_functions: Vec<ExternalRuntimeFunction>,
+    pub frame: usize, // index of the first argument of the current call
+    pub io: Arc<dyn Io>, // where input() reads from and println() writes to, stdin and stdout unless redirected
+    pub fs: Arc<dyn Vfs>, // where the file builtins read and write
+    pub profiler: Option<Arc<Mutex<Profiler>>>,
+    pub memo: HashMap<(FnId, Vec<Value>), Value>, // results of cached functions by their evaluated arguments
+    pub dependents: HashMap<String, Vec<FnId>>, // cached functions whose results depend on a global, by the global's name
+    pub function_names: HashMap<String, Vec<FnId>>, // overloads by name, kept in sync by add_function/delete_function
+    pub external_names: HashMap<String, Vec<usize>>,
+    pub limits: Limits,
+    pub depth: usize, // number of function calls currently running
+    pub echo: bool, // calculator mode, the values of loose expressions get printed
+    pub written: bool, // whether anything was written since the current loose expression started
+    pub json: bool, // output is written as JSON records (one per line) instead of plain text
+    pub styled: bool, // colors and bold text are only used when writing to a terminal
+    pub deterministic: bool, // the clock stands still (bench measures 0, records have timestamp 0) and sleep returns at once, so runs are reproducible
+    pub explain: bool, // loose expressions are evaluated step by step and every step is written (see steps::explain)
+    pub progress: Option<Instant>, // when the progress bar was last drawn
+    pub location: Location, // of the current loose expression
+    pub numeric: Arc<dyn Numeric>, // what the operators compute with, integer unless the manifest says otherwise
+    pub division: Division, // what / does with a remainder, truncate unless the manifest says otherwise
+    pub hooks: Hooks
+}
+
+pub type BeforeEval = Arc<dyn Fn(&Eval) -> Result<(), MathError> + Send + Sync>;
+pub type AfterEval = Arc<dyn Fn(&Eval, &Value) + Send + Sync>;
+pub type OnInvoke = Arc<dyn Fn(&Invocation) + Send + Sync>;
+
+// set by embedders (see Context::on_before_eval), an error returned before an evaluation aborts it like any other error
+#[derive(Clone, Default)]
+pub struct Hooks {
+    pub before_eval: Option<BeforeEval>,
+    pub after_eval: Option<AfterEval>,
+    pub invoke: Option<OnInvoke>
+}
+
+// a finished call of a defined function (externals aren't reported, their arguments are only evaluated by themselves)
+pub struct Invocation<'a> {
+    pub function: &'a str,
+    pub arguments: &'a [Value],
+    pub duration: Duration, // including the calls it made
+    pub cached: bool // the result was already remembered, the function didn't run
+}
+
+// what's about to be (or just was) evaluated
+pub enum Eval<'a> {
+    Expression(&'a Expression), // a loose expression
+    Call {
+        function: &'a str,
+        arguments: &'a [RuntimeExpression] // as written, they're evaluated when the function binds them
+    }
+}
+
+// set from the project manifest, checked whenever a function is called
+#[derive(Clone, Default)]
+pub struct Limits {
+    pub max_depth: Option<usize>,
+    pub deadline: Option<Instant>
 }
 
 #[derive(Clone)]
 pub struct ExternalRuntimeFunction {
     pub name: String,
     pub parameters: usize,
-    pub invoke: fn(Vec<RuntimeExpression>, &mut RuntimeAST) -> BigInt
+    pub variadic: bool,
+    pub pure: bool, // false for externals with effects (output, input, files, time), functions calling them are never cached automatically
+    pub invoke: fn(Vec<RuntimeExpression>, &mut RuntimeAST) -> Value
 }
 
 #[derive(Clone, Debug)]
 pub struct RuntimeVariable {
     pub name: String,
-    pub definition: RuntimeExpression,
-    pub function_argument: bool
+    pub definition: Arc<RuntimeExpression>,
+    pub function_argument: bool,
+    pub constant: bool // defined with const, it can't be reassigned
 }
 
 #[derive(Clone)]
 pub struct RuntimeFunction {
     pub name: String,
-    pub definition: RuntimeExpression,
+    pub definition: Arc<RuntimeExpression>,
     pub parameters: Vec<String>,
-    pub cached: bool,
-    pub cache: Vec<Tuple<Vec<RuntimeExpression>, BigInt>>
-}
-
-#[derive(Clone, Debug)]
-pub struct Tuple<A: PartialEq, B: PartialEq> {
-    pub a: A,
-    pub b: B
+    pub variadic: bool,
+    pub cached: bool
 }
 
 #[derive(Clone, Debug)]
@@ -0,0 +1,233 @@
+use crate::ast::{Expression, MathType, Slot, CallTarget};
+use crate::interpreter::runtime::{RuntimeAST, RuntimeExpression};
+use crate::value::Value;
+use std::sync::Arc;
+
+// the rest of longer evaluations (like deep recursion) is done in one step
+const MAX_STEPS: usize = 100;
+
+// externals that don't evaluate all of their arguments up front, calls of them are evaluated in one step
+const LAZY: [&str; 2] = ["try", "or_else"];
+
+// --explain, the expression is written and then rewritten one step at a time: variables are replaced by their definitions,
+// calls of functions by their bodies (with the arguments filled in) and the innermost operations are evaluated one by one
+pub fn explain(expr: &Expression, runtime: &mut RuntimeAST) -> Value {
+    let mut expr = expr.clone();
+
+    runtime.write(&format!("{}\n", show(&expr)));
+
+    for _ in 0..MAX_STEPS {
+        match step(&expr, runtime) {
+            Some(next) => expr = next,
+            None => break
+        }
+
+        runtime.write(&format!("= {}\n", show(&expr)));
+    }
+
+    let value = RuntimeExpression::execute_expr(&expr, runtime);
+
+    if !is_value(&expr) {
+        runtime.write(&format!("= {}\n", show(&Expression::Value { value: value.clone() })));
+    }
+
+    value
+}
+
+// -3 in the source is a negated number, but it's written like a value
+fn is_value(expr: &Expression) -> bool {
+    match expr {
+        Expression::NumberValue { .. } | Expression::Value { .. } => true,
+        Expression::Negate { value, .. } => matches!(value.as_ref(), Expression::NumberValue { .. }),
+        _ => false
+    }
+}
+
+// None if the expression is a value already
+fn step(expr: &Expression, runtime: &mut RuntimeAST) -> Option<Expression> {
+    match expr {
+        _ if is_value(expr) => None,
+        Expression::VariableAccess { variable } => Some(runtime.lookup_variable(variable).definition.orig.clone()),
+        Expression::ResolvedVariable { slot: Slot::Global(index), .. } => Some(runtime.get_variables()[*index].definition.orig.clone()),
+        Expression::Conditional { condition, then, otherwise } => Some(match step(condition, runtime) {
+            Some(next) => Expression::Conditional {
+                condition: Arc::new(next),
+                then: then.clone(),
+                otherwise: otherwise.clone()
+            },
+            None if RuntimeExpression::execute_expr(condition, runtime).is_true() => then.as_ref().clone(),
+            None => otherwise.as_ref().clone()
+        }),
+        // -(1 + 2) becomes -3 in one step, see is_value
+        Expression::Negate { value, span } => {
+            let value = step(value, runtime).unwrap_or_else(|| value.as_ref().clone());
+            let next = Expression::Negate {
+                value: Arc::new(value.clone()),
+                span: span.clone()
+            };
+
+            Some(if is_value(&value) { evaluated(&next, runtime) } else { next })
+        },
+        Expression::Math { var1, var2, math, span } => {
+            if let Some(next) = step(var1, runtime) {
+                return Some(Expression::Math {
+                    var1: Arc::new(next),
+                    var2: var2.clone(),
+                    math: math.clone(),
+                    span: span.clone()
+                });
+            }
+
+            if RuntimeExpression::short_circuit(math, &RuntimeExpression::execute_expr(var1, runtime)).is_some() {
+                return Some(evaluated(expr, runtime));
+            }
+
+            if let Some(next) = step(var2, runtime) {
+                return Some(Expression::Math {
+                    var1: var1.clone(),
+                    var2: Arc::new(next),
+                    math: math.clone(),
+                    span: span.clone()
+                });
+            }
+
+            Some(evaluated(expr, runtime))
+        },
+        Expression::FunctionInvocation { function, arguments, .. } | Expression::ResolvedInvocation { function, arguments, .. } => Some(invocation(expr, function, arguments, runtime)),
+        _ => Some(evaluated(expr, runtime))
+    }
+}
+
+fn invocation(expr: &Expression, function: &str, arguments: &[Expression], runtime: &mut RuntimeAST) -> Expression {
+    let target = runtime.target(function, arguments.len());
+
+    if let CallTarget::External(_) = target {
+        if function.eq("if") { // only the branch the condition picks is evaluated
+            return match step(&arguments[0], runtime) {
+                Some(next) => with_argument(expr, 0, next),
+                None => arguments[if RuntimeExpression::execute_expr(&arguments[0], runtime).is_true() { 1 } else { 2 }].clone()
+            }
+        }
+
+        if LAZY.contains(&function) {
+            return evaluated(expr, runtime);
+        }
+    }
+
+    for (i, argument) in arguments.iter().enumerate() {
+        if let Some(next) = step(argument, runtime) {
+            return with_argument(expr, i, next);
+        }
+    }
+
+    let body = match target {
+        CallTarget::Function(id) => {
+            let function = runtime.get_functions()[id].clone();
+
+            if function.variadic {
+                None
+            } else {
+                substitute(&function.definition.orig, &function.parameters, arguments)
+            }
+        },
+        CallTarget::External(_) => None
+    };
+
+    body.unwrap_or_else(|| evaluated(expr, runtime))
+}
+
+fn evaluated(expr: &Expression, runtime: &mut RuntimeAST) -> Expression {
+    Expression::Value {
+        value: RuntimeExpression::execute_expr(expr, runtime)
+    }
+}
+
+fn with_argument(expr: &Expression, i: usize, argument: Expression) -> Expression {
+    let mut next = expr.clone();
+
+    if let Expression::FunctionInvocation { arguments, .. } | Expression::ResolvedInvocation { arguments, .. } = &mut next {
+        arguments[i] = argument;
+    }
+
+    next
+}
+
+// the body of a function with its parameters replaced by the arguments, None for bodies with their own scopes (match,
+// comprehensions, ...) since those are only evaluated as a whole
+fn substitute(expr: &Expression, parameters: &[String], arguments: &[Expression]) -> Option<Expression> {
+    match expr {
+        Expression::NumberValue { .. } | Expression::Value { .. } | Expression::ResolvedVariable { slot: Slot::Global(_), .. } => Some(expr.clone()),
+        Expression::ResolvedVariable { slot: Slot::Argument(index), .. } => arguments.get(*index).cloned(),
+        Expression::VariableAccess { variable } => Some(parameters.iter().position(|p| p.eq(variable)).map(|i| arguments[i].clone()).unwrap_or_else(|| expr.clone())),
+        Expression::Negate { value, span } => Some(Expression::Negate {
+            value: Arc::new(substitute(value, parameters, arguments)?),
+            span: span.clone()
+        }),
+        Expression::Conditional { condition, then, otherwise } => Some(Expression::Conditional {
+            condition: Arc::new(substitute(condition, parameters, arguments)?),
+            then: Arc::new(substitute(then, parameters, arguments)?),
+            otherwise: Arc::new(substitute(otherwise, parameters, arguments)?)
+        }),
+        Expression::Math { var1, var2, math, span } => Some(Expression::Math {
+            var1: Arc::new(substitute(var1, parameters, arguments)?),
+            var2: Arc::new(substitute(var2, parameters, arguments)?),
+            math: math.clone(),
+            span: span.clone()
+        }),
+        Expression::FunctionInvocation { function, arguments: args, span } => Some(Expression::FunctionInvocation {
+            function: function.to_owned(),
+            arguments: args.iter().map(|arg| substitute(arg, parameters, arguments)).collect::<Option<Vec<Expression>>>()?,
+            span: span.clone()
+        }),
+        Expression::ResolvedInvocation { function, target, arguments: args, span } => Some(Expression::ResolvedInvocation {
+            function: function.to_owned(),
+            target: *target,
+            arguments: args.iter().map(|arg| substitute(arg, parameters, arguments)).collect::<Option<Vec<Expression>>>()?,
+            span: span.clone()
+        }),
+        _ => None
+    }
+}
+
+// like RuntimeExpression::expr_to_string, but with parentheses only where the parser needs them
+pub fn show(expr: &Expression) -> String {
+    match expr {
+        Expression::Math { var1, var2, math, .. } => {
+            let precedence = precedence(math);
+
+            // on the same level only the right operand needs them, except for ^ which is right-associative
+            if MathType::Pow == *math {
+                format!("{} {} {}", operand(var1, precedence + 1), math.operator(), operand(var2, precedence))
+            } else {
+                format!("{} {} {}", operand(var1, precedence), math.operator(), operand(var2, precedence + 1))
+            }
+        },
+        Expression::Negate { value, .. } => format!("-{}", operand(value, precedence(&MathType::Pow))),
+        Expression::Conditional { condition, then, otherwise } => format!("{} ? {} : {}", operand(condition, precedence(&MathType::Or)), show(then), show(otherwise)),
+        Expression::FunctionInvocation { function, arguments, .. } | Expression::ResolvedInvocation { function, arguments, .. } => format!("{}({})", function, arguments.iter().map(show).collect::<Vec<String>>().join(", ")),
+        Expression::Index { value, index, .. } => format!("{}[{}]", operand(value, u8::MAX), show(index)),
+        Expression::Slice { value, from, to, .. } => format!("{}[{}:{}]", operand(value, u8::MAX), from.as_deref().map(show).unwrap_or_default(), to.as_deref().map(show).unwrap_or_default()),
+        _ => RuntimeExpression::expr_to_string(expr)
+    }
+}
+
+fn operand(expr: &Expression, min: u8) -> String {
+    match expr {
+        Expression::Math { math, .. } if precedence(math) < min => format!("({})", show(expr)),
+        Expression::Negate { .. } if min > precedence(&MathType::Pow) => format!("({})", show(expr)), // -x ^ 2 is -(x ^ 2)
+        Expression::VariableAssignment { .. } | Expression::Match { .. } | Expression::Conditional { .. } => format!("({})", show(expr)),
+        _ => show(expr)
+    }
+}
+
+// the same levels as the parser's (see Precedence)
+fn precedence(math: &MathType) -> u8 {
+    match math {
+        MathType::Or => 3,
+        MathType::And => 4,
+        MathType::Add | MathType::Subtract => 7,
+        MathType::Multiply | MathType::Divide | MathType::FloorDivide => 8,
+        MathType::Pow => 9,
+        _ => 5
+    }
+}
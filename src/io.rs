@@ -0,0 +1,120 @@
+use std::io::{stdin, stdout, BufRead, Write};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+pub type Input = Arc<Mutex<dyn BufRead + Send>>;
+pub type Output = Arc<Mutex<dyn Write + Send>>;
+
+// everything the runtime does with the outside world, embedders that don't have a terminal (or want to capture the output) bring their own
+pub trait Io: Send + Sync {
+    // including the line break, empty once the input has ended
+    fn read_line(&self) -> String;
+
+    fn write(&self, s: &str);
+
+    fn flush(&self);
+
+    fn sleep(&self, duration: Duration);
+}
+
+// stdin and stdout of the process
+pub struct StdIo;
+
+// reads from and writes to the given streams, e.g. a Cursor and a Vec<u8> to run a program on fixed input
+pub struct Streams {
+    pub input: Input,
+    pub output: Output
+}
+
+impl Streams {
+    pub fn new(input: Input, output: Output) -> Self {
+        Streams {
+            input,
+            output
+        }
+    }
+}
+
+impl Io for StdIo {
+    fn read_line(&self) -> String {
+        let mut line = String::new();
+
+        stdin().read_line(&mut line).expect("Failed to read line");
+
+        line
+    }
+
+    fn write(&self, s: &str) {
+        stdout().write_all(s.as_bytes()).expect("Failed to write output");
+    }
+
+    fn flush(&self) {
+        stdout().flush().unwrap();
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+impl Io for Streams {
+    fn read_line(&self) -> String {
+        let mut line = String::new();
+
+        self.input.lock().unwrap().read_line(&mut line).expect("Failed to read line");
+
+        line
+    }
+
+    fn write(&self, s: &str) {
+        self.output.lock().unwrap().write_all(s.as_bytes()).expect("Failed to write output");
+    }
+
+    fn flush(&self) {
+        self.output.lock().unwrap().flush().unwrap();
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+// writes every line that is read through it to the record as well, so that an interactive run can be replayed
+pub struct Recorder {
+    io: Arc<dyn Io>,
+    record: Output
+}
+
+impl Recorder {
+    pub fn new(io: Arc<dyn Io>, record: Output) -> Self {
+        Recorder {
+            io,
+            record
+        }
+    }
+}
+
+impl Io for Recorder {
+    fn read_line(&self) -> String {
+        let line = self.io.read_line();
+        let mut record = self.record.lock().unwrap();
+
+        record.write_all(line.as_bytes()).expect("Failed to record input");
+        record.flush().unwrap();
+
+        line
+    }
+
+    fn write(&self, s: &str) {
+        self.io.write(s);
+    }
+
+    fn flush(&self) {
+        self.io.flush();
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.io.sleep(duration);
+    }
+}
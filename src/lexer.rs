@@ -1,10 +1,42 @@
 use regex::{Regex, escape};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::fs::read_to_string;
+use crate::ast::Span;
+use crate::error::{ErrorCode, MathError, catch, raise, raise_at, rethrow};
+use std::num::NonZeroUsize;
+use std::sync::OnceLock;
+use std::thread;
+use log::debug;
+
+pub type Version = (u32, u32);
+
+pub const LANGUAGE_VERSION: Version = (0, 2); // files without a #lang pragma are assumed to target this
+
+// syntax that was added after 0.1, files declaring an older version can't use it (doc comments are plain comments there)
+const FEATURES: [(&str, Version, &str); 2] = [
+    ("MACRO", (0, 2), "Macro definitions"),
+    ("ELLIPSIS", (0, 2), "Variadic functions")
+];
+// words the parser treats specially by their default spelling, the parser only looks at the ids of their tokens (the word in upper case)
+// so that they can be spelled differently (see LexerData::keyword)
+const KEYWORDS: [&str; 14] = ["let", "const", "define", "where", "external", "cache", "macro", "match", "none", "for", "in", "if", "step", "test"];
+const INCLUDES: Version = (0, 2);
+const DOC_COMMENTS: Version = (0, 2);
+const PARALLEL_LINES: usize = 4096; // smaller programs are lexed faster than threads can be started
+
+static DEFAULT_DATA: OnceLock<LexerData> = OnceLock::new();
+static SYNTHETIC_TOKENS: OnceLock<Vec<Token>> = OnceLock::new();
 
 #[derive(Debug)]
 pub struct Line {
     content: String,
+    comment: Option<String>,
+    documentation: Option<String>,
     line: usize,
-    file: String
+    file: String,
+    version: Version,
+    pragma: Option<String>
 }
 
 #[derive(PartialEq, Debug, Clone)]
@@ -15,9 +47,10 @@ pub struct LexedToken {
     line_content: String,
     token_type: Token,
     file: String,
+    trivia: Vec<LexedToken>
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct LexerData {
     tokens: Vec<Token>
 }
@@ -25,6 +58,7 @@ pub struct LexerData {
 #[derive(Debug, Clone)]
 pub struct Token {
     id: &'static str,
+    keyword: Option<&'static str>, // of identifiers that are keywords in some places (e.g. for in comprehensions)
     regex: Regex
 }
 
@@ -32,10 +66,6 @@ impl PartialEq for Token {
     fn eq(&self, other: &Self) -> bool {
         self.id.eq(other.id) && self.regex.as_str().eq(other.regex.as_str())
     }
-
-    fn ne(&self, other: &Self) -> bool {
-        !self.eq(other)
-    }
 }
 
 impl Line {
@@ -43,6 +73,14 @@ impl Line {
         &self.content
     }
 
+    pub fn comment(&self) -> &Option<String> {
+        &self.comment
+    }
+
+    pub fn documentation(&self) -> &Option<String> {
+        &self.documentation
+    }
+
     pub fn line(&self) -> &usize {
         &self.line
     }
@@ -50,6 +88,14 @@ impl Line {
     pub fn file(&self) -> &String {
         &self.file
     }
+
+    pub fn version(&self) -> &Version {
+        &self.version
+    }
+
+    pub fn pragma(&self) -> &Option<String> {
+        &self.pragma
+    }
 }
 
 impl LexedToken {
@@ -70,37 +116,33 @@ impl LexedToken {
     }
 
     pub fn err(&self, message: &str) -> ! {
-        panic!("{}\n{} |     {}\n{} |{}{} {} [{}]",
-               if self.line == 0 {
-                   "".to_owned()
-               } else {
-                   "\n".to_owned() + &" ".repeat(self.line.to_string().len()) + &" |"
-               },
-               self.line + 1,
-               self.line_content,
-               " ".repeat(self.line.to_string().len()),
-               " ".repeat("     ".len() + self.index),
-               "^".repeat(self.content.len()),
-               message,
-               self.file
-        )
+        self.err_neg_offset(message, 0)
     }
 
     pub fn err_neg_offset(&self, message: &str, offset: isize) -> ! {
-        panic!("{}\n{} |     {}\n{} |{}{} {} [{}]",
-               if self.line == 0 {
-                   "".to_owned()
-               } else {
-                   "\n".to_owned() + &" ".repeat(self.line.to_string().len()) + &" |"
-               },
-               self.line + 1,
-               self.line_content,
-               " ".repeat(self.line.to_string().len()),
-               " ".repeat(("     ".len() as isize + self.index as isize - offset) as usize),
-               "^".repeat(self.content.len()),
-               message,
-               self.file
-        )
+        raise_at(ErrorCode::UnexpectedToken, message, self.diagnostic(message, offset))
+    }
+
+    pub fn err_with(&self, code: ErrorCode, message: &str) -> ! {
+        self.err_offset_with(code, message, 0)
+    }
+
+    pub fn err_offset_with(&self, code: ErrorCode, message: &str, offset: usize) -> ! {
+        raise_at(code, message, self.diagnostic(message, -(offset as isize)))
+    }
+
+    pub fn diagnostic(&self, message: &str, offset: isize) -> String {
+        self.span().diagnostic(message, offset)
+    }
+
+    pub fn span(&self) -> Span {
+        Span {
+            file: self.file.clone(),
+            line: self.line,
+            index: self.index,
+            length: self.content.len(),
+            line_content: self.line_content.clone()
+        }
     }
 
     pub fn content(&self) -> &String {
@@ -119,31 +161,96 @@ impl LexedToken {
         &self.line_content
     }
 
+    pub fn file(&self) -> &String {
+        &self.file
+    }
+
     pub fn token_type(&self) -> &Token {
         &self.token_type
     }
 
-    pub fn clone(&self) -> LexedToken {
+    pub fn trivia(&self) -> &Vec<LexedToken> {
+        &self.trivia
+    }
+
+    // the same token spelled differently (e.g. a fresh name for a macro binder), diagnostics still point at the original
+    pub fn renamed(&self, content: String) -> LexedToken {
         LexedToken {
-            content: self.content.clone(),
-            line: self.line.clone(),
-            index: self.index.clone(),
-            line_content: self.line_content.clone(),
-            token_type: self.token_type.clone(),
-            file: self.file.clone()
+            content,
+            ..self.clone()
         }
     }
 }
 
+// the regexes are compiled once, clones share them
+impl Default for LexerData {
+    fn default() -> Self {
+        DEFAULT_DATA.get_or_init(|| data(default_tokens())).clone()
+    }
+}
+
 impl LexerData {
     pub fn tokens(&self) -> &Vec<Token> {
         &self.tokens
     }
+
+    // makes spelling another way to write the token spelled operator (e.g. ** for ^), false if there's no such token,
+    // aliases are tried before the other tokens so that they win over the tokens they start with,
+    // one that ends like a word only matches whole words (and isn't the start of android)
+    pub fn alias(&mut self, spelling: &str, operator: &str) -> bool {
+        let pattern = format!("^{}", escape(operator));
+        let boundary = if spelling.ends_with(|c: char| c.is_alphanumeric() || c == '_') { "\\b" } else { "" };
+
+        match self.tokens.iter().find(|t| t.regex.as_str().eq(&pattern)) {
+            Some(t) if !spelling.is_empty() => {
+                let alias = Token {
+                    id: t.id,
+                    keyword: t.keyword,
+                    regex: Regex::new(&format!("^{}{}", escape(spelling), boundary)).unwrap()
+                };
+
+                self.tokens.insert(0, alias);
+
+                true
+            },
+            _ => false
+        }
+    }
+
+    // the keyword an identifier is where the parser expects one (e.g. step after a range)
+    pub fn contextual(&self, word: &str) -> Option<&'static str> {
+        self.tokens.iter().filter(|t| t.keyword.is_some()).find(|t| t.regex.find(word).is_some_and(|m| m.len() == word.len())).and_then(|t| t.keyword)
+    }
+
+    // spells the keyword (given by its default spelling, e.g. let) another way, e.g. to teach in another language,
+    // false if there's no such keyword or the spelling isn't a word, the default spelling isn't a keyword anymore
+    pub fn keyword(&mut self, keyword: &str, spelling: &str) -> bool {
+        let id = keyword.to_uppercase();
+        let word = spelling.chars().next().is_some_and(char::is_alphabetic) && spelling.chars().all(|c| c.is_alphanumeric() || c == '_');
+
+        if !KEYWORDS.contains(&keyword) || !word {
+            return false;
+        }
+
+        // the last one, aliases come first (so is the original spelling)
+        match self.tokens.iter_mut().rev().find(|t| t.keyword.unwrap_or(t.id).eq(&id)) {
+            Some(t) => {
+                t.regex = Regex::new(&format!("^{}\\b", escape(spelling))).unwrap();
+
+                true
+            },
+            None => false
+        }
+    }
 }
 
 impl Token {
     pub fn id(&self) -> &'static str {
-        &self.id
+        self.id
+    }
+
+    pub fn keyword(&self) -> Option<&'static str> {
+        self.keyword
     }
 
     pub fn regex(&self) -> &Regex {
@@ -153,6 +260,7 @@ impl Token {
     pub fn empty() -> Token {
         Token {
             id: "",
+            keyword: None,
             regex: Regex::new("^$").unwrap()
         }
     }
@@ -160,19 +268,454 @@ impl Token {
     pub fn copy(&self) -> Token {
         Token {
             id: self.id,
+            keyword: self.keyword,
             regex: Regex::new(self.regex.as_str()).unwrap()
         }
     }
 }
 
 pub fn read_lines(comment: String, content: String, file: String) -> Vec<Line> {
-    content.lines().enumerate().map(|(i, s)| {
-        Line {
-            content: s.split(&comment).next().unwrap().to_owned().replace("\t", "    "),
+    read_lines_including(comment, content, file, &[], &mut vec![])
+}
+
+// includes that aren't found next to the including file are looked up in the include paths (in order)
+pub fn read_lines_with_include_paths(comment: String, content: String, file: String, include_paths: &[PathBuf]) -> Vec<Line> {
+    read_lines_including(comment, content, file, include_paths, &mut vec![])
+}
+
+// a first line like "#!comment //" changes the comment marker of that file
+pub fn comment_pragma(content: &str) -> Option<String> {
+    content.lines().next()?.trim().strip_prefix("#!comment").map(|c| c.trim().to_owned()).filter(|c| !c.is_empty())
+}
+
+// "#lang math 0.1" declares the language version a file was written for, it has to come before any code
+pub fn lang_pragma(line: &str) -> Option<Option<Version>> {
+    let rest = line.trim().strip_prefix("#lang ")?;
+    let version = rest.trim().strip_prefix("math ").and_then(|v| v.trim().split_once('.')).and_then(|(major, minor)| Some((major.parse().ok()?, minor.parse().ok()?)));
+
+    Some(version)
+}
+
+fn read_lines_including(comment: String, content: String, file: String, include_paths: &[PathBuf], included: &mut Vec<PathBuf>) -> Vec<Line> {
+    let mut lines = Vec::<Line>::new();
+    let pragma = comment_pragma(&content);
+    let comment = pragma.clone().unwrap_or(comment);
+    let mut version = LANGUAGE_VERSION;
+    let mut code = false;
+
+    for (i, s) in content.lines().enumerate() {
+        let s = s.replace("\t", "    ");
+
+        if i == 0 && pragma.is_some() {
+            lines.push(Line {
+                content: String::new(),
+                comment: Some(s),
+                documentation: None,
+                line: i,
+                file: file.clone(),
+                version,
+                pragma: None
+            });
+
+            continue;
+        }
+
+        if let Some(declared) = lang_pragma(&s) {
+            version = declared.unwrap_or_else(|| raise(ErrorCode::UnsupportedVersion, &format!("Malformed #lang pragma, expected #lang math <major>.<minor> ({}:{})", file, i + 1)));
+
+            if code {
+                raise(ErrorCode::UnsupportedVersion, &format!("#lang has to come before any code ({}:{})", file, i + 1));
+            }
+
+            if version > LANGUAGE_VERSION {
+                raise(ErrorCode::UnsupportedVersion, &format!("This file requires a newer interpreter (it targets math {}, this interpreter supports up to {}) ({}:{})", show(version), show(LANGUAGE_VERSION), file, i + 1));
+            }
+
+            lines.push(Line {
+                content: String::new(),
+                comment: Some(s),
+                documentation: None,
+                line: i,
+                file: file.clone(),
+                version,
+                pragma: None
+            });
+
+            continue;
+        }
+
+        if let Some(pragma) = s.trim().strip_prefix("#pragma ") { // handed to the parser as a PRAGMA token
+            lines.push(Line {
+                content: String::new(),
+                comment: Some(s.clone()),
+                documentation: None,
+                line: i,
+                file: file.clone(),
+                version,
+                pragma: Some(pragma.trim().to_owned())
+            });
+
+            continue;
+        }
+
+        if let Some(include) = s.trim().strip_prefix("#include") { // textual inclusion, paths are relative to the including file
+            let include = include.trim();
+
+            code = true;
+
+            if version < INCLUDES {
+                raise(ErrorCode::UnsupportedVersion, &format!("#include requires #lang math {} or newer (this file targets {}) ({}:{})", show(INCLUDES), show(version), file, i + 1));
+            }
+
+            if include.len() < 2 || !include.starts_with('"') || !include.ends_with('"') {
+                raise(ErrorCode::MalformedInclude, &format!("Malformed include, expected #include \"file\" ({}:{})", file, i + 1));
+            }
+
+            let name = &include[1..include.len() - 1];
+            let relative = Path::new(&file).parent().unwrap_or_else(|| Path::new("")).join(name);
+            let path = if relative.exists() {
+                relative
+            } else {
+                include_paths.iter().map(|p| p.join(name)).find(|p| p.exists()).unwrap_or(relative)
+            };
+            let canonical = path.canonicalize().unwrap_or_else(|_| raise(ErrorCode::IncludeNotFound, &format!("Included file not found ('{}') ({}:{})", path.display(), file, i + 1)));
+
+            if included.contains(&canonical) {
+                raise(ErrorCode::RecursiveInclude, &format!("Recursive include of '{}' ({}:{})", path.display(), file, i + 1));
+            }
+
+            let included_content = read_to_string(&path).unwrap_or_else(|_| raise(ErrorCode::IncludeNotFound, &format!("Error while reading included file '{}' ({}:{})", path.display(), file, i + 1)));
+
+            debug!("Including '{}' ({}:{})", path.display(), file, i + 1);
+
+            included.push(canonical);
+            lines.extend(read_lines_including(comment.clone(), included_content, path.to_str().unwrap().to_owned(), include_paths, included));
+            included.pop();
+
+            continue;
+        }
+
+        let start = comment_start(&s, &comment);
+        let content = s[..start.unwrap_or(s.len())].to_owned();
+        let documentation = if content.trim().is_empty() && version >= DOC_COMMENTS { // doc comments (comment marker twice) have to be on their own line
+            s.trim_start().strip_prefix(&comment.repeat(2)).map(|d| d.strip_prefix(' ').unwrap_or(d).to_owned())
+        } else {
+            None
+        };
+
+        lines.push(Line {
+            comment: start.map(|index| s[index..].to_owned()),
+            content,
+            documentation,
             line: i,
-            file: file.clone()
+            file: file.clone(),
+            version,
+            pragma: None
+        });
+
+        code |= !lines.last().unwrap().content.trim().is_empty();
+    }
+
+    lines
+}
+
+// comment markers inside string literals don't count
+fn comment_start(line: &str, comment: &str) -> Option<usize> {
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, c) in line.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if line[i..].starts_with(comment) {
+            return Some(i);
+        } else if c == '"' {
+            in_string = true;
         }
-    }).collect()
+    }
+
+    None
+}
+
+// token ids and where they are in a single line, nothing is expanded or reported so that it works on incomplete input (used for highlighting)
+pub fn spans(line: &str, comment: &str, data: &LexerData) -> Vec<(&'static str, Range<usize>)> {
+    let code = comment_start(line, comment).unwrap_or(line.len());
+    let mut spans = Vec::<(&'static str, Range<usize>)>::new();
+    let mut index = 0;
+
+    while index < code {
+        let content = &line[index..code];
+        let (id, length) = data.tokens.iter().find_map(|t| t.regex.find(content).filter(|m| !m.is_empty()).map(|m| (t.id, m.len()))).unwrap_or_else(|| if content.starts_with('"') {
+            ("STRING", content.len()) // not closed yet
+        } else {
+            ("ERROR", content.chars().next().unwrap().len_utf8())
+        });
+
+        spans.push((id, index..index + length));
+
+        index += length;
+    }
+
+    if code < line.len() {
+        spans.push(("COMMENT", code..line.len()));
+    }
+
+    spans
+}
+
+pub fn show(version: Version) -> String {
+    format!("{}.{}", version.0, version.1)
+}
+
+// the tokens of the language, tried in order (keywords before identifiers, <= before <)
+fn default_tokens() -> Vec<Token> {
+    vec![
+        token(
+            "LET",
+            "let",
+            false
+        ),
+        token(
+            "CONST",
+            "const",
+            false
+        ),
+        token(
+            "DEFINE",
+            "define",
+            false
+        ),
+        token(
+            "WHERE",
+            "where",
+            false
+        ),
+        token(
+            "EXTERNAL",
+            "external",
+            false
+        ),
+        token(
+            "CACHE",
+            "cache",
+            false
+        ),
+        token(
+            "MACRO",
+            "macro\\b",
+            true
+        ),
+        token(
+            "MATCH",
+            "match\\b",
+            true
+        ),
+        token(
+            "NONE",
+            "none\\b",
+            true
+        ),
+        token(
+            "COMMA",
+            ",",
+            false
+        ),
+        token(
+            "AND",
+            "&&",
+            false
+        ),
+        token(
+            "OR",
+            "||",
+            false
+        ),
+        token(
+            "PIPE",
+            "\\|\\s*$", // at the end of a line it continues the statement on the next one
+            true
+        ),
+        token(
+            "BAR",
+            "|",
+            false
+        ),
+        token(
+            "OPEN_PARENTHESIS",
+            "(",
+            false
+        ),
+        token(
+            "CLOSE_PARENTHESIS",
+            ")",
+            false
+        ),
+        token(
+            "OPEN_BRACKET",
+            "[",
+            false
+        ),
+        token(
+            "CLOSE_BRACKET",
+            "]",
+            false
+        ),
+        token(
+            "OPEN_BRACE",
+            "{",
+            false
+        ),
+        token(
+            "CLOSE_BRACE",
+            "}",
+            false
+        ),
+        token(
+            "COLON",
+            ":",
+            false
+        ),
+        token(
+            "QUESTION",
+            "?",
+            false
+        ),
+        token(
+            "SEMICOLON",
+            ";",
+            false
+        ),
+        token(
+            "EQUALS",
+            "==",
+            false
+        ),
+        token(
+            "NOT_EQUALS",
+            "=!",
+            false
+        ),
+        token(
+            "NOT_EQUALS",
+            "!=",
+            false
+        ),
+        token(
+            "NOT",
+            "!",
+            false
+        ),
+        token(
+            "BIGGER_OR_EQUALS",
+            ">=",
+            false
+        ),
+        token(
+            "BIGGER",
+            ">",
+            false
+        ),
+        token(
+            "SMALLER_OR_EQUALS",
+            "<=",
+            false
+        ),
+        token(
+            "SMALLER",
+            "<",
+            false
+        ),
+        token(
+            "ASSIGN",
+            "=",
+            false
+        ),
+        token(
+            "PLUS",
+            "+",
+            false
+        ),
+        token(
+            "ARROW",
+            "->",
+            false
+        ),
+        token(
+            "MINUS",
+            "-",
+            false
+        ),
+        token(
+            "FLOOR_DIVIDE",
+            "//",
+            false
+        ),
+        token(
+            "DIVIDE",
+            "/",
+            false
+        ),
+        token(
+            "MULTIPLY",
+            "*",
+            false
+        ),
+        token(
+            "POW",
+            "^",
+            false
+        ),
+        token(
+            "ELLIPSIS",
+            "...",
+            false
+        ),
+        token(
+            "RANGE",
+            "..",
+            false
+        ),
+        token(
+            "DOT",
+            ".",
+            false
+        ),
+        token(
+            "STRING",
+            "\"([^\"\\\\$]|\\\\.|\\$\\{[^}]*\\}|\\$)*\"", // quotes can be used inside ${...}
+            true
+        ),
+        token(
+            "WILDCARD",
+            "_\\b",
+            true
+        ),
+        token(
+            "NUMBER",
+            "([0-9_]+(\\.[0-9_]+)*)", // not 1..2, that's a range
+            true
+        ),
+        token(
+            "WHITESPACE",
+            "\\s+",
+            true
+        ),
+        contextual("FOR", "for"),
+        contextual("IN", "in"),
+        contextual("IF", "if"),
+        contextual("STEP", "step"),
+        contextual("TEST", "test"),
+        token(
+            "IDENTIFIER",
+            "[a-zA-Z][A-Za-z0-9_]*(\\*|)",
+            true
+        )
+    ]
 }
 
 pub fn data(tokens: Vec<Token>) -> LexerData {
@@ -184,6 +727,7 @@ pub fn data(tokens: Vec<Token>) -> LexerData {
 pub fn token(id: &'static str, regex: &'static str, is_regex: bool) -> Token {
     Token {
         id,
+        keyword: None,
         regex: Regex::new(&format!("^{}", if is_regex {
             regex.to_owned()
         } else {
@@ -192,48 +736,90 @@ pub fn token(id: &'static str, regex: &'static str, is_regex: bool) -> Token {
     }
 }
 
-pub fn full_lex(content: String, file: String, comment: String, data: LexerData) -> Vec<LexedToken> {
+// identifiers that the parser treats as keywords where it expects one of them (and as identifiers everywhere else)
+fn contextual(keyword: &'static str, spelling: &str) -> Token {
+    Token {
+        id: "IDENTIFIER",
+        keyword: Some(keyword),
+        regex: Regex::new(&format!("^{}\\b", escape(spelling))).unwrap()
+    }
+}
+
+// tokens the lexer creates itself instead of matching them
+fn synthetic(id: &str) -> Token {
+    SYNTHETIC_TOKENS.get_or_init(|| vec![
+        token("NEW_LINE", "\n", false),
+        token("DOC_COMMENT", ".*", true),
+        token("PRAGMA", ".*", true),
+        token("COMMENT", ".*", true),
+        token("ERROR", ".", true)
+    ]).iter().find(|t| t.id.eq(id)).unwrap().clone()
+}
+
+pub fn full_lex(content: String, file: String, comment: String, data: &LexerData) -> Vec<LexedToken> {
     lex(read_lines(comment, content, file), data)
 }
 
-pub fn lex(lines: Vec<Line>, data: LexerData) -> Vec<LexedToken> {
-    let mut tokens = Vec::new();
+pub fn full_lex_with_include_paths(content: String, file: String, comment: String, data: &LexerData, include_paths: &[PathBuf]) -> Vec<LexedToken> {
+    lex(read_lines_with_include_paths(comment, content, file, include_paths), data)
+}
 
-    lines.iter().enumerate().for_each(|(i, l)| {
-        let mut index = 0;
+pub fn full_lex_with_trivia(content: String, file: String, comment: String, data: &LexerData) -> Vec<LexedToken> {
+    lex_with_trivia(read_lines(comment, content, file), data)
+}
 
-        while !l.content[index..].is_empty() {
-            let mut found_token = false;
-            let content = &l.content[index..];
+// tokens never span lines, so big programs are split into chunks of lines that are lexed on their own threads
+pub fn lex(lines: Vec<Line>, data: &LexerData) -> Vec<LexedToken> {
+    let threads = thread::available_parallelism().map(NonZeroUsize::get).unwrap_or(1);
+    let tokens = if lines.len() < PARALLEL_LINES || threads == 1 {
+        lex_lines(&lines, data)
+    } else {
+        let chunks = thread::scope(|scope| {
+            let handles = lines.chunks(lines.len().div_ceil(threads)).map(|chunk| scope.spawn(move || catch(|| lex_lines(chunk, data)))).collect::<Vec<_>>();
 
-            data.tokens.iter().for_each(|p| {
-                if found_token {
-                    return;
-                }
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect::<Vec<Result<Vec<LexedToken>, MathError>>>()
+        });
 
-                let option = p.regex.find(content);
+        chunks.into_iter().flat_map(|chunk| chunk.unwrap_or_else(|e| rethrow(e))).collect() // the error of the first failing chunk is the one sequential lexing would've raised
+    };
 
-                if option.is_none() {
-                    return;
-                }
+    debug!("Lexed {} line(s) into {} tokens", lines.len(), tokens.len());
 
-                let found = option.unwrap();
+    tokens
+}
 
-                tokens.push(LexedToken {
-                    content: found.as_str().to_owned(),
-                    line: i,
-                    index,
-                    line_content: l.content.clone(),
-                    token_type: p.clone(),
-                    file: l.file.clone()
-                });
-                index += found.as_str().len();
-                found_token = true;
+fn lex_lines(lines: &[Line], data: &LexerData) -> Vec<LexedToken> {
+    let mut tokens = Vec::new();
+
+    lines.iter().for_each(|l| {
+        let index = l.content.len();
+
+        lex_span(l, 0, index, data, &mut tokens);
+
+        if let Some(documentation) = &l.documentation {
+            tokens.push(LexedToken {
+                content: documentation.clone(),
+                line: l.line,
+                index,
+                line_content: l.content.clone(),
+                token_type: synthetic("DOC_COMMENT"),
+                file: l.file.clone(),
+                trivia: vec![]
             });
+        }
 
-            if !found_token {
-                panic!("Unrecognized token at ({}:{}):\n{}\n", l.line, index, l.content); // TODO change this to Result stuff
-            }
+        if let Some(pragma) = &l.pragma {
+            let line_content = l.comment.clone().unwrap_or_default();
+
+            tokens.push(LexedToken {
+                content: pragma.clone(),
+                line: l.line,
+                index: line_content.rfind(pragma.as_str()).unwrap_or(0),
+                line_content,
+                token_type: synthetic("PRAGMA"),
+                file: l.file.clone(),
+                trivia: vec![]
+            });
         }
 
         tokens.push(LexedToken {
@@ -241,14 +827,236 @@ pub fn lex(lines: Vec<Line>, data: LexerData) -> Vec<LexedToken> {
             line: l.line,
             index,
             line_content: "?".to_owned(),
-            token_type: token(
-                "NEW_LINE",
-                "\n",
-                false
-            ),
-            file: l.file.clone()
+            token_type: synthetic("NEW_LINE"),
+            file: l.file.clone(),
+            trivia: vec![]
         });
     });
 
     tokens
-}
\ No newline at end of file
+}
+
+// lexes l.content[index..end] (the whole line or the inside of a ${...})
+fn lex_span(l: &Line, mut index: usize, end: usize, data: &LexerData, tokens: &mut Vec<LexedToken>) {
+    while index < end {
+        let mut found_token = false;
+        let content = &l.content[index..end];
+
+        data.tokens.iter().for_each(|p| {
+            if found_token {
+                return;
+            }
+
+            let option = p.regex.find(content);
+
+            if option.is_none() {
+                return;
+            }
+
+            let found = option.unwrap();
+
+            if let Some((_, required, feature)) = FEATURES.iter().find(|(id, required, _)| p.id.eq(*id) && l.version < *required) {
+                let message = format!("{} require #lang math {} or newer (this file targets {})", feature, show(*required), show(l.version));
+                let token = LexedToken {
+                    content: found.as_str().to_owned(),
+                    line: l.line,
+                    index,
+                    line_content: l.content.clone(),
+                    token_type: p.clone(),
+                    file: l.file.clone(),
+                    trivia: vec![]
+                };
+
+                token.err_with(ErrorCode::UnsupportedVersion, &message);
+            }
+
+            if p.id.eq("STRING") && found.as_str().contains("${") {
+                interpolate(l, index, found.as_str(), data, tokens);
+            } else {
+                tokens.push(LexedToken {
+                    content: found.as_str().to_owned(),
+                    line: l.line,
+                    index,
+                    line_content: l.content.clone(),
+                    token_type: p.clone(),
+                    file: l.file.clone(),
+                    trivia: vec![]
+                });
+            }
+
+            index += found.as_str().len();
+            found_token = true;
+        });
+
+        if !found_token { // emit an error token and keep lexing so that every stray character gets reported
+            let skipped = content.chars().next().unwrap().len_utf8();
+            let previous = tokens.last_mut().filter(|t: &&mut LexedToken| t.token_type.id.eq("ERROR") && t.line == l.line && t.index + t.content.len() == index);
+
+            match previous {
+                Some(previous) => previous.content.push_str(&content[..skipped]),
+                None => tokens.push(LexedToken {
+                    content: content[..skipped].to_owned(),
+                    line: l.line,
+                    index,
+                    line_content: l.content.clone(),
+                    token_type: synthetic("ERROR"),
+                    file: l.file.clone(),
+                    trivia: vec![]
+                })
+            }
+
+            index += skipped;
+        }
+    }
+}
+
+// "a ${x} b" is lexed as concat("a ", (x), " b"), the embedded expressions can't contain a }
+fn interpolate(l: &Line, start: usize, raw: &str, data: &LexerData, tokens: &mut Vec<LexedToken>) {
+    let synthetic = |id: &str, content: &str, index: usize| LexedToken {
+        content: content.to_owned(),
+        line: l.line,
+        index,
+        line_content: l.content.clone(),
+        token_type: data.tokens.iter().find(|t| t.id.eq(id)).unwrap().clone(),
+        file: l.file.clone(),
+        trivia: vec![]
+    };
+    let inner = &raw[1..raw.len() - 1];
+    let offset = start + 1; // of inner in the line
+    let mut chars = inner.char_indices().peekable();
+    let mut literal = 0; // start of the text since the last embedded expression
+    let mut parts = 0;
+
+    tokens.push(synthetic("IDENTIFIER", "concat", start));
+    tokens.push(synthetic("OPEN_PARENTHESIS", "(", start));
+
+    while let Some((i, c)) = chars.next() {
+        if c == '\\' { // escaped, \${ is a literal ${
+            chars.next();
+
+            continue;
+        }
+
+        if c != '$' || chars.peek().map(|(_, c)| *c) != Some('{') {
+            continue;
+        }
+
+        let close = inner[i + 2..].find('}').map(|j| i + 2 + j).unwrap_or_else(|| synthetic("STRING", "${", offset + i).err("Unclosed ${ in string"));
+
+        if inner[i + 2..close].trim().is_empty() {
+            synthetic("STRING", "${", offset + i).err("Empty ${} in string");
+        }
+
+        if literal < i {
+            if parts > 0 {
+                tokens.push(synthetic("COMMA", ",", offset + literal));
+            }
+
+            tokens.push(synthetic("STRING", &format!("\"{}\"", &inner[literal..i]), offset + literal));
+            parts += 1;
+        }
+
+        if parts > 0 {
+            tokens.push(synthetic("COMMA", ",", offset + i));
+        }
+
+        tokens.push(synthetic("OPEN_PARENTHESIS", "(", offset + i + 1));
+        lex_span(l, offset + i + 2, offset + close, data, tokens);
+        tokens.push(synthetic("CLOSE_PARENTHESIS", ")", offset + close));
+        parts += 1;
+        literal = close + 1;
+
+        while chars.peek().is_some_and(|(j, _)| *j <= close) {
+            chars.next();
+        }
+    }
+
+    if literal < inner.len() {
+        if parts > 0 {
+            tokens.push(synthetic("COMMA", ",", offset + literal));
+        }
+
+        tokens.push(synthetic("STRING", &format!("\"{}\"", &inner[literal..]), offset + literal));
+    }
+
+    tokens.push(synthetic("CLOSE_PARENTHESIS", ")", start + raw.len() - 1));
+}
+
+// keeps whitespace and comments around as trivia attached to the next significant token (needed by tooling like the formatter)
+pub fn lex_with_trivia(lines: Vec<Line>, data: &LexerData) -> Vec<LexedToken> {
+    let mut comments = lines.iter().map(|l| (l.line, l.content.len(), l.comment.clone(), l.content.clone(), l.file.clone())).collect::<Vec<(usize, usize, Option<String>, String, String)>>().into_iter();
+    let mut tokens = Vec::<LexedToken>::new();
+    let mut trivia = Vec::<LexedToken>::new();
+
+    for mut t in lex(lines, data) {
+        if t.token_type.id.eq("WHITESPACE") {
+            trivia.push(t);
+
+            continue;
+        }
+
+        if t.token_type.id.eq("DOC_COMMENT") || t.token_type.id.eq("PRAGMA") { // the full comment gets attached to the NEW_LINE below
+            continue;
+        }
+
+        if t.token_type.id.eq("NEW_LINE") { // every line ends with exactly one NEW_LINE token
+            if let Some((line, index, Some(comment), line_content, file)) = comments.next() {
+                trivia.push(LexedToken {
+                    content: comment,
+                    line,
+                    index,
+                    line_content,
+                    token_type: synthetic("COMMENT"),
+                    file,
+                    trivia: vec![]
+                });
+            }
+        }
+
+        t.trivia = trivia;
+        trivia = vec![];
+
+        tokens.push(t);
+    }
+
+    tokens
+}
+
+pub fn to_source(tokens: &[LexedToken]) -> String {
+    tokens.iter().map(|t| t.trivia.iter().map(|trivia| trivia.content.clone()).collect::<String>() + &t.content).collect()
+}
+
+pub fn errors(tokens: &[LexedToken]) -> Vec<MathError> {
+    tokens.iter().filter(|t| t.token_type.id.eq("ERROR")).map(|t| {
+        let message = format!("Unrecognized token ('{}')", t.content);
+
+        MathError::at(ErrorCode::UnrecognizedToken, &message, t.diagnostic(&message, 0))
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LexerData;
+    use crate::context::Context;
+    use crate::default_externals;
+    use crate::value::Value;
+
+    fn run(aliases: &[(&str, &str)], source: &str) -> Option<Value> {
+        let mut data = LexerData::default();
+
+        for (spelling, operator) in aliases {
+            assert!(data.alias(spelling, operator));
+        }
+
+        Context::create(data, default_externals()).exec(source).unwrap()
+    }
+
+    #[test]
+    fn aliases() {
+        assert_eq!(run(&[("**", "^")], "2 ** 10"), Some(Value::from(1024)));
+        assert_eq!(run(&[("and", "&&"), ("or", "||")], "1 and 0 or 1"), Some(Value::from(1)));
+        assert_eq!(run(&[("and", "&&")], "let android = 3\nlet and_more = 4\nandroid and and_more"), Some(Value::from(1)));
+        assert_eq!(run(&[("or", "||")], "define order(x) = x + 1; order(1) or 0"), Some(Value::from(1)));
+        assert_eq!(run(&[("and", "&&")], "let andy = 0\nandy and 1"), Some(Value::from(0)));
+    }
+}
@@ -0,0 +1,1366 @@
+use crate::lexer::{full_lex, LexerData};
+use crate::parser::parse;
+use crate::ast::{AST, MathType};
+use crate::context::Context;
+use crate::interpreter::runtime::{ExternalRuntimeFunction, RuntimeAST, RuntimeExpression};
+use crate::interpreter::steps;
+use std::mem::take;
+use std::time::{Duration, Instant};
+use num_bigint::{BigInt, Sign};
+use crate::value::{Set, Value};
+use std::collections::HashSet;
+use crate::error::{ErrorCode, MathError, catch, raise};
+use crate::numeric::{floor_div, invmod, order};
+use std::convert::TryFrom;
+
+pub mod ast;
+pub mod binary;
+pub mod cache;
+pub mod context;
+pub mod doc;
+pub mod dot;
+pub mod error;
+pub mod format;
+pub mod golden;
+pub mod graph;
+pub mod interpreter;
+pub mod io;
+pub mod lexer;
+pub mod logging;
+pub mod manifest;
+pub mod numeric;
+pub mod package;
+pub mod parser;
+pub mod plot;
+pub mod repl;
+pub mod snapshot;
+pub mod style;
+pub mod testing;
+pub mod value;
+pub mod vfs;
+
+macro_rules! external {
+    ($name: expr, $parameters: expr, $invoke: expr) => {
+        ExternalRuntimeFunction::create(
+            $name,
+            $parameters,
+            $invoke
+        )
+    };
+    (impure $name: expr, $parameters: expr, $invoke: expr) => {
+        ExternalRuntimeFunction::create(
+            $name,
+            $parameters,
+            $invoke
+        ).impure()
+    };
+    (variadic $name: expr, $parameters: expr, $invoke: expr) => {
+        ExternalRuntimeFunction::create_variadic(
+            $name,
+            $parameters,
+            $invoke
+        )
+    };
+}
+
+// a host can run independent contexts on different threads (checked at compile time)
+const _: fn() = || {
+    fn thread_safe<T: Send + Sync>() {}
+
+    thread_safe::<Context>();
+    thread_safe::<RuntimeAST>();
+    thread_safe::<ExternalRuntimeFunction>();
+    thread_safe::<RuntimeExpression>();
+    thread_safe::<LexerData>();
+    thread_safe::<AST>();
+    thread_safe::<Value>();
+    thread_safe::<MathError>();
+};
+
+// evaluates a whole program with the default tokens and externals, the result is the value of its last expression
+// (e.g. eval_str("define sq(x) = x * x\nsq(3) + 1") is 10), output still goes to stdout and input comes from stdin
+pub fn eval_str(source: &str) -> Result<BigInt, MathError> {
+    catch(|| {
+        let mut ast = parse(full_lex(source.to_owned(), "<eval>".to_owned(), "#".to_owned(), &LexerData::default()), default_externals());
+        let loose_expressions = take(&mut ast.loose_expressions);
+        let mut runtime = RuntimeAST::create(ast, default_externals());
+
+        match interpreter::run(&mut runtime, &loose_expressions) {
+            Some(value) => value.number().clone(),
+            None => raise(ErrorCode::InvalidArgument, "Nothing to evaluate (the source has no expression)")
+        }
+    })
+}
+
+pub fn default_externals() -> Vec<ExternalRuntimeFunction> {
+    vec![
+        external!( // println(output)
+            impure "println",
+            1,
+            |args, ast| {
+                let value = args.first().unwrap().execute(ast);
+
+                ast.print(&value, "\n");
+
+                Value::from(0)
+            }
+        ),
+        external!( // print(output)
+            impure "print",
+            1,
+            |args, ast| {
+                let value = args.first().unwrap().execute(ast);
+
+                ast.print(&value, "");
+
+                Value::from(0)
+            }
+        ),
+        external!( // print_color(output, color), plain when not writing to a terminal
+            impure "print_color",
+            2,
+            |args, ast| {
+                let value = args.first().unwrap().execute(ast);
+                let color = args.get(1).unwrap().execute(ast);
+                let code = style::color_code(color.string()).unwrap_or_else(|| raise(ErrorCode::InvalidArgument, &format!("Unknown color ('{}'), known colors are {}", color, style::COLORS.iter().map(|(name, _)| *name).collect::<Vec<&str>>().join(", "))));
+
+                if ast.styled {
+                    ast.print(&Value::String(style::color(&value.to_string(), code)), "");
+                } else {
+                    ast.print(&value, "");
+                }
+
+                Value::from(0)
+            }
+        ),
+        external!( // bold(text), the text as a string that prints bold (unchanged when not writing to a terminal)
+            "bold",
+            1,
+            |args, ast| {
+                let value = args.first().unwrap().execute(ast);
+
+                if ast.styled {
+                    Value::String(style::bold(&value.to_string()))
+                } else {
+                    value
+                }
+            }
+        ),
+        external!( // progress(current, total), draws a progress bar on stderr (at most every 100ms, the last step is always drawn)
+            impure "progress",
+            2,
+            |args, ast| {
+                let current = args.first().unwrap().execute(ast);
+                let total = args.get(1).unwrap().execute(ast);
+                let (current, total) = u64::try_from(current.number()).ok().zip(u64::try_from(total.number()).ok().filter(|t| *t > 0)).map(|(c, t)| (c.min(t), t)).unwrap_or_else(|| raise(ErrorCode::InvalidArgument, &format!("Invalid progress ({} of {})", current, total)));
+                let now = Instant::now();
+                let due = ast.progress.is_none_or(|last| now.duration_since(last) >= Duration::from_millis(PROGRESS_INTERVAL));
+
+                if style::progress_enabled() && (due || current == total) {
+                    eprint!("{}", style::progress_bar(current, total));
+
+                    if current == total {
+                        eprintln!();
+                    }
+
+                    ast.progress = Some(now);
+                }
+
+                Value::from(0)
+            }
+        ),
+        external!( // if(condition, true, false)
+            "if",
+            3,
+            |args, ast| {
+                if args.first().unwrap().execute(ast).is_true() {
+                    args.get(1).unwrap().execute(ast)
+                } else {
+                    args.get(2).unwrap().execute(ast)
+                }
+            }
+        ),
+        external!( // try(expression), none if evaluating the expression fails
+            "try",
+            1,
+            |args, ast| {
+                ast.attempt(args.first().unwrap()).unwrap_or(Value::None)
+            }
+        ),
+        external!( // try(expression, fallback), the fallback is used if evaluating the expression fails
+            "try",
+            2,
+            |args, ast| {
+                ast.attempt(args.first().unwrap()).unwrap_or_else(|_| args.get(1).unwrap().execute(ast))
+            }
+        ),
+        external!( // none(), also written none
+            "none",
+            0,
+            |_, _| {
+                Value::None
+            }
+        ),
+        external!( // is_none(value)
+            "is_none",
+            1,
+            |args, ast| {
+                Value::bool(args.first().unwrap().execute(ast) == Value::None)
+            }
+        ),
+        external!( // or_else(value, default), the default (only evaluated then) if the value is none
+            "or_else",
+            2,
+            |args, ast| {
+                match args.first().unwrap().execute(ast) {
+                    Value::None => args.get(1).unwrap().execute(ast),
+                    value => value
+                }
+            }
+        ),
+        external!( // assert(condition)
+            "assert",
+            1,
+            |args, ast| {
+                if !args.first().unwrap().execute(ast).is_true() {
+                    raise(ErrorCode::AssertionFailed, &format!("Assertion failed: {}", source(&args[0])));
+                }
+
+                Value::bool(true)
+            }
+        ),
+        external!( // assert_eq(actual, expected)
+            "assert_eq",
+            2,
+            |args, ast| {
+                let actual = args.first().unwrap().execute(ast);
+                let expected = args.get(1).unwrap().execute(ast);
+
+                if actual != expected {
+                    raise(ErrorCode::AssertionFailed, &format!("Assertion failed: {} == {}\n    actual: {}\n  expected: {}", source(&args[0]), source(&args[1]), actual, expected));
+                }
+
+                Value::bool(true)
+            }
+        ),
+        external!( // assert_near(a, b, epsilon), |a - b| <= epsilon
+            "assert_near",
+            3,
+            |args, ast| {
+                let a = args.first().unwrap().execute(ast);
+                let b = args.get(1).unwrap().execute(ast);
+                let epsilon = args.get(2).unwrap().execute(ast);
+
+                if (a.number() - b.number()).magnitude() > epsilon.number().magnitude() {
+                    raise(ErrorCode::AssertionFailed, &format!("Assertion failed: {} ~ {} (within {})\n  left: {}\n right: {}", source(&args[0]), source(&args[1]), epsilon, a, b));
+                }
+
+                Value::bool(true)
+            }
+        ),
+        external!( // input()
+            impure "input",
+            0,
+            |_, ast| {
+                let input = ast.io.read_line();
+                let result = input.replace("\r\n", "").replace("\n", "").parse::<isize>();
+
+                if result.is_err() {
+                    raise(ErrorCode::InvalidInput, "Input must be a number");
+                }
+
+                Value::from(BigInt::from(result.unwrap()))
+            }
+        ),
+        external!( // readline(), the next line of input as a string
+            impure "readline",
+            0,
+            |_, ast| {
+                let line = ast.io.read_line();
+
+                if line.is_empty() {
+                    raise(ErrorCode::InvalidInput, "No more input");
+                }
+
+                Value::String(line.strip_suffix('\n').map(|l| l.strip_suffix('\r').unwrap_or(l)).unwrap_or(&line).to_owned())
+            }
+        ),
+        external!( // parse_int(string)
+            "parse_int",
+            1,
+            |args, ast| {
+                let s = args.first().unwrap().execute(ast);
+
+                Value::from(s.string().trim().parse::<BigInt>().unwrap_or_else(|_| raise(ErrorCode::InvalidArgument, &format!("Not an integer ('{}')", s))))
+            }
+        ),
+        external!( // parse_num(string), also accepts _ separators and 0x/0o/0b prefixes
+            "parse_num",
+            1,
+            |args, ast| {
+                let s = args.first().unwrap().execute(ast);
+
+                parse_num(s.string()).unwrap_or_else(|| raise(ErrorCode::InvalidArgument, &format!("Not a number ('{}')", s)))
+            }
+        ),
+        external!( // parse_num(string, default), default is used for malformed input
+            "parse_num",
+            2,
+            |args, ast| {
+                let s = args.first().unwrap().execute(ast);
+
+                parse_num(s.string()).unwrap_or_else(|| args.get(1).unwrap().execute(ast))
+            }
+        ),
+        external!( // split(string, separator), an empty separator splits into characters
+            "split",
+            2,
+            |args, ast| {
+                let s = args.first().unwrap().execute(ast);
+                let separator = args.get(1).unwrap().execute(ast);
+
+                Value::List(if separator.string().is_empty() {
+                    s.string().chars().map(|c| Value::String(c.to_string())).collect()
+                } else {
+                    s.string().split(separator.string().as_str()).map(|part| Value::String(part.to_owned())).collect()
+                })
+            }
+        ),
+        external!( // shl(n, k), n * 2^k
+            "shl",
+            2,
+            |args, ast| {
+                let n = args.first().unwrap().execute(ast);
+
+                Value::from(n.number() << shift(&args.get(1).unwrap().execute(ast)))
+            }
+        ),
+        external!( // shr(n, k), floor(n / 2^k)
+            "shr",
+            2,
+            |args, ast| {
+                let n = args.first().unwrap().execute(ast);
+
+                Value::from(n.number() >> shift(&args.get(1).unwrap().execute(ast)))
+            }
+        ),
+        external!( // bitand(a, b), negative numbers behave like infinite two's complement
+            "bitand",
+            2,
+            |args, ast| {
+                let a = args.first().unwrap().execute(ast);
+                let b = args.get(1).unwrap().execute(ast);
+
+                Value::from(a.number() & b.number())
+            }
+        ),
+        external!( // bitor(a, b)
+            "bitor",
+            2,
+            |args, ast| {
+                let a = args.first().unwrap().execute(ast);
+                let b = args.get(1).unwrap().execute(ast);
+
+                Value::from(a.number() | b.number())
+            }
+        ),
+        external!( // bitxor(a, b)
+            "bitxor",
+            2,
+            |args, ast| {
+                let a = args.first().unwrap().execute(ast);
+                let b = args.get(1).unwrap().execute(ast);
+
+                Value::from(a.number() ^ b.number())
+            }
+        ),
+        external!( // popcount(n), number of set bits of a non-negative number
+            "popcount",
+            1,
+            |args, ast| {
+                let n = args.first().unwrap().execute(ast);
+
+                if n.number().sign() == Sign::Minus {
+                    raise(ErrorCode::InvalidArgument, &format!("popcount needs a non-negative number ('{}')", n));
+                }
+
+                Value::from(BigInt::from(n.number().magnitude().count_ones()))
+            }
+        ),
+        external!( // bitlen(n), number of bits needed to write |n| (0 for 0)
+            "bitlen",
+            1,
+            |args, ast| {
+                Value::from(BigInt::from(args.first().unwrap().execute(ast).number().bits()))
+            }
+        ),
+        external!( // factorial(n), also written n!
+            "factorial",
+            1,
+            |args, ast| {
+                let n = args.first().unwrap().execute(ast);
+                let k = u32::try_from(n.number()).unwrap_or_else(|_| raise(ErrorCode::InvalidArgument, &format!("factorial needs a non-negative 32-bit number ('{}')", n)));
+
+                Value::from((2..=k).fold(BigInt::from(1), |product, i| product * i))
+            }
+        ),
+        external!( // powmod(base, exponent, modulus), a negative exponent uses the modular inverse of the base
+            "powmod",
+            3,
+            |args, ast| {
+                let base = args.first().unwrap().execute(ast);
+                let exponent = args.get(1).unwrap().execute(ast);
+                let modulus = modulus(&args.get(2).unwrap().execute(ast));
+
+                if exponent.number().sign() == Sign::Minus {
+                    let inverse = invmod(&base.number(), &modulus).unwrap_or_else(|| raise(ErrorCode::InvalidArgument, &format!("{} has no inverse modulo {}", base, modulus)));
+
+                    Value::from(inverse.modpow(&-exponent.number(), &modulus))
+                } else {
+                    Value::from(base.number().modpow(&exponent.number(), &modulus))
+                }
+            }
+        ),
+        external!( // invmod(a, modulus), x with a * x = 1 (mod modulus)
+            "invmod",
+            2,
+            |args, ast| {
+                let a = args.first().unwrap().execute(ast);
+                let modulus = modulus(&args.get(1).unwrap().execute(ast));
+
+                Value::from(invmod(&a.number(), &modulus).unwrap_or_else(|| raise(ErrorCode::InvalidArgument, &format!("{} has no inverse modulo {}", a, modulus))))
+            }
+        ),
+        external!( // cfrac(p, q, terms), the first terms of the continued fraction expansion of p / q (fewer if it ends earlier)
+            "cfrac",
+            3,
+            |args, ast| {
+                let mut p = args.first().unwrap().execute(ast).number().clone();
+                let mut q = args.get(1).unwrap().execute(ast).number().clone();
+                let terms = args.get(2).unwrap().execute(ast);
+                let terms = usize::try_from(terms.number()).unwrap_or_else(|_| raise(ErrorCode::InvalidArgument, &format!("Number of terms has to be non-negative ('{}')", terms)));
+                let mut expansion = Vec::<Value>::new();
+
+                if q == BigInt::from(0) {
+                    raise(ErrorCode::InvalidArgument, "Division by zero");
+                }
+
+                while expansion.len() < terms && q != BigInt::from(0) {
+                    let a = floor_div(&p, &q);
+                    let r = &p - &a * &q;
+
+                    expansion.push(Value::from(a));
+                    (p, q) = (q, r);
+                }
+
+                Value::List(expansion)
+            }
+        ),
+        external!( // convergent(cf, k), the k-th convergent (counting from 0) of a continued fraction as list(numerator, denominator)
+            "convergent",
+            2,
+            |args, ast| {
+                let cf = args.first().unwrap().execute(ast);
+                let k = args.get(1).unwrap().execute(ast);
+                let terms = usize::try_from(k.number()).ok().and_then(|k| cf.list().get(..=k)).unwrap_or_else(|| raise(ErrorCode::IndexOutOfBounds, &format!("No convergent {} for {} term(s)", k, cf.list().len())));
+                let (mut h, mut h1) = (BigInt::from(1), BigInt::from(0)); // h(n - 1), h(n - 2)
+                let (mut d, mut d1) = (BigInt::from(0), BigInt::from(1));
+
+                for a in terms {
+                    (h, h1) = (a.number() * &h + &h1, h);
+                    (d, d1) = (a.number() * &d + &d1, d);
+                }
+
+                Value::List(vec![Value::from(h), Value::from(d)])
+            }
+        ),
+        external!( // plot_svg(function, from, to, path), writes a line chart of the function (given by name) over [from, to]
+            impure "plot_svg",
+            4,
+            |args, ast| {
+                let function = args.first().unwrap().execute(ast);
+                let from = args.get(1).unwrap().execute(ast).number();
+                let to = args.get(2).unwrap().execute(ast).number();
+                let path = args.get(3).unwrap().execute(ast);
+                let step = ((&to - &from) / MAX_SAMPLES).max(BigInt::from(1));
+
+                plot_svg(ast, &function, &from, &to, &step, &path)
+            }
+        ),
+        external!( // plot_svg(function, range, path), only at the numbers of the range
+            impure "plot_svg",
+            3,
+            |args, ast| {
+                let function = args.first().unwrap().execute(ast);
+                let range = args.get(1).unwrap().execute(ast);
+                let path = args.get(2).unwrap().execute(ast);
+                let (from, to, step) = range.range();
+
+                plot_svg(ast, &function, from, to, step, &path)
+            }
+        ),
+        external!( // table(function, from, to, step), prints the inputs and outputs of the function (given by name) right-aligned
+            impure "table",
+            4,
+            |args, ast| {
+                let function = args.first().unwrap().execute(ast);
+                let from = args.get(1).unwrap().execute(ast);
+                let to = args.get(2).unwrap().execute(ast);
+                let step = args.get(3).unwrap().execute(ast);
+
+                table(ast, &function, &from.number(), &to.number(), &step.number())
+            }
+        ),
+        external!( // table(function, range)
+            impure "table",
+            2,
+            |args, ast| {
+                let function = args.first().unwrap().execute(ast);
+                let range = args.get(1).unwrap().execute(ast);
+                let (from, to, step) = range.range();
+
+                table(ast, &function, from, to, step)
+            }
+        ),
+        external!( // write_csv(path, function, from, to, step), writes x and f(x) (function given by name) with a header row
+            impure "write_csv",
+            5,
+            |args, ast| {
+                let path = args.first().unwrap().execute(ast);
+                let function = args.get(1).unwrap().execute(ast);
+                let from = args.get(2).unwrap().execute(ast);
+                let to = args.get(3).unwrap().execute(ast);
+                let step = args.get(4).unwrap().execute(ast);
+
+                write_samples(ast, &path, &function, &from.number(), &to.number(), &step.number())
+            }
+        ),
+        external!( // write_csv(path, function, range)
+            impure "write_csv",
+            3,
+            |args, ast| {
+                let path = args.first().unwrap().execute(ast);
+                let function = args.get(1).unwrap().execute(ast);
+                let range = args.get(2).unwrap().execute(ast);
+                let (from, to, step) = range.range();
+
+                write_samples(ast, &path, &function, from, to, step)
+            }
+        ),
+        external!( // write_csv(path, list), one row per element, elements that are lists become several columns
+            impure "write_csv",
+            2,
+            |args, ast| {
+                let path = args.first().unwrap().execute(ast);
+                let list = args.get(1).unwrap().execute(ast);
+                let rows = list.list().iter().map(|row| match row {
+                    Value::List(columns) => columns.clone(),
+                    value => vec![value.clone()]
+                }).collect::<Vec<Vec<Value>>>();
+
+                write_csv(ast, path.string(), rows.iter());
+
+                Value::from(BigInt::from(rows.len()))
+            }
+        ),
+        external!( // readfile(path), the whole file as a string
+            impure "readfile",
+            1,
+            |args, ast| {
+                let path = args.first().unwrap().execute(ast);
+
+                Value::String(read_file(ast, path.string()))
+            }
+        ),
+        external!( // writefile(path, content), replaces the file, the content is written as text
+            impure "writefile",
+            2,
+            |args, ast| {
+                let path = args.first().unwrap().execute(ast);
+                let content = args.get(1).unwrap().execute(ast).to_string();
+
+                write_file(ast, path.string(), content.as_bytes());
+
+                Value::from(content.chars().count() as i64)
+            }
+        ),
+        external!( // snapshot(path), saves the assigned variables and cached results so that restore(path) can resume from there
+            impure "snapshot",
+            1,
+            |args, ast| {
+                let path = args.first().unwrap().execute(ast);
+                let bytes = snapshot::snapshot(ast);
+
+                write_file(ast, path.string(), &bytes);
+
+                Value::from(bytes.len() as i64)
+            }
+        ),
+        external!( // restore(path), the number of restored variables and cached results
+            impure "restore",
+            1,
+            |args, ast| {
+                let path = args.first().unwrap().execute(ast);
+                let bytes = read_bytes(ast, path.string());
+                let restored = snapshot::restore(ast, &bytes).unwrap_or_else(|e| raise(e.code(), &format!("{} ('{}')", e.message(), path)));
+
+                Value::from(restored as i64)
+            }
+        ),
+        external!( // read_csv(path, column), the numbers in a column given by index (from 0) or header name, a header row is skipped
+            impure "read_csv",
+            2,
+            |args, ast| {
+                let path = args.first().unwrap().execute(ast);
+                let column = args.get(1).unwrap().execute(ast);
+                let content = read_file(ast, path.string());
+                let rows = parse_csv(&content);
+                let index = match &column {
+                    Value::String(name) => rows.first().and_then(|header| header.iter().position(|h| h.trim().eq(name))).unwrap_or_else(|| raise(ErrorCode::InvalidArgument, &format!("No column named '{}' in '{}'", name, path))),
+                    _ => usize::try_from(column.number()).unwrap_or_else(|_| raise(ErrorCode::InvalidArgument, &format!("Invalid column ('{}')", column)))
+                };
+                let mut values = Vec::<Value>::new();
+
+                for (i, row) in rows.iter().enumerate() {
+                    let field = row.get(index).unwrap_or_else(|| raise(ErrorCode::IndexOutOfBounds, &format!("Row {} of '{}' has no column {}", i + 1, path, index)));
+
+                    match parse_num(field) {
+                        Some(value) => values.push(value),
+                        None if i == 0 => {}, // header
+                        None => raise(ErrorCode::InvalidArgument, &format!("Not a number in row {} of '{}' ('{}')", i + 1, path, field))
+                    }
+                }
+
+                Value::List(values)
+            }
+        ),
+        external!( // to_base(n, base), digits above 9 are lowercase letters
+            "to_base",
+            2,
+            |args, ast| {
+                let n = args.first().unwrap().execute(ast);
+                let base = radix(&args.get(1).unwrap().execute(ast));
+
+                Value::String(n.number().to_str_radix(base))
+            }
+        ),
+        external!( // from_base(string, base)
+            "from_base",
+            2,
+            |args, ast| {
+                let s = args.first().unwrap().execute(ast);
+                let base = radix(&args.get(1).unwrap().execute(ast));
+
+                Value::from(BigInt::parse_bytes(s.string().trim().as_bytes(), base).unwrap_or_else(|| raise(ErrorCode::InvalidArgument, &format!("Not a number in base {} ('{}')", base, s))))
+            }
+        ),
+        external!( // input_prompt(prompt), asks again until a number is entered
+            impure "input_prompt",
+            1,
+            |args, ast| {
+                let prompt = args.first().unwrap().execute(ast);
+
+                ask_number(ast, prompt.string(), None, None)
+            }
+        ),
+        external!( // input_prompt(prompt, default), an empty line means default
+            impure "input_prompt",
+            2,
+            |args, ast| {
+                let prompt = args.first().unwrap().execute(ast);
+                let default = args.get(1).unwrap().execute(ast);
+
+                ask_number(ast, prompt.string(), None, Some(default))
+            }
+        ),
+        external!( // input_range(lo, hi), asks again until a number between lo and hi (inclusive) is entered
+            impure "input_range",
+            2,
+            |args, ast| {
+                let lo = args.first().unwrap().execute(ast).number().clone();
+                let hi = args.get(1).unwrap().execute(ast).number().clone();
+
+                ask_number(ast, &format!("Enter a number between {} and {}: ", lo, hi), Some((lo, hi)), None)
+            }
+        ),
+        external!( // input_range(lo, hi, default)
+            impure "input_range",
+            3,
+            |args, ast| {
+                let lo = args.first().unwrap().execute(ast).number().clone();
+                let hi = args.get(1).unwrap().execute(ast).number().clone();
+                let default = args.get(2).unwrap().execute(ast);
+
+                ask_number(ast, &format!("Enter a number between {} and {} [{}]: ", lo, hi, default), Some((lo, hi)), Some(default))
+            }
+        ),
+        external!( // clear_cache(), forgets the remembered results of all cached functions and returns how many there were
+            impure "clear_cache",
+            0,
+            |_, ast| {
+                Value::from(ast.clear_cache() as i64)
+            }
+        ),
+        external!( // clear_cache(function), only those of the function (given by name)
+            impure "clear_cache",
+            1,
+            |args, ast| {
+                let function = args.first().unwrap().execute(ast);
+
+                if !ast.function_names.contains_key(function.string()) {
+                    raise(ErrorCode::UndefinedFunction, &format!("Function not found ('{}')", function));
+                }
+
+                Value::from(ast.clear_function_cache(function.string()) as i64)
+            }
+        ),
+        external!( // sleep(millis)
+            impure "sleep",
+            1,
+            |args, ast| {
+                let millis = u64::try_from(args.first().unwrap().execute(ast).number()).unwrap_or_else(|_| raise(ErrorCode::InvalidArgument, "Invalid duration"));
+
+                if !ast.deterministic {
+                    ast.io.sleep(Duration::from_millis(millis));
+                }
+
+                Value::from(0)
+            }
+        ),
+        external!( // bench(function, n), calls the function without arguments n times and returns the average time in microseconds
+            impure "bench",
+            2,
+            |args, ast| {
+                let function = args.first().unwrap().execute(ast);
+                let n = args.get(1).unwrap().execute(ast);
+                let n = u32::try_from(n.number()).ok().filter(|n| *n > 0).unwrap_or_else(|| raise(ErrorCode::InvalidArgument, &format!("Benchmark needs a positive number of iterations ('{}')", n)));
+                let target = ast.target(function.string(), 0);
+                let start = Instant::now();
+
+                for _ in 0..n {
+                    ast.invoke_target(target, vec![]);
+                }
+
+                if ast.deterministic {
+                    return Value::from(0);
+                }
+
+                Value::from(BigInt::from(start.elapsed().as_micros() / n as u128))
+            }
+        ),
+        external!( // newline()
+            impure "newline",
+            0,
+            |_, ast| {
+                ast.write("\n");
+
+                Value::from(0)
+            }
+        ),
+        external!( // empty()
+            impure "empty",
+            0,
+            |_, ast| {
+                ast.write(" ");
+
+                Value::from(0)
+            }
+        ),
+        external!( // list(values...)
+            variadic "list",
+            1,
+            |args, ast| {
+                Value::List(args.iter().flat_map(|arg| match arg.execute(ast) {
+                    value @ (Value::Range(_, _, _) | Value::Set(_)) => value.elements(), // list(1..10) has the numbers, not the range
+                    value => vec![value]
+                }).collect())
+            }
+        ),
+        external!( // sort(list), the elements of a list or range from smallest to largest
+            "sort",
+            1,
+            |args, ast| {
+                let mut values = args.first().unwrap().execute(ast).elements();
+
+                values.sort_by(order);
+
+                Value::List(values)
+            }
+        ),
+        external!( // reverse(list), the elements of a list or range (or the characters of a string) back to front
+            "reverse",
+            1,
+            |args, ast| {
+                match args.first().unwrap().execute(ast) {
+                    Value::String(s) => Value::String(s.chars().rev().collect()),
+                    value => Value::List(value.elements().into_iter().rev().collect())
+                }
+            }
+        ),
+        external!( // contains(list, value), 1 if an element of the list or range is equal to the value (as with ==)
+            "contains",
+            2,
+            |args, ast| {
+                let values = args.first().unwrap().execute(ast);
+                let value = args.get(1).unwrap().execute(ast);
+
+                if let Value::Range(from, to, step) = &values {
+                    // ranges aren't expanded, only integers can be in them
+                    let n = match &value {
+                        Value::Small(_) | Value::Number(_) => value.number(),
+                        _ => return Value::bool(false)
+                    };
+                    let between = if step.sign() == Sign::Minus { &n <= from && &n >= to } else { &n >= from && &n <= to };
+
+                    Value::bool(between && ((&n - from) % step).sign() == Sign::NoSign)
+                } else {
+                    Value::bool(values.list().iter().any(|element| equal(element, &value, ast)))
+                }
+            }
+        ),
+        external!( // binary_search(list, value), the index of the value in a sorted list or range, none if it isn't in it
+            "binary_search",
+            2,
+            |args, ast| {
+                let values = args.first().unwrap().execute(ast).elements();
+                let value = args.get(1).unwrap().execute(ast);
+
+                match values.binary_search_by(|element| order(element, &value)) {
+                    Ok(index) => Value::from(BigInt::from(index)),
+                    Err(_) => Value::None
+                }
+            }
+        ),
+        external!( // tuple(values...), also written (a, b, c)
+            variadic "tuple",
+            1,
+            |args, ast| {
+                Value::Tuple(args.iter().map(|arg| arg.execute(ast)).collect())
+            }
+        ),
+        external!( // set(values...), also written {a, b, c}, lists, ranges and sets give all their numbers
+            variadic "set",
+            1,
+            |args, ast| {
+                let mut set = HashSet::<BigInt>::new();
+
+                for arg in args {
+                    match arg.execute(ast) {
+                        value @ (Value::List(_) | Value::Range(_, _, _) | Value::Set(_)) => set.extend(value.elements().iter().map(Value::number)),
+                        value => {
+                            set.insert(value.number());
+                        }
+                    }
+                }
+
+                Value::Set(Set(set))
+            }
+        ),
+        external!( // union(a, b), the numbers in either set
+            "union",
+            2,
+            |args, ast| {
+                let a = args.first().unwrap().execute(ast);
+                let b = args.get(1).unwrap().execute(ast);
+
+                Value::Set(Set(a.set().union(b.set()).cloned().collect()))
+            }
+        ),
+        external!( // intersect(a, b), the numbers in both sets
+            "intersect",
+            2,
+            |args, ast| {
+                let a = args.first().unwrap().execute(ast);
+                let b = args.get(1).unwrap().execute(ast);
+
+                Value::Set(Set(a.set().intersection(b.set()).cloned().collect()))
+            }
+        ),
+        external!( // diff(a, b), the numbers in a that aren't in b
+            "diff",
+            2,
+            |args, ast| {
+                let a = args.first().unwrap().execute(ast);
+                let b = args.get(1).unwrap().execute(ast);
+
+                Value::Set(Set(a.set().difference(b.set()).cloned().collect()))
+            }
+        ),
+        external!( // member(set, n), 1 if n is in the set
+            "member",
+            2,
+            |args, ast| {
+                let set = args.first().unwrap().execute(ast);
+                let n = args.get(1).unwrap().execute(ast);
+
+                Value::bool(matches!(n, Value::Small(_) | Value::Number(_)) && set.set().contains(&n.number()))
+            }
+        ),
+        external!( // first(tuple), also written t.0
+            "first",
+            1,
+            |args, ast| {
+                position(&args.first().unwrap().execute(ast), 0)
+            }
+        ),
+        external!( // second(tuple), also written t.1
+            "second",
+            1,
+            |args, ast| {
+                position(&args.first().unwrap().execute(ast), 1)
+            }
+        ),
+        external!( // divmod(a, b), the tuple (a // b, a mod b) rounding down, the remainder has the sign of b
+            "divmod",
+            2,
+            |args, ast| {
+                let a = args.first().unwrap().execute(ast).number();
+                let b = args.get(1).unwrap().execute(ast).number();
+
+                if b == BigInt::from(0) {
+                    raise(ErrorCode::InvalidArgument, "Division by zero");
+                }
+
+                let q = floor_div(&a, &b);
+                let r = &a - &q * &b;
+
+                Value::Tuple(vec![Value::from(q), Value::from(r)])
+            }
+        ),
+        // the functions are given by name and looked up once, calling them can have effects
+        external!( // map(function, list), the function applied to every element of a list or range
+            impure "map",
+            2,
+            |args, ast| {
+                let function = args.first().unwrap().execute(ast);
+                let values = args.get(1).unwrap().execute(ast).elements();
+                let target = ast.target(function.string(), 1);
+
+                Value::List(values.into_iter().map(|value| ast.invoke_target(target, vec![RuntimeExpression::value(value)])).collect())
+            }
+        ),
+        external!( // filter(function, list), the elements the function returns 1 for
+            impure "filter",
+            2,
+            |args, ast| {
+                let function = args.first().unwrap().execute(ast);
+                let values = args.get(1).unwrap().execute(ast).elements();
+                let target = ast.target(function.string(), 1);
+
+                Value::List(values.into_iter().filter(|value| ast.invoke_target(target, vec![RuntimeExpression::value(value.clone())]).is_true()).collect())
+            }
+        ),
+        external!( // find(function, list), the first element the function returns 1 for, none if there isn't one
+            impure "find",
+            2,
+            |args, ast| {
+                let function = args.first().unwrap().execute(ast);
+                let values = args.get(1).unwrap().execute(ast).elements();
+                let target = ast.target(function.string(), 1);
+
+                values.into_iter().find(|value| ast.invoke_target(target, vec![RuntimeExpression::value(value.clone())]).is_true()).unwrap_or(Value::None)
+            }
+        ),
+        external!( // reduce(function, initial, list), function(function(initial, first), second) and so on
+            impure "reduce",
+            3,
+            |args, ast| {
+                let function = args.first().unwrap().execute(ast);
+                let initial = args.get(1).unwrap().execute(ast);
+                let values = args.get(2).unwrap().execute(ast).elements();
+                let target = ast.target(function.string(), 2);
+
+                values.into_iter().fold(initial, |accumulated, value| ast.invoke_target(target, vec![RuntimeExpression::value(accumulated), RuntimeExpression::value(value)]))
+            }
+        ),
+        external!( // range(from, to), the numbers from from to to (inclusive), also written from..to
+            "range",
+            2,
+            |args, ast| {
+                let from = args.first().unwrap().execute(ast);
+                let to = args.get(1).unwrap().execute(ast);
+
+                Value::Range(from.number(), to.number(), BigInt::from(1))
+            }
+        ),
+        external!( // range(from, to, step), from..to step step, counts down with a negative step
+            "range",
+            3,
+            |args, ast| {
+                let from = args.first().unwrap().execute(ast);
+                let to = args.get(1).unwrap().execute(ast);
+                let step = args.get(2).unwrap().execute(ast).number();
+
+                if step.sign() == Sign::NoSign {
+                    raise(ErrorCode::InvalidArgument, "Step can't be 0");
+                }
+
+                Value::Range(from.number(), to.number(), step)
+            }
+        ),
+        external!( // sum(list or range)
+            "sum",
+            1,
+            |args, ast| {
+                let values = args.first().unwrap().execute(ast).elements();
+                let numeric = ast.numeric.clone();
+
+                values.iter().fold(Value::from(0), |sum, value| numeric.math(&MathType::Add, &sum, value))
+            }
+        ),
+        external!( // len(list, tuple, range, set or string), strings are counted in characters
+            "len",
+            1,
+            |args, ast| {
+                let value = args.first().unwrap().execute(ast);
+
+                Value::from(BigInt::from(match &value {
+                    Value::String(s) => s.chars().count(),
+                    Value::Range(_, _, _) => value.elements().len(),
+                    Value::Tuple(values) => values.len(),
+                    Value::Set(set) => set.0.len(),
+                    _ => value.list().len()
+                }))
+            }
+        ),
+        external!( // concat(values...), the values joined as text
+            variadic "concat",
+            1,
+            |args, ast| {
+                Value::String(args.iter().map(|arg| arg.execute(ast).to_string()).collect())
+            }
+        ),
+        external!( // substr(string, start, length), in characters from 0
+            "substr",
+            3,
+            |args, ast| {
+                let s = args.first().unwrap().execute(ast);
+                let start = args.get(1).unwrap().execute(ast);
+                let length = args.get(2).unwrap().execute(ast);
+                let chars = s.string().chars().collect::<Vec<char>>();
+                let range = usize::try_from(start.number()).ok().zip(usize::try_from(length.number()).ok()).map(|(start, length)| start..start + length).filter(|r| r.end <= chars.len());
+
+                match range {
+                    Some(range) => Value::String(chars[range].iter().collect()),
+                    None => raise(ErrorCode::IndexOutOfBounds, &format!("Substring out of bounds ({} characters from {} for length {})", length, start, chars.len()))
+                }
+            }
+        ),
+        external!( // repeat(string, n)
+            "repeat",
+            2,
+            |args, ast| {
+                let s = args.first().unwrap().execute(ast);
+                let n = args.get(1).unwrap().execute(ast);
+
+                Value::String(s.string().repeat(usize::try_from(n.number()).unwrap_or_else(|_| raise(ErrorCode::InvalidArgument, &format!("Can't repeat a string {} times", n)))))
+            }
+        ),
+        external!( // pad_left(value, width), padded with spaces
+            "pad_left",
+            2,
+            |args, ast| {
+                let value = args.first().unwrap().execute(ast);
+                let width = args.get(1).unwrap().execute(ast);
+
+                pad_left(&value, &width, ' ')
+            }
+        ),
+        external!( // pad_left(value, width, fill), fill is a single character
+            "pad_left",
+            3,
+            |args, ast| {
+                let value = args.first().unwrap().execute(ast);
+                let width = args.get(1).unwrap().execute(ast);
+                let fill = args.get(2).unwrap().execute(ast);
+                let mut chars = fill.string().chars();
+
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => pad_left(&value, &width, c),
+                    _ => raise(ErrorCode::InvalidArgument, &format!("The fill has to be a single character ('{}')", fill))
+                }
+            }
+        ),
+        external!( // digits(n), the decimal digits of a non negative number, most significant first
+            "digits",
+            1,
+            |args, ast| {
+                let n = args.first().unwrap().execute(ast);
+
+                if n.number().sign() == Sign::Minus {
+                    raise(ErrorCode::InvalidArgument, &format!("Can't take the digits of a negative number ({})", n));
+                }
+
+                Value::List(n.number().to_radix_be(10).1.into_iter().map(|d| Value::from(i64::from(d))).collect())
+            }
+        ),
+        external!( // from_digits(list), inverse of digits
+            "from_digits",
+            1,
+            |args, ast| {
+                let list = args.first().unwrap().execute(ast);
+                let digits = list.list().iter().map(|d| u8::try_from(d.number()).ok().filter(|d| *d < 10)).collect::<Option<Vec<u8>>>().unwrap_or_else(|| raise(ErrorCode::InvalidArgument, &format!("Not a list of digits ({})", list)));
+
+                Value::from(BigInt::from_radix_be(Sign::Plus, &digits, 10).unwrap_or_default())
+            }
+        ),
+        external!( // get(list, index)
+            "get",
+            2,
+            |args, ast| {
+                let list = args.first().unwrap().execute(ast);
+                let index = args.get(1).unwrap().execute(ast);
+
+                usize::try_from(index.number()).ok().and_then(|i| list.list().get(i).cloned()).unwrap_or_else(|| raise(ErrorCode::IndexOutOfBounds, &format!("Index out of bounds ({} for length {})", index, list.list().len())))
+            }
+        )
+    ]
+}
+
+fn parse_num(s: &str) -> Option<Value> {
+    let s = s.trim().replace('_', "");
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.strip_prefix('+').unwrap_or(&s))
+    };
+    let (base, digits) = match digits.get(..2) {
+        Some("0x") | Some("0X") => (16, &digits[2..]),
+        Some("0o") | Some("0O") => (8, &digits[2..]),
+        Some("0b") | Some("0B") => (2, &digits[2..]),
+        _ => (10, digits)
+    };
+
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(base)) {
+        return None;
+    }
+
+    BigInt::parse_bytes(digits.as_bytes(), base).map(|n| Value::from(if negative { -n } else { n }))
+}
+
+fn write_csv<'a>(ast: &RuntimeAST, path: &str, rows: impl Iterator<Item = &'a Vec<Value>>) {
+    let field = |value: &Value| {
+        let s = value.to_string();
+
+        if s.contains([',', '"', '\n']) {
+            format!("\"{}\"", s.replace('"', "\"\""))
+        } else {
+            s
+        }
+    };
+    let csv = rows.map(|row| row.iter().map(field).collect::<Vec<String>>().join(",") + "\n").collect::<String>();
+
+    write_file(ast, path, csv.as_bytes());
+}
+
+fn read_file(ast: &RuntimeAST, path: &str) -> String {
+    String::from_utf8(read_bytes(ast, path)).unwrap_or_else(|_| raise(ErrorCode::InvalidArgument, &format!("Error while reading '{}' (not UTF-8 text)", path)))
+}
+
+fn read_bytes(ast: &RuntimeAST, path: &str) -> Vec<u8> {
+    ast.fs.read(path).unwrap_or_else(|e| raise(ErrorCode::InvalidArgument, &format!("Error while reading '{}' ({})", path, e)))
+}
+
+fn write_file(ast: &RuntimeAST, path: &str, content: &[u8]) {
+    ast.fs.write(path, content).unwrap_or_else(|e| raise(ErrorCode::InvalidArgument, &format!("Error while writing '{}' ({})", path, e)));
+}
+
+// fields can be quoted ("a, ""b""" is a, "b"), empty lines are skipped
+fn parse_csv(content: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::<Vec<String>>::new();
+    let mut row = Vec::<String>::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = content.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            },
+            '"' => quoted = !quoted,
+            ',' if !quoted => row.push(take(&mut field)),
+            '\n' if !quoted => {
+                row.push(take(&mut field));
+
+                if row.len() > 1 || !row[0].trim().is_empty() {
+                    rows.push(take(&mut row));
+                } else {
+                    row.clear();
+                }
+            },
+            '\r' if !quoted => {},
+            _ => field.push(c)
+        }
+    }
+
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+
+    rows
+}
+
+fn plot_svg(ast: &mut RuntimeAST, function: &Value, from: &BigInt, to: &BigInt, step: &BigInt, path: &Value) -> Value {
+    let points = sample(ast, function.string(), from, to, step).iter().map(|(x, y)| (float(x), float(&y.number()))).collect::<Vec<(f64, f64)>>();
+
+    if points.is_empty() {
+        raise(ErrorCode::InvalidArgument, &format!("Nothing to plot, {} is bigger than {}", from, to));
+    }
+
+    write_file(ast, path.string(), plot::svg(&format!("{}(x)", function), &points).as_bytes());
+
+    Value::from(BigInt::from(points.len()))
+}
+
+fn table(ast: &mut RuntimeAST, function: &Value, from: &BigInt, to: &BigInt, step: &BigInt) -> Value {
+    let rows = sample(ast, function.string(), from, to, step).into_iter().map(|(x, y)| (x.to_string(), y.to_string())).collect::<Vec<(String, String)>>();
+    let header = ("x".to_owned(), format!("{}(x)", function));
+    let (a, b) = rows.iter().chain(Some(&header)).fold((0, 0), |(a, b), (x, y)| (a.max(x.len()), b.max(y.len())));
+    let mut out = format!("{:>a$} | {:>b$}\n{}-+-{}\n", header.0, header.1, "-".repeat(a), "-".repeat(b), a = a, b = b);
+
+    for (x, y) in &rows {
+        out.push_str(&format!("{:>a$} | {:>b$}\n", x, y, a = a, b = b));
+    }
+
+    ast.write(&out);
+
+    Value::from(BigInt::from(rows.len()))
+}
+
+fn write_samples(ast: &mut RuntimeAST, path: &Value, function: &Value, from: &BigInt, to: &BigInt, step: &BigInt) -> Value {
+    let rows = sample(ast, function.string(), from, to, step).into_iter().map(|(x, y)| vec![Value::from(x), y]).collect::<Vec<Vec<Value>>>();
+    let header = vec![Value::String("x".to_owned()), Value::String(format!("{}(x)", function))];
+
+    write_csv(ast, path.string(), Some(header).iter().chain(rows.iter()));
+
+    Value::from(BigInt::from(rows.len()))
+}
+
+const PROGRESS_INTERVAL: u64 = 100; // ms between redraws of the progress bar
+
+const MAX_SAMPLES: i64 = 1000; // points per plot, larger ranges are sampled with a bigger step
+
+// the values of a one-parameter function at from, from + step, ... up to to
+fn sample(ast: &mut RuntimeAST, function: &str, from: &BigInt, to: &BigInt, step: &BigInt) -> Vec<(BigInt, Value)> {
+    if step.sign() != Sign::Plus {
+        raise(ErrorCode::InvalidArgument, &format!("Step has to be positive ('{}')", step));
+    }
+
+    let mut samples = Vec::<(BigInt, Value)>::new();
+    let mut x = from.clone();
+
+    while &x <= to {
+        let y = ast.invoke_function(function, vec![RuntimeExpression::value(Value::from(x.clone()))]);
+
+        samples.push((x.clone(), y));
+
+        x += step;
+    }
+
+    samples
+}
+
+// the same as ==, lists and strings are only equal to equal values
+fn equal(a: &Value, b: &Value, ast: &RuntimeAST) -> bool {
+    if a.is_numeric() && b.is_numeric() {
+        ast.numeric.math(&MathType::Equals, a, b).is_true()
+    } else {
+        a == b
+    }
+}
+
+fn position(tuple: &Value, i: usize) -> Value {
+    tuple.tuple().get(i).cloned().unwrap_or_else(|| raise(ErrorCode::IndexOutOfBounds, &format!("Index out of bounds ({} for length {})", i, tuple.tuple().len())))
+}
+
+fn float(n: &BigInt) -> f64 {
+    n.to_string().parse().unwrap()
+}
+
+fn modulus(m: &Value) -> BigInt {
+    if m.number().sign() != Sign::Plus {
+        raise(ErrorCode::InvalidArgument, &format!("Modulus has to be positive ('{}')", m));
+    }
+
+    m.number().clone()
+}
+
+fn shift(k: &Value) -> usize {
+    usize::try_from(k.number()).unwrap_or_else(|_| raise(ErrorCode::InvalidArgument, &format!("Shift has to be a non-negative number ('{}')", k)))
+}
+
+fn pad_left(value: &Value, width: &Value, fill: char) -> Value {
+    let s = value.to_string();
+    let width = usize::try_from(width.number()).unwrap_or_else(|_| raise(ErrorCode::InvalidArgument, &format!("Invalid width ('{}')", width)));
+    let missing = width.saturating_sub(s.chars().count());
+
+    Value::String(fill.to_string().repeat(missing) + &s)
+}
+
+fn radix(base: &Value) -> u32 {
+    u32::try_from(base.number()).ok().filter(|b| (2..=36).contains(b)).unwrap_or_else(|| raise(ErrorCode::InvalidArgument, &format!("Base has to be between 2 and 36 ('{}')", base)))
+}
+
+fn ask_number(ast: &mut RuntimeAST, prompt: &str, range: Option<(BigInt, BigInt)>, default: Option<Value>) -> Value {
+    loop {
+        ast.write(prompt);
+
+        let line = ast.io.read_line();
+
+        if line.is_empty() { // end of input, asking again wouldn't help
+            return default.unwrap_or_else(|| raise(ErrorCode::InvalidInput, "Input ended before a valid number was entered"));
+        }
+
+        let line = line.trim();
+
+        if let Some(default) = default.as_ref().filter(|_| line.is_empty()) {
+            return default.clone();
+        }
+
+        match line.parse::<BigInt>() {
+            Ok(n) if range.as_ref().is_none_or(|(lo, hi)| lo <= &n && &n <= hi) => return Value::from(n),
+            Ok(_) => {
+                let (lo, hi) = range.as_ref().unwrap();
+
+                ast.write(&format!("Please enter a number between {} and {}\n", lo, hi));
+            },
+            Err(_) => ast.write("Please enter a number\n")
+        }
+    }
+}
+
+// the argument as it was written at the call site (re-printed, so spacing may differ)
+fn source(arg: &RuntimeExpression) -> String {
+    steps::show(&arg.orig)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::eval_str;
+    use crate::error::ErrorCode;
+    use num_bigint::BigInt;
+
+    fn eval(source: &str) -> Result<BigInt, ErrorCode> {
+        eval_str(source).map_err(|e| e.code())
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(eval("3 ^ 10 + 1"), Ok(BigInt::from(59050)));
+        assert_eq!(eval("define sq(x) = x * x\nsq(3) + 1"), Ok(BigInt::from(10)));
+        assert_eq!(eval("let a = 2\nlet b = a * 3\na + b"), Ok(BigInt::from(8)));
+        assert_eq!(eval("1\n2\n3"), Ok(BigInt::from(3)));
+        assert_eq!(eval("2 ^ 100"), Ok(BigInt::from(2).pow(100)));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(eval("1 / 0"), Err(ErrorCode::InvalidArgument));
+        assert_eq!(eval("let a = 1"), Err(ErrorCode::InvalidArgument));
+        assert_eq!(eval("\"text\""), Err(ErrorCode::TypeMismatch));
+        assert_eq!(eval("f(1)"), Err(ErrorCode::UndefinedFunction));
+        assert_eq!(eval("(1 + 2"), Err(ErrorCode::UnbalancedParentheses));
+    }
+}
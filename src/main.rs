@@ -1,303 +1,623 @@
-use std::path::Path;
-use crate::lexer::{data, token, full_lex};
-use std::fs::read_to_string;
-use crate::parser::parse;
-use crate::interpreter::{interpret, runtime::ExternalRuntimeFunction};
-use std::panic::set_hook;
+use std::path::{Path, PathBuf};
+use math::{cache, default_externals, doc, dot, format, golden, graph, interpreter, logging, manifest, package, repl, testing};
+use math::lexer::{full_lex, full_lex_with_include_paths, LexerData, LexedToken};
+use std::fs::{read_to_string, metadata, write, File};
+use std::sync::{Arc, Mutex};
+use std::mem::take;
+use math::parser::parse_with;
+use math::ast::{AST, Expression};
+use math::interpreter::{runtime::{RuntimeAST, RuntimeExpression, ExternalRuntimeFunction}, profile::Profiler};
+use std::panic::{set_hook, catch_unwind};
 use std::env;
-use std::time::{SystemTime, UNIX_EPOCH, Duration};
-use std::io::{stdin, Write};
-use num_bigint::BigInt;
+use std::process;
+use std::time::{Duration, Instant};
+use math::error::{ErrorCode, MathError, raise};
+use math::manifest::Dependency;
+use math::io::{Input, Io, Recorder, StdIo, Streams};
 use std::thread;
-use std::io::stdout;
-
-pub mod ast;
-pub mod interpreter;
-pub mod lexer;
-pub mod parser;
-
-macro_rules! external {
-    ($name: expr, $parameters: expr, $invoke: expr) => {
-        ExternalRuntimeFunction::create(
-            $name,
-            $parameters,
-            $invoke
-        )
-    };
-}
+use std::io::{stdin, stdout, sink, BufReader};
+use log::info;
+use clap::{Args, ArgAction, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap::builder::NonEmptyStringValueParser;
+use clap::error::ErrorKind;
+use clap_complete::{generate, Shell};
 
 const DEV: bool = false;
 
+#[derive(Parser)]
+#[command(name = "math", version, about = "An integer-only functional programming language", after_help = "math <files...> is short for math run <files...>, math without a command starts the REPL")]
+struct Cli {
+    #[arg(short, long, action = ArgAction::Count, global = true, help = "Log what the interpreter does (-vv and -vvv log more)")]
+    verbose: u8,
+    #[command(subcommand)]
+    command: Option<Command>
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Run files, or the project of a math.toml")]
+    Run(RunArgs),
+    #[command(about = "Start the REPL")]
+    Repl,
+    #[command(about = "Lex and parse files without running them")]
+    Check {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        #[arg(long, help = "Also run the strict mode checks")]
+        strict: bool,
+        #[arg(long, default_value = "#", value_parser = NonEmptyStringValueParser::new())]
+        comment: String
+    },
+    #[command(about = "Format files in place")]
+    Fmt {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        #[arg(long, help = "Only list the files that aren't formatted (fails if there are any)")]
+        check: bool,
+        #[arg(long, default_value = "#", value_parser = NonEmptyStringValueParser::new())]
+        comment: String
+    },
+    #[command(about = "Run the test declarations of files, or the examples of a directory")]
+    Test {
+        #[arg(required_unless_present = "golden")]
+        files: Vec<PathBuf>,
+        #[arg(long, value_name = "DIRECTORY", conflicts_with = "files", help = "Run every example.math and compare its output with example.expected")]
+        golden: Option<PathBuf>,
+        #[arg(long, default_value = "#", value_parser = NonEmptyStringValueParser::new())]
+        comment: String
+    },
+    #[command(about = "Print the definitions and expressions a file is parsed into")]
+    Ast {
+        file: PathBuf,
+        #[arg(long, help = "As a Graphviz graph")]
+        dot: bool,
+        #[arg(long, default_value = "#", value_parser = NonEmptyStringValueParser::new())]
+        comment: String
+    },
+    #[command(about = "Print the tokens a file is lexed into")]
+    Tokens {
+        file: PathBuf,
+        #[arg(long, default_value = "#", value_parser = NonEmptyStringValueParser::new())]
+        comment: String
+    },
+    #[command(about = "Generate documentation from doc comments")]
+    Doc {
+        file: PathBuf,
+        #[arg(long, help = "HTML instead of Markdown")]
+        html: bool,
+        #[arg(long, default_value = "#", value_parser = NonEmptyStringValueParser::new())]
+        comment: String
+    },
+    #[command(about = "Explain an error code")]
+    Explain {
+        code: String
+    },
+    #[command(about = "Run a file again whenever it changes")]
+    Watch {
+        file: PathBuf
+    },
+    #[command(about = "Print the call graph of a file")]
+    Graph {
+        file: PathBuf,
+        #[arg(long, help = "As JSON instead of a Graphviz graph")]
+        json: bool,
+        #[arg(long, default_value = "#", value_parser = NonEmptyStringValueParser::new())]
+        comment: String
+    },
+    #[command(about = "Add a dependency to the math.toml in the current directory")]
+    Add {
+        name: String,
+        #[arg(long, conflicts_with = "path")]
+        git: Option<String>,
+        #[arg(long, requires = "git")]
+        rev: Option<String>,
+        #[arg(long, value_name = "DIRECTORY")]
+        path: Option<PathBuf>
+    },
+    #[command(about = "Print a completion script for a shell (e.g. math completions bash > /etc/bash_completion.d/math)")]
+    Completions {
+        shell: Shell
+    }
+}
+
+#[derive(Args)]
+struct RunArgs {
+    #[arg(help = "The last file is the main one, the others are libraries (a directory or math.toml runs that project)")]
+    files: Vec<PathBuf>,
+    #[arg(short, value_name = "SOURCE", allow_hyphen_values = true, help = "Source to run after the files")]
+    e: Vec<String>,
+    #[arg(long, help = "Print the AST as a Graphviz graph instead of running it")]
+    emit_dot: bool,
+    #[arg(long, conflicts_with = "result", help = "Profile the run and write the folded stacks next to the main file")]
+    profile: bool,
+    #[arg(long, help = "Only print the value of the last expression")]
+    result: bool,
+    #[arg(long, help = "Print the value of every loose expression")]
+    print: bool,
+    #[arg(long, help = "Reject redefinitions, shadowing parameters and functions using mutable globals (like #pragma strict)")]
+    strict: bool,
+    #[arg(long, help = "Cache pure functions as if they were defined with cache")]
+    auto_cache: bool,
+    #[arg(long, help = "Freeze the clock and skip sleeps, so that every run prints the same")]
+    deterministic: bool,
+    #[arg(long, conflicts_with_all = ["emit_dot", "profile", "result"], help = "Print how every loose expression is evaluated step by step (variables replaced, calls expanded, operations done one at a time)")]
+    explain: bool,
+    #[arg(long, value_enum, default_value = "text")]
+    output: Output,
+    #[arg(long, default_value = "#", value_parser = NonEmptyStringValueParser::new())]
+    comment: String,
+    #[arg(long, help = "Only compile the project into its cache")]
+    compile: bool,
+    #[arg(long, value_name = "FILE", help = "Read the input (e.g. of input()) from a file instead of stdin")]
+    stdin_from: Option<PathBuf>,
+    #[arg(long, value_name = "FILE", help = "Write every line of input that is read to a file, to replay it with --stdin-from")]
+    record_inputs: Option<PathBuf>
+}
+
+// what math run <file> does without flags
+impl Default for RunArgs {
+    fn default() -> Self {
+        RunArgs {
+            files: vec![],
+            e: vec![],
+            emit_dot: false,
+            profile: false,
+            result: false,
+            print: false,
+            strict: false,
+            auto_cache: false,
+            deterministic: false,
+            explain: false,
+            output: Output::Text,
+            comment: "#".to_owned(),
+            compile: false,
+            stdin_from: None,
+            record_inputs: None
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, PartialEq)]
+enum Output {
+    Text,
+    Json
+}
+
 fn main() {
     if DEV {
-        fake_main(Path::new("test.math"));
+        fake_main(&[Path::new("test.math")], &RunArgs::default());
     } else {
-        let mut args: Vec<String> = env::args().collect();
+        let cli = Cli::parse_from(arguments());
 
-        args.remove(0);
+        logging::init(cli.verbose as usize);
 
-        if args.len() != 1 {
-            println!("Usage: math <file>");
+        set_hook(Box::new(|info| { // "suppress" panics so that only their message will be shown
+            println!("{}", MathError::from_panic(info.payload()));
+        }));
 
-            return;
+        match cli.command {
+            None | Some(Command::Repl) => repl::repl(),
+            Some(Command::Run(args)) => run(args),
+            Some(Command::Check { files, strict, comment }) => check(&files, strict, &comment),
+            Some(Command::Fmt { files, check, comment }) => fmt(&files, check, &comment),
+            Some(Command::Test { files, golden, comment }) => test(&files, golden, &comment),
+            Some(Command::Ast { file, dot, comment }) => ast(&file, dot, &comment),
+            Some(Command::Tokens { file, comment }) => tokens(&file, &comment),
+            Some(Command::Doc { file, html, comment }) => doc(&file, html, &comment),
+            Some(Command::Explain { code }) => explain(&code),
+            Some(Command::Watch { file }) => watch(&file),
+            Some(Command::Graph { file, json, comment }) => graph(&file, json, &comment),
+            Some(Command::Add { name, git, rev, path }) => add(&name, Dependency { git, rev, path }),
+            Some(Command::Completions { shell }) => generate(shell, &mut Cli::command(), "math", &mut stdout())
         }
+    }
+}
 
-        let file = args.get(0).expect("uh");
-        let path = Path::new(file);
+// math <files...> is short for math run <files...>
+fn arguments() -> Vec<String> {
+    let mut args = env::args().collect::<Vec<String>>();
+    let command = Cli::command();
+    let first = args.iter().skip(1).find(|a| !(a.len() > 1 && a.starts_with('-') && a[1..].chars().all(|c| c == 'v')) && a.ne(&"--verbose"));
 
-        if !path.exists() {
-            println!("File not found");
+    if let Some(first) = first {
+        let known = command.get_subcommands().any(|c| c.get_name().eq(first)) || ["help", "-h", "--help", "-V", "--version"].contains(&first.as_str());
 
-            return;
+        if !known {
+            args.insert(1, "run".to_owned());
         }
+    }
 
-        set_hook(Box::new(|info| { // "suppress" panics so that only their message will be shown
-            let mut s = format!("{}", info);
+    args
+}
 
-            s = s.replace("panicked at '", "");
-            s = s[..s.rfind("', src\\").expect("Malformed string")].to_owned();
+fn existing_file(path: &Path) -> Option<&Path> {
+    if !path.exists() {
+        println!("File not found ('{}')", path.display());
 
-            println!("{}", s);
-        }));
+        return None;
+    }
 
-        fake_main(path);
+    Some(path)
+}
+
+fn existing_files(files: &[PathBuf]) -> Option<Vec<&Path>> {
+    files.iter().map(|f| existing_file(f)).collect()
+}
+
+fn doc(file: &Path, html: bool, comment: &str) {
+    if let Some(path) = existing_file(file) {
+        let ast = parse_file(path, comment);
+        let title = path.file_name().unwrap().to_str().unwrap();
+
+        print!("{}", if html {
+            doc::html(title, &ast)
+        } else {
+            doc::markdown(title, &ast)
+        });
     }
 }
 
-fn fake_main(file: &Path) {
-    let start = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_micros();
-    let data = data(vec![
-        token(
-            "LET",
-            "let",
-            false
-        ),
-        token(
-            "CONST",
-            "const",
-            false
-        ),
-        token(
-            "DEFINE",
-            "define",
-            false
-        ),
-        token(
-            "WHERE",
-            "where",
-            false
-        ),
-        token(
-            "EXTERNAL",
-            "external",
-            false
-        ),
-        token(
-            "CACHE",
-            "cache",
-            false
-        ),
-        token(
-            "COMMA",
-            ",",
-            false
-        ),
-        token(
-            "PIPE",
-            "|",
-            false
-        ),
-        token(
-            "OPEN_PARENTHESIS",
-            "(",
-            false
-        ),
-        token(
-            "CLOSE_PARENTHESIS",
-            ")",
-            false
-        ),
-        token(
-            "EQUALS",
-            "==",
-            false
-        ),
-        token(
-            "NOT_EQUALS",
-            "=!",
-            false
-        ),
-        token(
-            "BIGGER_OR_EQUALS",
-            ">=",
-            false
-        ),
-        token(
-            "BIGGER",
-            ">",
-            false
-        ),
-        token(
-            "SMALLER_OR_EQUALS",
-            "<=",
-            false
-        ),
-        token(
-            "SMALLER",
-            "<",
-            false
-        ),
-        token(
-            "ASSIGN",
-            "=",
-            false
-        ),
-        token(
-            "PLUS",
-            "+",
-            false
-        ),
-        token(
-            "MINUS",
-            "-",
-            false
-        ),
-        token(
-            "DIVIDE",
-            "/",
-            false
-        ),
-        token(
-            "MULTIPLY",
-            "*",
-            false
-        ),
-        token(
-            "POW",
-            "^",
-            false
-        ),
-        token(
-            "NUMBER",
-            "([0-9_.]+)",
-            true
-        ),
-        token(
-            "WHITESPACE",
-            "\\s+",
-            true
-        ),
-        token(
-            "IDENTIFIER",
-            "[a-zA-Z][A-Za-z0-9_]*(\\*|)",
-            true
-        )
-    ]);
-    let t = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_micros();
-    let content = read_to_string(file).expect("Error while reading file");
-    let r = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_micros();
-    let lex_result = full_lex(content.to_owned(), file.file_name().unwrap().to_str().unwrap().to_owned(), "#".to_owned(), data);
-    let l = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_micros();
-    let external_functions = vec![
-        external!( // println(output)
-            "println",
-            1,
-            |args, ast| {
-                println!("{}", args.get(0).unwrap().execute(ast));
-
-                BigInt::from(0)
-            }
-        ),
-        external!( // print(output)
-            "print",
-            1,
-            |args, ast| {
-                print!("{}", args.get(0).unwrap().execute(ast));
+fn run(args: RunArgs) {
+    let evals = args.e.iter().collect::<Vec<&String>>();
 
-                stdout().flush().unwrap(); // flush so it gets printed
+    if args.output == Output::Json && (args.emit_dot || args.profile) {
+        run_error("--output json can't be combined with --emit-dot or --profile");
+    }
 
-                BigInt::from(0)
-            }
-        ),
-        external!( // if(condition, true, false)
-            "if",
-            3,
-            |args, ast| {
-                return if args.get(0).unwrap().execute(ast) == BigInt::from(1) {
-                    args.get(1).unwrap().execute(ast)
-                } else {
-                    args.get(2).unwrap().execute(ast)
-                }
+    // without files math run runs the project in the current directory
+    let project_path = match args.files.as_slice() {
+        [] if evals.is_empty() => Some(Path::new(".")),
+        [path] if evals.is_empty() && (path.is_dir() || path.file_name().is_some_and(|n| n.eq(manifest::MANIFEST))) => Some(path.as_path()),
+        _ => None
+    };
+
+    match project_path {
+        Some(path) => project(path, &args),
+        None if args.compile => run_error("--compile needs a project (a directory or math.toml)"),
+        None => if let Some(paths) = existing_files(&args.files) {
+            if args.emit_dot {
+                print!("{}", dot::dot(&parse_files(&paths, &evals, &args.comment, args.strict)));
+            } else if args.profile {
+                profile(&paths, &args);
+            } else {
+                fake_main(&paths, &args);
             }
-        ),
-        external!( // input()
-            "input",
-            0,
-            |_, _| {
-                let mut input = String::new();
+        }
+    }
+}
+
+// combinations of flags clap can't check, reported like the ones it can
+fn run_error(message: &str) -> ! {
+    let mut command = Cli::command();
+
+    command.build();
+    command.find_subcommand_mut("run").unwrap().error(ErrorKind::ArgumentConflict, message).exit()
+}
 
-                stdin().read_line(&mut input).ok().expect("Failed to read line");
+// runs the loose expressions of files and projects alike, setup is for what only one of them configures (e.g. the manifest's limits),
+// with --result only the value of the last expression is printed, so that shell scripts can capture it
+fn execute(mut ast: AST, externals: Vec<ExternalRuntimeFunction>, args: &RunArgs, setup: impl FnOnce(&mut RuntimeAST)) {
+    let json = args.output == Output::Json;
+    let loose_expressions = take(&mut ast.loose_expressions);
+    let locations = take(&mut ast.locations);
+    let output: math::io::Output = if args.result {
+        Arc::new(Mutex::new(sink()))
+    } else {
+        Arc::new(Mutex::new(stdout()))
+    };
+    let mut runtime = RuntimeAST::create(ast, externals);
 
-                let result = input.replace("\r\n", "").replace("\n", "").parse::<isize>();
+    runtime.io = io(args, output);
+    runtime.echo = args.print;
+    runtime.json = json;
+    runtime.styled &= !json && !args.result;
+    runtime.deterministic = args.deterministic;
+    runtime.explain = args.explain;
 
-                if result.is_err() {
-                    panic!("Input must be a number");
-                }
+    setup(&mut runtime);
 
-                BigInt::from(result.unwrap())
-            }
-        ),
-        external!( // sleep(millis)
-            "sleep",
-            1,
-            |args, ast| {
-                thread::sleep(Duration::from_millis(*args.get(0).unwrap().execute(ast).to_u64_digits().1.get(0).unwrap()));
-
-                BigInt::from(0)
-            }
-        ),
-        external!( // newline()
-            "newline",
-            0,
-            |_, _| {
-                println!();
-
-                BigInt::from(0)
+    if args.auto_cache {
+        cache_pure(&mut runtime);
+    }
+
+    let last = interpreter::run_at(&mut runtime, &loose_expressions, &locations);
+
+    if let Some(value) = last {
+        if json {
+            if args.result { // everything else was written to the sink
+                runtime.io = Arc::new(StdIo);
             }
-        ),
-        external!( // empty()
-            "empty",
-            0,
-            |_, _| {
-                print!(" ");
 
-                stdout().flush().unwrap(); // flush so it gets printed
+            runtime.record("result", &value);
+        } else if args.result {
+            println!("{}", value);
+        }
+    }
+}
+
+// stdin unless --stdin-from says otherwise, with --record-inputs every line that is read is also written to a file
+fn io(args: &RunArgs, output: math::io::Output) -> Arc<dyn Io> {
+    let input: Input = match &args.stdin_from {
+        Some(path) => Arc::new(Mutex::new(BufReader::new(File::open(path).unwrap_or_else(|e| raise(ErrorCode::InvalidArgument, &format!("Error while reading '{}' ({})", path.display(), e)))))),
+        None => Arc::new(Mutex::new(BufReader::new(stdin())))
+    };
+    let io = Arc::new(Streams::new(input, output));
+
+    match &args.record_inputs {
+        Some(path) => Arc::new(Recorder::new(io, Arc::new(Mutex::new(File::create(path).unwrap_or_else(|e| raise(ErrorCode::InvalidArgument, &format!("Error while writing '{}' ({})", path.display(), e))))))),
+        None => io
+    }
+}
+
+// --auto-cache, pure functions are remembered as if they were defined with cache
+fn cache_pure(runtime: &mut RuntimeAST) {
+    let cached = runtime.auto_cache();
+
+    info!("Caching {} pure function(s) automatically", cached);
+}
+
+fn profile(files: &[&Path], args: &RunArgs) {
+    let file = files.last().copied().unwrap_or_else(|| Path::new("eval")); // the last file is the main one, the others are libraries
+    let ast = parse_files(files, &args.e.iter().collect::<Vec<&String>>(), &args.comment, args.strict);
+    let profiler = Arc::new(Mutex::new(Profiler::new()));
+    let folded = file.with_extension("folded");
+
+    execute(ast, default_externals(), args, |runtime| runtime.profiler = Some(profiler.clone()));
+
+    let profiler = profiler.lock().unwrap();
+
+    write(&folded, profiler.folded()).expect("Error while writing profile");
+
+    println!();
+    print!("{}", profiler.summary());
+    println!("Folded stacks written to {} (render them with inferno-flamegraph or flamegraph.pl)", folded.display());
+}
+
+fn explain(code: &str) {
+    match ErrorCode::of(code) {
+        Some(code) => print!("{}", code.explain()),
+        None => println!("Unknown error code ('{}'), known codes are {}", code, ErrorCode::entries().iter().map(|c| c.code()).collect::<Vec<&str>>().join(", "))
+    }
+}
+
+// only parses, errors are printed by the panic hook
+fn check(files: &[PathBuf], strict: bool, comment: &str) {
+    if let Some(paths) = existing_files(files) {
+        parse_files(&paths, &[], comment, strict);
+
+        println!("No errors in {} file(s)", paths.len());
+    }
+}
+
+fn fmt(files: &[PathBuf], check: bool, comment: &str) {
+    let data = LexerData::default();
+    let mut unformatted = 0;
+
+    for path in existing_files(files).unwrap_or_default() {
+        let source = read_to_string(path).expect("Error while reading file");
+        let formatted = format::format(&source, comment, &data);
+
+        if formatted.eq(&source) {
+            continue;
+        }
+
+        unformatted += 1;
+
+        if check {
+            println!("{} isn't formatted", path.display());
+        } else {
+            write(path, formatted).expect("Error while writing file");
+        }
+    }
+
+    if check && unformatted > 0 {
+        process::exit(1);
+    }
+}
+
+// the test declarations of the files, or with --golden the examples of a directory
+fn test(files: &[PathBuf], golden: Option<PathBuf>, comment: &str) {
+    if let Some(dir) = golden {
+        if !dir.is_dir() {
+            println!("Directory not found ('{}')", dir.display());
+
+            return;
+        }
+
+        if !golden::golden(&dir) {
+            process::exit(1);
+        }
+
+        return;
+    }
+
+    if let Some(files) = existing_files(files) {
+        if !testing::run(parse_files(&files, &[], comment, false)) {
+            process::exit(1);
+        }
+    }
+}
+
+// one line per definition and loose expression, operations are parenthesized
+fn ast(file: &Path, dot: bool, comment: &str) {
+    if let Some(path) = existing_file(file) {
+        let ast = parse_file(path, comment);
+
+        if dot {
+            print!("{}", dot::dot(&ast));
+
+            return;
+        }
+
+        for f in ast.functions.iter().filter(|f| Expression::External != f.definition) {
+            println!("define {}{}({}{}) = {}", if f.cached { "cache " } else { "" }, f.name, f.parameters.join(", "), if f.variadic { "..." } else { "" }, RuntimeExpression::expr_to_string(&f.definition));
+        }
 
-                BigInt::from(0)
+        for v in &ast.variables {
+            println!("{} {} = {}", if v.constant { "const" } else { "let" }, v.name, RuntimeExpression::expr_to_string(&v.definition));
+        }
+
+        for expr in &ast.loose_expressions {
+            println!("{}", RuntimeExpression::expr_to_string(expr));
+        }
+    }
+}
+
+fn tokens(file: &Path, comment: &str) {
+    if let Some(path) = existing_file(file) {
+        let tokens = full_lex(read_to_string(path).expect("Error while reading file"), path.to_str().unwrap().to_owned(), comment.to_owned(), &LexerData::default());
+
+        for t in tokens.iter().filter(|t| t.token_type().id().ne("WHITESPACE")) {
+            println!("{}:{} {} {:?}", t.line() + 1, t.index() + 1, t.token_type().id(), t.content());
+        }
+    }
+}
+
+fn watch(file: &Path) {
+    if let Some(path) = existing_file(file) {
+        let mut last_modified = None;
+
+        loop {
+            let modified = metadata(path).and_then(|m| m.modified()).ok();
+
+            if modified.is_some() && modified != last_modified {
+                last_modified = modified;
+
+                print!("\x1B[2J\x1B[1;1H"); // clear the screen
+
+                let _ = catch_unwind(|| fake_main(&[path], &RunArgs::default())); // diagnostics are printed by the panic hook
+
+                println!("\nWatching {} for changes...", path.display());
             }
-        )
-    ];
-    let parse_result = parse(lex_result, external_functions.clone());
-    let p = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_micros();
-
-    interpret(parse_result, external_functions);
-
-    let i = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_micros();
-    let token_t = t - start;
-    let read_t = r - t;
-    let lex_t = l - r;
-    let parse_t = p - l;
-    let interpret_t = i - p;
-    let total_t = i - start;
-    let t_stuff = |i: u128| -> String {
-        let m = i / 1000;
-
-        return if m != 0 {
-            format!("{}ms", m)
+
+            thread::sleep(Duration::from_millis(250));
+        }
+    }
+}
+
+fn graph(file: &Path, json: bool, comment: &str) {
+    if let Some(path) = existing_file(file) {
+        let graph = graph::call_graph(&parse_file(path, comment));
+
+        print!("{}", if json {
+            graph.json()
         } else {
-            format!("{}µs", i)
+            graph.dot()
+        });
+    }
+}
+
+fn project(path: &Path, args: &RunArgs) {
+    if args.emit_dot || args.profile {
+        run_error("--emit-dot and --profile only work with files, not with a project");
+    }
+
+    let compile = args.compile;
+    let manifest = manifest::load(path);
+    let externals = default_externals();
+
+    manifest.check_externals(&externals);
+
+    let entry = manifest.entry();
+    let compiled = cache::path(&entry);
+    let strict = manifest.package.strict || args.strict;
+    let options = format!("comment={} strict={} keywords={:?} aliases={:?}", manifest.package.comment, strict, manifest.keywords, manifest.aliases);
+    let cached = if compile { None } else { cache::load(&compiled, &options) };
+    let ast = match cached {
+        Some(ast) => {
+            info!("Loaded {}", compiled.display());
+
+            ast
+        },
+        None => {
+            let mut include_paths = manifest.include_paths();
+
+            include_paths.push(package::resolve(&manifest));
+
+            let tokens = full_lex_with_include_paths(read_to_string(&entry).expect("Error while reading file"), entry.to_str().unwrap().to_owned(), manifest.package.comment.clone(), &manifest.lexer_data(), &include_paths);
+            let sources = cache::sources(&tokens);
+            let ast = parse_with(tokens, externals.clone(), strict);
+
+            if compile {
+                cache::save(&compiled, &ast, &sources, &options);
+
+                println!("Compiled {} source file(s) to {}", sources.len(), compiled.display());
+
+                return;
+            }
+
+            ast
         }
     };
 
-    println!("Finished in {} (T: {}, R: {} L: {} P: {} I: {})", t_stuff(total_t), t_stuff(token_t), t_stuff(read_t), t_stuff(lex_t), t_stuff(parse_t), t_stuff(interpret_t));
+    execute(ast, externals, args, |runtime| {
+        runtime.limits = manifest.limits();
+        runtime.numeric = manifest.numeric();
+        runtime.division = manifest.division();
+    });
+}
+
+// adds a dependency to the manifest in the current directory, without --git or --path the name is looked up in the registry
+fn add(name: &str, mut dependency: Dependency) {
+    let file = Path::new(manifest::MANIFEST);
+    let manifest = manifest::load(file);
+
+    if dependency.git.is_none() && dependency.path.is_none() {
+        dependency = package::lookup(name, &manifest.root);
+    }
+
+    package::add(manifest, file, name, dependency);
+
+    println!("Added {}", name);
+}
+
+fn parse_file(file: &Path, comment: &str) -> AST {
+    parse_files(&[file], &[], comment, false)
+}
+
+// the files are lexed separately (so diagnostics name the right file) and parsed as one program
+fn parse_files(files: &[&Path], evals: &[&String], comment: &str, strict: bool) -> AST {
+    let data = LexerData::default();
+    let mut tokens = files.iter().flat_map(|file| full_lex(read_to_string(file).expect("Error while reading file"), file.to_str().unwrap().to_owned(), comment.to_owned(), &data)).collect::<Vec<LexedToken>>();
+
+    tokens.extend(lex_evals(evals, comment, &data));
+
+    parse_with(tokens, default_externals(), strict)
+}
+
+// sources given with -e come after the files
+fn lex_evals(evals: &[&String], comment: &str, data: &LexerData) -> Vec<LexedToken> {
+    evals.iter().enumerate().flat_map(|(i, source)| full_lex(source.to_string(), format!("<eval:{}>", i + 1), comment.to_owned(), data)).collect()
+}
+
+// how long each phase took is logged with -v
+fn fake_main(files: &[&Path], args: &RunArgs) {
+    let evals = args.e.iter().collect::<Vec<&String>>();
+    let comment = args.comment.as_str();
+    let start = Instant::now();
+    let contents = files.iter().map(|file| read_to_string(file).expect("Error while reading file")).collect::<Vec<String>>();
+
+    info!("Read {} file(s) in {}", files.len(), logging::duration(start.elapsed()));
+
+    let phase = Instant::now();
+    let data = LexerData::default();
+    let mut lex_result = files.iter().zip(contents).flat_map(|(file, content)| full_lex(content, file.to_str().unwrap().to_owned(), comment.to_owned(), &data)).collect::<Vec<LexedToken>>();
+
+    lex_result.extend(lex_evals(&evals, comment, &data));
+
+    info!("Lexed in {}", logging::duration(phase.elapsed()));
+
+    let phase = Instant::now();
+    let external_functions = default_externals();
+    let parse_result = parse_with(lex_result, external_functions.clone(), args.strict);
+
+    info!("Parsed in {}", logging::duration(phase.elapsed()));
+
+    let phase = Instant::now();
+
+    execute(parse_result, external_functions, args, |_| {});
+
+    info!("Interpreted in {}", logging::duration(phase.elapsed()));
+
+    info!("Finished in {}", logging::duration(start.elapsed()));
 }
@@ -0,0 +1,254 @@
+use crate::error::{ErrorCode, raise};
+use crate::interpreter::runtime::{ExternalRuntimeFunction, Limits};
+use crate::lexer::LexerData;
+use crate::numeric::{self, Division, Numeric};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs::read_to_string;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub const MANIFEST: &str = "math.toml";
+
+// [package]
+// name = "primes"
+// entry = "src/main.math"
+// include = ["lib"]          # searched for #include files that aren't next to the including file
+// numeric = "integer"        # or checked, rational, decimal:4 (places), modular:1000000007
+// division = "truncate"      # what / does with a remainder: truncate (towards zero), floor (like //) or exact (an error)
+// comment = "#"
+// externals = ["println"]    # externals the program needs, checked before it runs
+// strict = true
+//
+// [limits]
+// max_depth = 10000          # nested function calls
+// timeout_ms = 5000
+//
+// [aliases]                  # other spellings of operators and keywords
+// "**" = "^"
+//
+// [keywords]                 # keywords spelled differently, the default spelling becomes an identifier
+// let = "sei"
+// for = "für"
+//
+// [dependencies]             # fetched into .math/deps, included with #include "sieve/sieve.math"
+// sieve = { git = "https://github.com/someone/sieve.git", rev = "v1.0" }
+// util = { path = "../util" }
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub package: Package,
+    #[serde(default)]
+    pub limits: ManifestLimits,
+    #[serde(default)]
+    pub aliases: BTreeMap<String, String>,
+    #[serde(default)]
+    pub keywords: BTreeMap<String, String>,
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, Dependency>,
+    #[serde(skip)]
+    pub root: PathBuf // directory of the manifest, the paths in it are relative to it
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Package {
+    pub name: String,
+    pub entry: PathBuf,
+    #[serde(default)]
+    pub include: Vec<PathBuf>,
+    #[serde(default = "integer")]
+    pub numeric: String,
+    #[serde(default = "truncate")]
+    pub division: String,
+    #[serde(default = "hash")]
+    pub comment: String,
+    #[serde(default)]
+    pub externals: Vec<String>,
+    #[serde(default)]
+    pub strict: bool
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestLimits {
+    pub max_depth: Option<usize>,
+    pub timeout_ms: Option<u64>
+}
+
+// exactly one of git and path, rev is a branch, tag or commit of the git repository
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Dependency {
+    pub git: Option<String>,
+    pub rev: Option<String>,
+    pub path: Option<PathBuf>
+}
+
+fn integer() -> String {
+    "integer".to_owned()
+}
+
+fn truncate() -> String {
+    "truncate".to_owned()
+}
+
+fn hash() -> String {
+    "#".to_owned()
+}
+
+// path is either the manifest itself or the directory containing it
+pub fn load(path: &Path) -> Manifest {
+    let file = if path.is_dir() {
+        path.join(MANIFEST)
+    } else {
+        path.to_path_buf()
+    };
+    let content = read_to_string(&file).unwrap_or_else(|_| raise(ErrorCode::InvalidManifest, &format!("Manifest not found ('{}')", file.display())));
+    let mut manifest = toml::from_str::<Manifest>(&content).unwrap_or_else(|e| raise(ErrorCode::InvalidManifest, &format!("Invalid manifest ('{}'): {}", file.display(), e.message())));
+
+    manifest.root = file.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+
+    if numeric::backend(&manifest.package.numeric).is_none() {
+        raise(ErrorCode::InvalidManifest, &format!("Unsupported numeric mode ('{}'), expected one of {}", manifest.package.numeric, numeric::MODES));
+    }
+
+    if numeric::division(&manifest.package.division).is_none() {
+        raise(ErrorCode::InvalidManifest, &format!("Unsupported division ('{}'), expected one of {}", manifest.package.division, numeric::DIVISIONS));
+    }
+
+    // only integer quotients are rounded, floor would turn 1/3 into 0 with rational and decimal and modular has no remainders
+    if manifest.package.division.ne("truncate") && !["integer", "checked"].contains(&manifest.package.numeric.as_str()) {
+        raise(ErrorCode::InvalidManifest, &format!("division = \"{}\" only works with numeric = \"integer\" or \"checked\" (not '{}')", manifest.package.division, manifest.package.numeric));
+    }
+
+    if manifest.package.comment.is_empty() {
+        raise(ErrorCode::InvalidManifest, "The comment marker can't be empty");
+    }
+
+    if !manifest.entry().is_file() {
+        raise(ErrorCode::InvalidManifest, &format!("Entry file not found ('{}')", manifest.entry().display()));
+    }
+
+    if let Some(include) = manifest.include_paths().into_iter().find(|p| !p.is_dir()) {
+        raise(ErrorCode::InvalidManifest, &format!("Include path not found ('{}')", include.display()));
+    }
+
+    manifest.lexer_data(); // checks the keywords and aliases
+
+    if let Some((name, _)) = manifest.dependencies.iter().find(|(_, d)| d.git.is_some() == d.path.is_some() || (d.rev.is_some() && d.git.is_none())) {
+        raise(ErrorCode::InvalidManifest, &format!("Dependency '{}' needs either git (and optionally rev) or path", name));
+    }
+
+    if let Some(name) = manifest.dependencies.keys().find(|name| !dependency_name(name)) {
+        raise(ErrorCode::InvalidManifest, &format!("Invalid dependency name ('{}'), names can't be paths", name));
+    }
+
+    manifest
+}
+
+// dependencies are fetched into a directory named after them, so a name has to stay inside the cache (no .., / or \)
+pub fn dependency_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+
+    !name.contains(['/', '\\']) && matches!((components.next(), components.next()), (Some(Component::Normal(_)), None))
+}
+
+impl Manifest {
+    pub fn entry(&self) -> PathBuf {
+        self.root.join(&self.package.entry)
+    }
+
+    pub fn include_paths(&self) -> Vec<PathBuf> {
+        self.package.include.iter().map(|p| self.root.join(p)).collect()
+    }
+
+    // the timeout starts counting when this is called
+    pub fn limits(&self) -> Limits {
+        Limits {
+            max_depth: self.limits.max_depth,
+            deadline: self.limits.timeout_ms.map(|ms| Instant::now() + Duration::from_millis(ms))
+        }
+    }
+
+    // the default tokens with the keywords and aliases
+    pub fn lexer_data(&self) -> LexerData {
+        let mut data = LexerData::default();
+
+        for (keyword, spelling) in &self.keywords {
+            if !data.keyword(keyword, spelling) {
+                raise(ErrorCode::InvalidManifest, &format!("Invalid keyword ('{}' = '{}'), keywords need the default spelling of a keyword and a word to spell it with", keyword, spelling));
+            }
+        }
+
+        for (spelling, operator) in &self.aliases {
+            if !data.alias(spelling, operator) {
+                raise(ErrorCode::InvalidManifest, &format!("Invalid alias ('{}' = '{}'), aliases need a spelling and the spelling of an existing operator or keyword", spelling, operator));
+            }
+        }
+
+        data
+    }
+
+    pub fn numeric(&self) -> Arc<dyn Numeric> {
+        numeric::backend(&self.package.numeric).unwrap()
+    }
+
+    pub fn division(&self) -> Division {
+        numeric::division(&self.package.division).unwrap()
+    }
+
+    pub fn check_externals(&self, externals: &[ExternalRuntimeFunction]) {
+        let missing = self.package.externals.iter().filter(|name| !externals.iter().any(|e| e.name.eq(*name))).map(String::as_str).collect::<Vec<&str>>();
+
+        if !missing.is_empty() {
+            raise(ErrorCode::InvalidManifest, &format!("Required externals aren't available ({})", missing.join(", ")));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{dependency_name, load};
+    use crate::error::{ErrorCode, catch};
+    use std::env::temp_dir;
+    use std::fs::{create_dir_all, write};
+
+    // the numeric and division of a math.toml in a directory of its own
+    fn check(name: &str, numeric: &str, division: &str) -> Result<(), ErrorCode> {
+        let dir = temp_dir().join(format!("math-manifest-{}-{}", name, std::process::id()));
+
+        create_dir_all(&dir).unwrap();
+        write(dir.join("math.toml"), format!("[package]\nname = \"{}\"\nentry = \"main.math\"\nnumeric = \"{}\"\ndivision = \"{}\"\n", name, numeric, division)).unwrap();
+        write(dir.join("main.math"), "").unwrap();
+
+        catch(|| {
+            load(&dir);
+        }).map_err(|e| e.code())
+    }
+
+    #[test]
+    fn division() {
+        assert_eq!(check("integer-floor", "integer", "floor"), Ok(()));
+        assert_eq!(check("checked-exact", "checked", "exact"), Ok(()));
+        assert_eq!(check("rational-truncate", "rational", "truncate"), Ok(()));
+        assert_eq!(check("rational-floor", "rational", "floor"), Err(ErrorCode::InvalidManifest));
+        assert_eq!(check("decimal-exact", "decimal:4", "exact"), Err(ErrorCode::InvalidManifest));
+        assert_eq!(check("modular-floor", "modular:7", "floor"), Err(ErrorCode::InvalidManifest));
+        assert_eq!(check("integer-round", "integer", "round"), Err(ErrorCode::InvalidManifest));
+    }
+
+    #[test]
+    fn dependency_names() {
+        assert!(dependency_name("sieve"));
+        assert!(dependency_name("number-theory_2"));
+        assert!(!dependency_name(""));
+        assert!(!dependency_name("."));
+        assert!(!dependency_name(".."));
+        assert!(!dependency_name("../../../victim"));
+        assert!(!dependency_name("lib/sieve"));
+        assert!(!dependency_name("lib\\sieve"));
+        assert!(!dependency_name("/tmp/victim"));
+    }
+}
@@ -0,0 +1,357 @@
+use crate::ast::MathType;
+use crate::error::{ErrorCode, raise};
+use crate::value::Value;
+use num_bigint::{BigInt, Sign};
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::ops::{Add, Div, Mul, Sub};
+use std::sync::Arc;
+
+pub const MODES: &str = "integer, checked, rational, decimal[:places], modular:<modulus>";
+
+pub const DIVISIONS: &str = "truncate, floor, exact";
+
+const DECIMAL_PLACES: u32 = 10;
+
+// how the operators compute, chosen when the runtime is created (numeric in the manifest)
+pub trait Numeric: Send + Sync {
+    // comparisons return 0 or 1
+    fn math(&self, math: &MathType, a: &Value, b: &Value) -> Value;
+}
+
+// arbitrary precision integers, numbers that fit in an i64 don't allocate
+pub struct Integer;
+
+// 64-bit integers, overflow is an error instead of switching to arbitrary precision
+pub struct Checked;
+
+// exact fractions, division doesn't round
+pub struct Rational;
+
+// fractions rounded to a fixed number of decimal places after every operation (half away from zero)
+pub struct Decimal(pub u32);
+
+// integers modulo a positive number, division multiplies with the inverse
+pub struct Modular(pub BigInt);
+
+// what / does when the result isn't a whole number (division in the manifest)
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Division {
+    Truncate, // towards zero
+    Floor, // like //
+    Exact // it's an error
+}
+
+pub fn division(mode: &str) -> Option<Division> {
+    match mode {
+        "truncate" => Some(Division::Truncate),
+        "floor" => Some(Division::Floor),
+        "exact" => Some(Division::Exact),
+        _ => None
+    }
+}
+
+// integer, checked, rational, decimal (10 places), decimal:4, modular:1000000007
+pub fn backend(mode: &str) -> Option<Arc<dyn Numeric>> {
+    let (name, argument) = match mode.split_once(':') {
+        Some((name, argument)) => (name, Some(argument)),
+        None => (mode, None)
+    };
+
+    match (name, argument) {
+        ("integer", None) => Some(Arc::new(Integer)),
+        ("checked", None) => Some(Arc::new(Checked)),
+        ("rational", None) => Some(Arc::new(Rational)),
+        ("decimal", None) => Some(Arc::new(Decimal(DECIMAL_PLACES))),
+        ("decimal", Some(places)) => places.parse::<u32>().ok().map(|places| Arc::new(Decimal(places)) as Arc<dyn Numeric>),
+        ("modular", Some(modulus)) => modulus.parse::<BigInt>().ok().filter(|m| m.sign() == Sign::Plus).map(|m| Arc::new(Modular(m)) as Arc<dyn Numeric>),
+        _ => None
+    }
+}
+
+impl Numeric for Integer {
+    fn math(&self, math: &MathType, a: &Value, b: &Value) -> Value {
+        if let (Value::Small(a), Value::Small(b)) = (a, b) {
+            if let Some(value) = small_math(math, *a, *b) {
+                return value;
+            }
+        }
+
+        match math {
+            MathType::Add               => Value::from(a.number().add(b.number())),
+            MathType::Subtract          => Value::from(a.number().sub(b.number())),
+            MathType::Multiply          => Value::from(a.number().mul(b.number())),
+            MathType::Divide if b.number() == BigInt::from(0) => raise(ErrorCode::InvalidArgument, "Division by zero"),
+            MathType::Divide            => Value::from(a.number().div(b.number())),
+            MathType::FloorDivide if b.number() == BigInt::from(0) => raise(ErrorCode::InvalidArgument, "Division by zero"),
+            MathType::FloorDivide       => Value::from(floor_div(&a.number(), &b.number())),
+            MathType::Pow               => Value::from(a.number().pow(exponent(b))),
+            _                           => Value::bool(compare(math, a.number().cmp(&b.number())))
+        }
+    }
+}
+
+impl Numeric for Checked {
+    fn math(&self, math: &MathType, a: &Value, b: &Value) -> Value {
+        let (x, y) = (small(a), small(b));
+
+        match math {
+            MathType::Divide | MathType::FloorDivide if y == 0 => raise(ErrorCode::InvalidArgument, "Division by zero"),
+            MathType::Pow => {
+                exponent(b);
+            },
+            _ => {}
+        }
+
+        small_math(math, x, y).unwrap_or_else(|| raise(ErrorCode::Overflow, &format!("Overflow ({} {} {} doesn't fit in 64 bits)", a, math.operator(), b)))
+    }
+}
+
+impl Numeric for Rational {
+    fn math(&self, math: &MathType, a: &Value, b: &Value) -> Value {
+        let (p1, q1) = ratio(a);
+        let (p2, q2) = ratio(b);
+
+        match math {
+            MathType::Add               => Value::fraction(&p1 * &q2 + &p2 * &q1, q1 * q2),
+            MathType::Subtract          => Value::fraction(&p1 * &q2 - &p2 * &q1, q1 * q2),
+            MathType::Multiply          => Value::fraction(p1 * p2, q1 * q2),
+            MathType::Divide if p2 == BigInt::from(0) => raise(ErrorCode::InvalidArgument, "Division by zero"),
+            MathType::Divide            => Value::fraction(p1 * q2, q1 * p2),
+            MathType::FloorDivide if p2 == BigInt::from(0) => raise(ErrorCode::InvalidArgument, "Division by zero"),
+            MathType::FloorDivide       => Value::from(floor_div(&(p1 * q2), &(q1 * p2))),
+            MathType::Pow => {
+                let e = b.number();
+                let n = exponent(&Value::from(BigInt::from(e.magnitude().clone())));
+
+                if e.sign() != Sign::Minus {
+                    Value::fraction(p1.pow(n), q1.pow(n))
+                } else if p1 == BigInt::from(0) {
+                    raise(ErrorCode::InvalidArgument, "Division by zero")
+                } else {
+                    Value::fraction(q1.pow(n), p1.pow(n))
+                }
+            },
+            _ => Value::bool(compare(math, (p1 * q2).cmp(&(p2 * q1))))
+        }
+    }
+}
+
+impl Numeric for Decimal {
+    fn math(&self, math: &MathType, a: &Value, b: &Value) -> Value {
+        match Rational.math(math, a, b) {
+            Value::Fraction(p, q) => round(&p, &q, self.0),
+            exact => exact
+        }
+    }
+}
+
+impl Numeric for Modular {
+    fn math(&self, math: &MathType, a: &Value, b: &Value) -> Value {
+        let m = &self.0;
+        let reduce = |x: BigInt| ((x % m) + m) % m;
+        let (x, y) = (reduce(a.number()), reduce(b.number()));
+
+        match math {
+            MathType::Add               => Value::from(reduce(x + y)),
+            MathType::Subtract          => Value::from(reduce(x - y)),
+            MathType::Multiply          => Value::from(reduce(x * y)),
+            MathType::Divide            => Value::from(reduce(x * inverse(&y, m))),
+            MathType::FloorDivide       => raise(ErrorCode::InvalidArgument, "// isn't defined for numbers modulo m, / multiplies with the inverse"),
+            MathType::Pow if b.number().sign() == Sign::Minus => Value::from(inverse(&x, m).modpow(&-b.number(), m)),
+            MathType::Pow               => Value::from(x.modpow(&b.number(), m)),
+            _                           => Value::bool(compare(math, x.cmp(&y)))
+        }
+    }
+}
+
+// None if the result doesn't fit (or is an error), the callers handle those
+fn small_math(math: &MathType, a: i64, b: i64) -> Option<Value> {
+    match math {
+        MathType::Add               => a.checked_add(b).map(Value::from),
+        MathType::Subtract          => a.checked_sub(b).map(Value::from),
+        MathType::Multiply          => a.checked_mul(b).map(Value::from),
+        MathType::Divide            => a.checked_div(b).map(Value::from),
+        MathType::FloorDivide       => a.checked_div(b).map(|q| if a % b != 0 && (a < 0) != (b < 0) { q - 1 } else { q }).map(Value::from),
+        MathType::Pow               => u32::try_from(b).ok().and_then(|b| a.checked_pow(b)).map(Value::from),
+        _                           => Some(Value::bool(compare(math, a.cmp(&b))))
+    }
+}
+
+fn small(value: &Value) -> i64 {
+    match value {
+        Value::Small(value) => *value,
+        _ => raise(ErrorCode::Overflow, &format!("Overflow ({} doesn't fit in 64 bits)", value.number()))
+    }
+}
+
+fn exponent(value: &Value) -> u32 {
+    u32::try_from(value.number()).unwrap_or_else(|_| raise(ErrorCode::InvalidArgument, "Exponent has to be a non-negative 32-bit number"))
+}
+
+fn compare(math: &MathType, ordering: Ordering) -> bool {
+    match math {
+        MathType::Equals            => ordering == Ordering::Equal,
+        MathType::NotEquals         => ordering != Ordering::Equal,
+        MathType::BiggerOrEquals    => ordering != Ordering::Less,
+        MathType::Bigger            => ordering == Ordering::Greater,
+        MathType::SmallerOrEquals   => ordering != Ordering::Greater,
+        MathType::Smaller           => ordering == Ordering::Less,
+        _ => unreachable!()
+    }
+}
+
+// numerator and (positive) denominator
+fn ratio(value: &Value) -> (BigInt, BigInt) {
+    match value {
+        Value::Fraction(p, q) => (p.clone(), q.clone()),
+        Value::Decimal(digits, scale) => (digits.clone(), BigInt::from(10).pow(*scale)),
+        _ => (value.number(), BigInt::from(1))
+    }
+}
+
+// how sort and binary_search order values, numbers by their value whatever their kind, strings by their characters
+// and tuples element by element
+pub fn order(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::String(x), Value::String(y)) => x.cmp(y),
+        (Value::Tuple(x), Value::Tuple(y)) => x.iter().zip(y).map(|(x, y)| order(x, y)).find(|o| o.is_ne()).unwrap_or_else(|| x.len().cmp(&y.len())),
+        _ if a.is_numeric() && b.is_numeric() => {
+            let ((p1, q1), (p2, q2)) = (ratio(a), ratio(b));
+
+            (p1 * q2).cmp(&(p2 * q1))
+        },
+        _ => raise(ErrorCode::TypeMismatch, &format!("Can't order {} ('{}') and {} ('{}')", a.type_name(), a, b.type_name(), b))
+    }
+}
+
+fn round(p: &BigInt, q: &BigInt, places: u32) -> Value {
+    let scaled = p * BigInt::from(10).pow(places);
+    let (quotient, remainder) = (&scaled / q, &scaled % q);
+    let quotient = if remainder.magnitude() * 2u32 >= *q.magnitude() {
+        quotient + BigInt::from(if scaled.sign() == Sign::Minus { -1 } else { 1 })
+    } else {
+        quotient
+    };
+
+    Value::decimal(quotient, places)
+}
+
+// rounds down instead of towards zero
+pub fn floor_div(a: &BigInt, b: &BigInt) -> BigInt {
+    let q = a / b;
+
+    if (a % b != BigInt::from(0)) && ((a.sign() == Sign::Minus) != (b.sign() == Sign::Minus)) {
+        q - 1
+    } else {
+        q
+    }
+}
+
+// extended Euclid, None if a and m aren't coprime
+pub fn invmod(a: &BigInt, m: &BigInt) -> Option<BigInt> {
+    let (mut r0, mut r1) = (a.modpow(&BigInt::from(1), m), m.clone());
+    let (mut s0, mut s1) = (BigInt::from(1), BigInt::from(0));
+
+    while r1 != BigInt::from(0) {
+        let q = &r0 / &r1;
+
+        (r0, r1) = (r1.clone(), &r0 - &q * &r1);
+        (s0, s1) = (s1.clone(), &s0 - &q * &s1);
+    }
+
+    if r0 == BigInt::from(1) {
+        Some(((s0 % m) + m) % m)
+    } else {
+        None
+    }
+}
+
+fn inverse(a: &BigInt, m: &BigInt) -> BigInt {
+    invmod(a, m).unwrap_or_else(|| raise(ErrorCode::InvalidArgument, &format!("Division by {}, which has no inverse mod {}", a, m)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{backend, Division};
+    use crate::context::Context;
+    use crate::error::ErrorCode;
+
+    // the value of the last expression of source, computed with the backend
+    fn run(mode: &str, source: &str) -> Result<String, ErrorCode> {
+        run_with(mode, Division::Truncate, source)
+    }
+
+    fn run_with(mode: &str, division: Division, source: &str) -> Result<String, ErrorCode> {
+        let mut context = Context::new();
+
+        context.runtime.numeric = backend(mode).unwrap();
+        context.runtime.division = division;
+
+        context.exec(source).map(|value| value.unwrap().to_string()).map_err(|e| e.code())
+    }
+
+    #[test]
+    fn checked() {
+        assert_eq!(run("checked", "1 / 3 + 1 / 6"), Ok("0".to_owned()));
+        assert_eq!(run("checked", "9223372036854775807"), Ok("9223372036854775807".to_owned()));
+        assert_eq!(run("checked", "9223372036854775807 + 1"), Err(ErrorCode::Overflow));
+        assert_eq!(run("checked", "2 ^ 63"), Err(ErrorCode::Overflow));
+        assert_eq!(run("checked", "1 / 0"), Err(ErrorCode::InvalidArgument));
+    }
+
+    #[test]
+    fn rational() {
+        assert_eq!(run("rational", "1 / 3 + 1 / 6"), Ok("1/2".to_owned()));
+        assert_eq!(run("rational", "(2 / 3) ^ -2"), Ok("9/4".to_owned()));
+        assert_eq!(run("rational", "1 / 3 + 2 / 3"), Ok("1".to_owned()));
+        assert_eq!(run("rational", "7 // 2"), Ok("3".to_owned()));
+    }
+
+    #[test]
+    fn decimal() {
+        assert_eq!(run("decimal", "1 / 3 + 1 / 6"), Ok("0.5".to_owned()));
+        assert_eq!(run("decimal:2", "1 / 3 + 1 / 6"), Ok("0.5".to_owned()));
+        assert_eq!(run("decimal:3", "1 / 3"), Ok("0.333".to_owned()));
+    }
+
+    // half away from zero, after every operation
+    #[test]
+    fn decimal_rounding() {
+        assert_eq!(run("decimal:2", "1 / 8"), Ok("0.13".to_owned()));
+        assert_eq!(run("decimal:2", "-1 / 8"), Ok("-0.13".to_owned()));
+        assert_eq!(run("decimal:2", "1 / 200"), Ok("0.01".to_owned()));
+        assert_eq!(run("decimal:2", "1 / 300"), Ok("0".to_owned()));
+        assert_eq!(run("decimal:1", "(1 / 3) * 3"), Ok("0.9".to_owned()));
+    }
+
+    #[test]
+    fn modular() {
+        assert_eq!(run("modular:7", "1 / 3 + 1 / 6"), Ok("4".to_owned())); // 1/2, 2 * 4 is 1 mod 7
+        assert_eq!(run("modular:7", "3 / 2"), Ok("5".to_owned()));
+        assert_eq!(run("modular:7", "5 + 4"), Ok("2".to_owned()));
+        assert_eq!(run("modular:7", "3 ^ 6"), Ok("1".to_owned()));
+        assert_eq!(run("modular:10", "1 / 4"), Err(ErrorCode::InvalidArgument));
+        assert_eq!(run("modular:10", "7 // 2"), Err(ErrorCode::InvalidArgument));
+    }
+
+    #[test]
+    fn division() {
+        assert_eq!(run_with("integer", Division::Truncate, "-7 / 2"), Ok("-3".to_owned()));
+        assert_eq!(run_with("integer", Division::Floor, "-7 / 2"), Ok("-4".to_owned()));
+        assert_eq!(run_with("integer", Division::Floor, "7 / 2"), Ok("3".to_owned()));
+        assert_eq!(run_with("checked", Division::Floor, "-7 / 2"), Ok("-4".to_owned()));
+        assert_eq!(run_with("integer", Division::Exact, "-8 / 2"), Ok("-4".to_owned()));
+        assert_eq!(run_with("integer", Division::Exact, "7 / 2"), Err(ErrorCode::InvalidArgument));
+        assert_eq!(run_with("checked", Division::Exact, "7 / 2"), Err(ErrorCode::InvalidArgument));
+        assert_eq!(run_with("integer", Division::Exact, "7 // 2"), Ok("3".to_owned()));
+    }
+
+    #[test]
+    fn modes() {
+        assert!(backend("modular:0").is_none());
+        assert!(backend("modular").is_none());
+        assert!(backend("decimal:x").is_none());
+        assert!(backend("float").is_none());
+    }
+}
@@ -0,0 +1,185 @@
+use crate::error::{ErrorCode, raise};
+use crate::manifest::{self, Dependency, Manifest, MANIFEST};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{copy, create_dir_all, read_dir, read_to_string, remove_dir_all, write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub const DEPENDENCIES: &str = ".math/deps"; // relative to the project root, every dependency gets a directory named after it
+const REGISTRY: &str = ".math/registry";
+const SOURCE: &str = ".source"; // remembers where a cached dependency came from so it's only fetched again when that changes
+
+// fetches the dependencies of the project and of its dependencies, returns the include path they can be included from
+pub fn resolve(manifest: &Manifest) -> PathBuf {
+    let cache = manifest.root.join(DEPENDENCIES);
+    let mut resolved = BTreeMap::<String, String>::new();
+
+    resolve_into(manifest, &cache, &mut resolved);
+
+    cache
+}
+
+fn resolve_into(manifest: &Manifest, cache: &Path, resolved: &mut BTreeMap<String, String>) {
+    for (name, dependency) in &manifest.dependencies {
+        let source = source(dependency, &manifest.root);
+
+        match resolved.get(name) {
+            Some(existing) if existing.eq(&source) => continue,
+            Some(existing) => raise(ErrorCode::UnresolvedDependency, &format!("Dependency '{}' is required from two sources ({} and {})", name, existing, source)),
+            None => {}
+        }
+
+        resolved.insert(name.clone(), source);
+
+        let dir = fetch(name, dependency, &manifest.root, cache);
+
+        if dir.join(MANIFEST).is_file() { // libraries can have dependencies of their own
+            resolve_into(&manifest::load(&dir), cache, resolved);
+        }
+    }
+}
+
+fn source(dependency: &Dependency, root: &Path) -> String {
+    if let Some(git) = &dependency.git {
+        format!("git+{}#{}", git, dependency.rev.as_deref().unwrap_or("HEAD"))
+    } else {
+        let path = root.join(dependency.path.as_ref().unwrap());
+
+        format!("path+{}", path.canonicalize().unwrap_or(path).display())
+    }
+}
+
+fn fetch(name: &str, dependency: &Dependency, root: &Path, cache: &Path) -> PathBuf {
+    let dir = cache.join(name);
+    let source = source(dependency, root);
+
+    // the directory gets deleted below, it must never be outside of the cache
+    if !manifest::dependency_name(name) || dir.parent() != Some(cache) {
+        raise(ErrorCode::InvalidManifest, &format!("Invalid dependency name ('{}'), names can't be paths", name));
+    }
+
+    if dependency.git.is_some() && read_to_string(dir.join(SOURCE)).is_ok_and(|s| s.eq(&source)) {
+        return dir;
+    }
+
+    if dir.exists() {
+        remove_dir_all(&dir).expect("Error while clearing dependency cache");
+    }
+
+    create_dir_all(cache).expect("Error while creating dependency cache");
+
+    if let Some(git) = &dependency.git {
+        if !run_git(&["clone", "--quiet", git, name], cache) {
+            raise(ErrorCode::UnresolvedDependency, &format!("Failed to clone dependency '{}' ({})", name, git));
+        }
+
+        if let Some(rev) = &dependency.rev {
+            if !run_git(&["checkout", "--quiet", rev], &dir) {
+                raise(ErrorCode::UnresolvedDependency, &format!("Revision '{}' of dependency '{}' not found", rev, name));
+            }
+        }
+    } else { // path dependencies are copied on every run so that changes to them are picked up
+        let path = root.join(dependency.path.as_ref().unwrap());
+
+        if !path.is_dir() {
+            raise(ErrorCode::UnresolvedDependency, &format!("Dependency '{}' not found ('{}')", name, path.display()));
+        }
+
+        copy_dir(&path, &dir);
+    }
+
+    write(dir.join(SOURCE), source).expect("Error while writing dependency cache");
+
+    dir
+}
+
+fn run_git(args: &[&str], dir: &Path) -> bool {
+    Command::new("git").args(args).current_dir(dir).status().is_ok_and(|s| s.success())
+}
+
+fn copy_dir(from: &Path, to: &Path) {
+    create_dir_all(to).expect("Error while copying dependency");
+
+    for entry in read_dir(from).expect("Error while copying dependency").map(|e| e.unwrap()) {
+        let path = entry.path();
+
+        if path.is_dir() {
+            if !entry.file_name().eq(".math") && !entry.file_name().eq(".git") {
+                copy_dir(&path, &to.join(entry.file_name()));
+            }
+        } else {
+            copy(&path, to.join(entry.file_name())).expect("Error while copying dependency");
+        }
+    }
+}
+
+// the registry is an index.toml (name = { git = "...", rev = "..." }) in the directory or git repository MATH_REGISTRY points to
+pub fn lookup(name: &str, root: &Path) -> Dependency {
+    let registry = env::var("MATH_REGISTRY").unwrap_or_else(|_| raise(ErrorCode::UnresolvedDependency, &format!("No registry configured to look up '{}' in, set MATH_REGISTRY or use --git/--path", name)));
+    let dir = if Path::new(&registry).is_dir() {
+        PathBuf::from(&registry)
+    } else {
+        let dir = root.join(REGISTRY);
+        let updated = if dir.is_dir() {
+            run_git(&["pull", "--quiet"], &dir)
+        } else {
+            create_dir_all(&dir).expect("Error while creating registry cache");
+            run_git(&["clone", "--quiet", &registry, "."], &dir)
+        };
+
+        if !updated {
+            raise(ErrorCode::UnresolvedDependency, &format!("Failed to fetch registry ({})", registry));
+        }
+
+        dir
+    };
+    let index = read_to_string(dir.join("index.toml")).unwrap_or_else(|_| raise(ErrorCode::UnresolvedDependency, &format!("Registry has no index.toml ({})", registry)));
+    let mut packages = toml::from_str::<BTreeMap<String, Dependency>>(&index).unwrap_or_else(|e| raise(ErrorCode::UnresolvedDependency, &format!("Invalid registry index ({}): {}", registry, e.message())));
+
+    packages.remove(name).filter(|d| d.git.is_some()).unwrap_or_else(|| raise(ErrorCode::UnresolvedDependency, &format!("Package '{}' not found in the registry", name)))
+}
+
+// fetches the dependency first so that a broken one never ends up in the manifest
+pub fn add(mut manifest: Manifest, file: &Path, name: &str, dependency: Dependency) {
+    if !manifest::dependency_name(name) {
+        raise(ErrorCode::InvalidManifest, &format!("Invalid dependency name ('{}'), names can't be paths", name));
+    }
+
+    if manifest.dependencies.contains_key(name) {
+        raise(ErrorCode::InvalidManifest, &format!("Dependency '{}' already exists", name));
+    }
+
+    manifest.dependencies.insert(name.to_owned(), dependency.clone());
+
+    resolve(&manifest);
+
+    let mut fields = Vec::<String>::new();
+
+    if let Some(git) = &dependency.git {
+        fields.push(format!("git = \"{}\"", git));
+    }
+
+    if let Some(rev) = &dependency.rev {
+        fields.push(format!("rev = \"{}\"", rev));
+    }
+
+    if let Some(path) = &dependency.path {
+        fields.push(format!("path = \"{}\"", path.display()));
+    }
+
+    let entry = format!("{} = {{ {} }}", name, fields.join(", "));
+    let content = read_to_string(file).expect("Error while reading manifest");
+    let mut lines = content.lines().map(str::to_owned).collect::<Vec<String>>();
+
+    match lines.iter().position(|l| l.trim().eq("[dependencies]")) {
+        Some(header) => lines.insert(header + 1, entry),
+        None => {
+            lines.push(String::new());
+            lines.push("[dependencies]".to_owned());
+            lines.push(entry);
+        }
+    }
+
+    write(file, lines.join("\n") + "\n").expect("Error while writing manifest");
+}
@@ -1,36 +1,112 @@
-use crate::ast::{AST, Function, Variable, Expression};
-use crate::parser::expression::{PartExpression, actual_parse_expression, Precedence, parse_expression_part};
-use crate::lexer::LexedToken;
+use crate::ast::{AST, Function, Variable, Expression, Location, Test};
+use crate::parser::expression::{PartExpression, actual_parse_expression, Precedence, parse_expression_part, unescape};
+use crate::parser::macros::expand_macros;
+use crate::lexer::{LexedToken, errors};
+use crate::error::{ErrorCode, raise_at};
 use crate::interpreter::runtime::ExternalRuntimeFunction;
+use log::debug;
 
 pub mod expression;
+pub mod macros;
+pub mod strict;
+
+// a line ending with one of these is continued on the next one as if it ended with |
+const CONTINUING: [&str; 17] = ["PLUS", "MINUS", "MULTIPLY", "DIVIDE", "FLOOR_DIVIDE", "POW", "EQUALS", "NOT_EQUALS", "BIGGER_OR_EQUALS", "BIGGER", "SMALLER_OR_EQUALS", "SMALLER", "AND", "OR", "QUESTION", "COLON", "RANGE"];
 
 pub fn parse(tokens: Vec<LexedToken>, external_functions: Vec<ExternalRuntimeFunction>) -> AST {
+    parse_with(tokens, external_functions, false)
+}
+
+// strict mode is also turned on by a #pragma strict anywhere in the program
+pub fn parse_with(tokens: Vec<LexedToken>, external_functions: Vec<ExternalRuntimeFunction>, mut strict: bool) -> AST {
     let mut queue = token_queue(tokens);
     let mut variables = Vec::<Variable>::new();
     let mut functions = external_functions.into_iter().map(map_function).collect::<Vec<Function>>();
     let mut loose_expressions_pre = Vec::<PartExpression>::new();
+    let mut documentation = Vec::<String>::new();
+    let mut variable_tokens = Vec::<LexedToken>::new(); // for strict mode diagnostics
+    let mut function_tokens = Vec::<LexedToken>::new();
+    let mut loose_tokens = Vec::<LexedToken>::new();
+    let mut tests_pre = Vec::<(String, PartExpression, LexedToken)>::new();
+    let lexer_errors = errors(&queue.elements);
+
+    if !lexer_errors.is_empty() {
+        let message = format!("{} error(s) while lexing", lexer_errors.len());
+
+        raise_at(ErrorCode::UnrecognizedToken, &message, lexer_errors.iter().map(|e| format!("\n{}", e)).collect::<String>()); // every unrecognized token is reported, not just the first one
+    }
 
     queue.purge_all("WHITESPACE");
 
+    queue = token_queue(expand_macros(join_continued(queue.elements)));
+
     // pre parse
 
     while queue.is_not_empty() {
         let next = queue.peek();
 
         match next.token_type().id() {
-            "LET" => variables.push(pre_parse_variable(&mut queue)),
+            "LET" => {
+                variable_tokens.push(next.clone());
+
+                let mut var = pre_parse_variable(&mut queue);
+
+                var.documentation = take_documentation(&mut documentation);
+
+                variables.push(var);
+            },
             "CONST" => {
+                variable_tokens.push(next.clone());
+
                 let mut var = pre_parse_variable(&mut queue);
 
                 var.constant = true;
+                var.documentation = take_documentation(&mut documentation);
 
                 variables.push(var);
             }
-            "DEFINE" => functions.push(pre_parse_function(&mut queue)),
-            "NEW_LINE" => {}, // do nothing
+            "DEFINE" => {
+                function_tokens.push(next.clone());
+
+                let mut func = pre_parse_function(&mut queue);
+
+                func.documentation = take_documentation(&mut documentation);
+
+                functions.push(func);
+            },
+            // cache define f(x) = ..., the same as define cache f(x) = ...
+            "CACHE" => {
+                function_tokens.push(next.clone());
+
+                if queue.is_empty() || queue.peek().token_type().id().ne("DEFINE") {
+                    next.err("Expected define");
+                }
+
+                let mut func = pre_parse_function(&mut queue);
+
+                func.cached = true;
+                func.documentation = take_documentation(&mut documentation);
+
+                functions.push(func);
+            },
+            // test "name" = condition, test is only a keyword when a string follows
+            "IDENTIFIER" if next.token_type().keyword() == Some("TEST") && queue.is_not_empty() && queue.get().token_type().id().eq("STRING") => {
+                documentation.clear();
+
+                let (name, condition) = pre_parse_test(&mut queue);
+
+                tests_pre.push((name, condition, next));
+            },
+            "DOC_COMMENT" => documentation.push(next.content().to_owned()),
+            "PRAGMA" => match next.content().as_str() {
+                "strict" => strict = true,
+                _ => next.err(&format!("Unknown pragma ('{}')", next.content()))
+            },
+            "NEW_LINE" | "SEMICOLON" => {}, // ; separates statements on the same line, it ends them like a line break
             _ => {
+                loose_tokens.push(next.clone());
                 queue.back();
+                documentation.clear(); // documentation only belongs to definitions
 
                 loose_expressions_pre.push(pre_parse_loose_expression(&mut queue));
             }
@@ -47,33 +123,103 @@ pub fn parse(tokens: Vec<LexedToken>, external_functions: Vec<ExternalRuntimeFun
 
     let mut loose_expressions = Vec::<Expression>::new();
 
-    for expr in loose_expressions_pre {
+    let mut loose_tokens_left = Vec::<LexedToken>::new();
+    let mut locations = Vec::<Location>::new();
+
+    for (expr, token) in loose_expressions_pre.into_iter().zip(loose_tokens) {
         if PartExpression::Comment == expr { // filter out comments
             continue;
         }
 
         loose_expressions.push(actual_parse_expression(expr, &variables, &functions));
+        locations.push(Location { file: token.file().clone(), line: *token.line() });
+        loose_tokens_left.push(token);
     }
 
-    AST {
+    let tests = tests_pre.into_iter().map(|(name, condition, token)| Test {
+        name,
+        condition: actual_parse_expression(condition, &variables, &functions),
+        location: Location { file: token.file().clone(), line: *token.line() }
+    }).collect::<Vec<Test>>();
+
+    let ast = AST {
         functions,
         variables,
-        loose_expressions
+        loose_expressions,
+        locations,
+        tests
+    };
+
+    if strict {
+        strict::check(&ast, &variable_tokens, &function_tokens, &loose_tokens_left);
+    }
+
+    debug!("Parsed {} function(s), {} variable(s) and {} loose expression(s){}", ast.functions.iter().filter(|f| Expression::External != f.definition).count(), ast.variables.len(), ast.loose_expressions.len(), if strict { " (strict)" } else { "" });
+
+    ast
+}
+
+// drops the line breaks after trailing binary operators (and commas, semicolons and opening parentheses inside parentheses), so the pre parse loops never see them
+fn join_continued(tokens: Vec<LexedToken>) -> Vec<LexedToken> {
+    let mut joined = Vec::<LexedToken>::with_capacity(tokens.len());
+    let mut depth = 0;
+
+    for t in tokens {
+        if t.token_type().id().eq("NEW_LINE") {
+            if joined.last().is_some_and(|last: &LexedToken| continues(last.token_type().id(), depth)) {
+                continue;
+            }
+
+            depth = 0;
+        }
+
+        depth = nesting(t.token_type().id(), depth);
+        joined.push(t);
+    }
+
+    joined
+}
+
+// whether a line ending with the token continues on the next one, depth is how many parentheses are open
+pub(crate) fn continues(last: &str, depth: usize) -> bool {
+    CONTINUING.contains(&last) || (["COMMA", "SEMICOLON", "OPEN_PARENTHESIS"].contains(&last) && depth > 0)
+}
+
+pub(crate) fn nesting(id: &str, depth: usize) -> usize {
+    match id {
+        "OPEN_PARENTHESIS" | "OPEN_BRACKET" | "OPEN_BRACE" => depth + 1,
+        "CLOSE_PARENTHESIS" | "CLOSE_BRACKET" | "CLOSE_BRACE" => depth.saturating_sub(1),
+        _ => depth
     }
 }
 
+fn take_documentation(documentation: &mut Vec<String>) -> Option<String> {
+    if documentation.is_empty() {
+        return None;
+    }
+
+    let joined = documentation.join("\n");
+
+    documentation.clear();
+
+    Some(joined)
+}
+
 fn map_function(f: ExternalRuntimeFunction) -> Function {
     Function {
         name: f.name().to_owned(),
         definition: Expression::External,
         parameters: (0..*f.parameters()).map(|i| format!("p{}", i)).collect::<Vec<String>>(),
+        variadic: *f.variadic(),
         pre_definition: PartExpression::None,
-        cached: false
+        cached: false,
+        documentation: None
     }
 }
 
 fn pre_parse_loose_expression(queue: &mut TokenQueue) -> PartExpression {
     let mut lines_left = 1;
+    let mut depth = 0; // a ; inside parentheses separates the expressions of a block instead
     let mut actual_tokens = Vec::<LexedToken>::new();
 
     while lines_left > 0 && queue.is_not_empty() {
@@ -82,7 +228,11 @@ fn pre_parse_loose_expression(queue: &mut TokenQueue) -> PartExpression {
         match next.token_type().id() {
             "PIPE" => lines_left += 1,
             "NEW_LINE" => lines_left -= 1,
-            _ => actual_tokens.push(next)
+            "SEMICOLON" if depth == 0 => lines_left -= 1,
+            id => {
+                depth = nesting(id, depth);
+                actual_tokens.push(next);
+            }
         }
     }
 
@@ -93,23 +243,35 @@ fn pre_parse_loose_expression(queue: &mut TokenQueue) -> PartExpression {
     parse_expression_part(&mut token_queue(actual_tokens), Precedence::None)
 }
 
-fn post_parse_variable(var: &mut Variable, variables: &Vec<Variable>, functions: &Vec<Function>) {
-    var.definition = actual_parse_expression(var.pre_definition.clone(), variables, functions);
+fn pre_parse_test(queue: &mut TokenQueue) -> (String, PartExpression) {
+    let name = queue.peek();
+
+    if queue.is_empty() {
+        name.err_offset("Expected =", name.content().len());
+    }
+
+    queue.peek().check_id("ASSIGN", "Expected =");
+
+    let condition = pre_parse_loose_expression(queue);
 
-    for expr in &var.pre_wherepart {
-        var.wherepart.push(actual_parse_expression(expr.clone(), variables, functions));
+    if PartExpression::Comment == condition {
+        name.err("Expected condition");
     }
 
-    // clear pre definition/wherepart
+    (unescape(&name), condition)
+}
+
+fn post_parse_variable(var: &mut Variable, variables: &Vec<Variable>, functions: &Vec<Function>) {
+    var.definition = actual_parse_expression(var.pre_definition.clone(), variables, functions);
+
+    // clear pre definition
 
     var.pre_definition = PartExpression::None;
-    var.pre_wherepart.clear();
 }
 
 fn pre_parse_variable(queue: &mut TokenQueue) -> Variable {
     let mut name = String::new();
     let mut definition = PartExpression::None;
-    let /* mut */ wherepart = Vec::<PartExpression>::new();
     let mut lines_left = 1;
 
     while lines_left > 0 && queue.is_not_empty() {
@@ -117,7 +279,7 @@ fn pre_parse_variable(queue: &mut TokenQueue) -> Variable {
 
         match next.token_type().id() {
             "PIPE" => lines_left += 1,
-            "NEW_LINE" => lines_left -= 1,
+            "NEW_LINE" | "SEMICOLON" => lines_left -= 1,
             "ASSIGN" => {
                 if name.is_empty() {
                     next.err("Expected identifier");
@@ -126,12 +288,13 @@ fn pre_parse_variable(queue: &mut TokenQueue) -> Variable {
                 }
 
                 let mut expr_queue_vec = Vec::<LexedToken>::new();
+                let mut depth = 0;
 
                 loop {
                     let get = queue.peek();
                     let id = get.token_type().id();
 
-                    if id.eq("NEW_LINE") {
+                    if id.eq("NEW_LINE") || (id.eq("SEMICOLON") && depth == 0) {
                         lines_left -= 1;
 
                         break;
@@ -147,6 +310,7 @@ fn pre_parse_variable(queue: &mut TokenQueue) -> Variable {
                         break;
                     }
 
+                    depth = nesting(id, depth);
                     expr_queue_vec.push(get.clone());
                 }
 
@@ -168,9 +332,8 @@ fn pre_parse_variable(queue: &mut TokenQueue) -> Variable {
                     next.err("Expected definition");
                 }
 
-                // wherepart = read_where(queue);
-
-                todo!("where part");
+                // a where binding is an extra argument of the current call, variables aren't called
+                next.err("where is only allowed on functions");
             },
             _ => {
                 if !name.is_empty() {
@@ -185,15 +348,14 @@ fn pre_parse_variable(queue: &mut TokenQueue) -> Variable {
     Variable {
         name,
         definition: Expression::None, // do in post parse so that we can do lookahead variable parsing etc...
-        wherepart: vec![],
         pre_definition: definition,
-        pre_wherepart: wherepart,
-        constant: false
+        constant: false,
+        documentation: None
     }
 }
 
-fn post_parse_function(func: &mut Function, variables: &Vec<Variable>, functions: &Vec<Function>) {
-    let mut vars = variables.clone();
+fn post_parse_function(func: &mut Function, variables: &[Variable], functions: &Vec<Function>) {
+    let mut vars = variables.to_vec();
 
     for param in &func.parameters {
         vars.push(fake_variable(param.to_owned()));
@@ -206,14 +368,13 @@ fn post_parse_function(func: &mut Function, variables: &Vec<Variable>, functions
     func.pre_definition = PartExpression::None;
 }
 
-fn fake_variable(name: String) -> Variable {
+pub(crate) fn fake_variable(name: String) -> Variable {
     Variable {
         name,
         definition: Expression::None,
-        wherepart: vec![],
         pre_definition: PartExpression::None,
-        pre_wherepart: vec![],
-        constant: false
+        constant: false,
+        documentation: None
     }
 }
 
@@ -223,13 +384,14 @@ fn pre_parse_function(queue: &mut TokenQueue) -> Function {
     let mut parameters = Vec::<String>::new();
     let mut lines_left = 1;
     let mut cached = false;
+    let mut variadic = false;
 
     while lines_left > 0 && queue.is_not_empty() {
         let next = queue.peek();
 
         match next.token_type().id() {
             "PIPE" => lines_left += 1,
-            "NEW_LINE" => lines_left -= 1,
+            "NEW_LINE" | "SEMICOLON" => lines_left -= 1,
             "OPEN_PARENTHESIS" => {
                 if name.is_empty() {
                     next.err("Expected identifier");
@@ -243,7 +405,7 @@ fn pre_parse_function(queue: &mut TokenQueue) -> Function {
                     let get = queue.peek();
                     let id = get.token_type().id();
 
-                    if id.eq("NEW_LINE") {
+                    if id.eq("NEW_LINE") || id.eq("SEMICOLON") {
                         lines_left -= 1;
 
                         break;
@@ -284,7 +446,9 @@ fn pre_parse_function(queue: &mut TokenQueue) -> Function {
                     } else {
                         match token.as_str() {
                             "CLOSE_PARENTHESIS" => break,
+                            "COMMA" if variadic => next.err("The rest parameter has to be the last parameter"),
                             "COMMA" => parameters.push(expr_queue.peek().check_id("IDENTIFIER", "Identifier expected").content().to_owned()),
+                            "ELLIPSIS" if !variadic => variadic = true,
                             _ => next.err("CLOSE_PARENTHESIS or COMMA expected")
                         }
                     }
@@ -298,12 +462,13 @@ fn pre_parse_function(queue: &mut TokenQueue) -> Function {
                 }
 
                 let mut expr_queue_vec = Vec::<LexedToken>::new();
+                let mut depth = 0;
 
                 loop {
                     let get = queue.peek();
                     let id = get.token_type().id();
 
-                    if id.eq("NEW_LINE") {
+                    if id.eq("NEW_LINE") || (id.eq("SEMICOLON") && depth == 0) {
                         lines_left -= 1;
 
                         break;
@@ -319,12 +484,17 @@ fn pre_parse_function(queue: &mut TokenQueue) -> Function {
                         break;
                     }
 
+                    depth = nesting(id, depth);
                     expr_queue_vec.push(get.clone());
                 }
 
                 let mut expr_queue = token_queue(expr_queue_vec);
 
                 definition = parse_expression_part(&mut expr_queue, Precedence::None);
+
+                if expr_queue.is_not_empty() {
+                    definition = parse_where(&mut expr_queue, definition);
+                }
             },
             "IDENTIFIER" => {
                 if !name.is_empty() {
@@ -348,9 +518,50 @@ fn pre_parse_function(queue: &mut TokenQueue) -> Function {
         name,
         definition: Expression::None,
         parameters,
+        variadic,
         pre_definition: definition,
-        cached
+        cached,
+        documentation: None
+    }
+}
+
+// body where a = ..., b = ..., later bindings can use the earlier ones: the body is wrapped in one Where per binding
+fn parse_where(queue: &mut TokenQueue, body: PartExpression) -> PartExpression {
+    let token = queue.peek().check_id("WHERE", "Invalid token");
+    let mut bindings = Vec::<(String, PartExpression, LexedToken)>::new();
+
+    loop {
+        if queue.is_empty() {
+            token.err_offset("Expected identifier", token.content().len());
+        }
+
+        let name = queue.peek().check_id("IDENTIFIER", "Expected identifier");
+
+        if queue.is_empty() {
+            name.err_offset("Expected =", name.content().len());
+        }
+
+        let assign = queue.peek().check_id("ASSIGN", "Expected =");
+
+        if queue.is_empty() {
+            assign.err_offset("Expected definition", assign.content().len());
+        }
+
+        bindings.push((name.content().to_owned(), parse_expression_part(queue, Precedence::None), name));
+
+        if queue.is_empty() {
+            break;
+        }
+
+        queue.peek().check_id("COMMA", "Invalid token");
     }
+
+    bindings.into_iter().rev().fold(body, |body, (variable, value, token)| PartExpression::Where {
+        variable,
+        value: Box::new(value),
+        body: Box::new(body),
+        token
+    })
 }
 
 pub fn token_queue(elements: Vec<LexedToken>) -> TokenQueue {
@@ -380,7 +591,7 @@ impl TokenQueue {
     }
 
     pub fn get(&self) -> &LexedToken {
-        &self.elements.get(self.pointer).expect("Out of bounds")
+        self.elements.get(self.pointer).expect("Out of bounds")
     }
 
     pub fn remove(&mut self) {
@@ -388,7 +599,7 @@ impl TokenQueue {
     }
 
     pub fn is_empty(&self) -> bool {
-        self.elements.len() - self.pointer <= 0
+        self.elements.len() <= self.pointer
     }
 
     pub fn is_not_empty(&self) -> bool {
@@ -1,9 +1,12 @@
-use crate::ast::{Expression, Variable, MathType, Function};
-use crate::parser::{TokenQueue, token_queue};
+use crate::ast::{Expression, Variable, MathType, Function, MatchArm, arity_mismatch};
+use crate::parser::{TokenQueue, token_queue, fake_variable};
 use crate::lexer::{LexedToken, Token};
+use crate::error::ErrorCode;
 use std::collections::HashMap;
+use std::sync::Arc;
 use std::fmt::Debug;
 use num_bigint::BigInt;
+use crate::value::Value;
 
 pub fn parse_expression(queue: &mut TokenQueue, variables: &Vec<Variable>, functions: &Vec<Function>) -> Expression {
     actual_parse_expression(parse_expression_part(queue, Precedence::None), variables, functions)
@@ -29,7 +32,10 @@ pub fn parse_expression_part(queue: &mut TokenQueue, precedence: Precedence) ->
 
         queue.remove();
 
-        left = infix_parser.runner_infix()(queue, left, next, infix_parser.precedence_infix().clone())
+        left = match infix_parser {
+            Parser::Postfix { runner, .. } => runner(left, next),
+            _ => infix_parser.runner_infix()(queue, left, next, infix_parser.precedence_infix().clone())
+        }
     }
 
     left
@@ -42,6 +48,10 @@ enum Parser {
     },
     Prefix {
         runner: fn(&mut TokenQueue, LexedToken) -> PartExpression
+    },
+    Postfix { // like infix, but without a right side
+        runner: fn(PartExpression, LexedToken) -> PartExpression,
+        precedence: Precedence
     }
 }
 
@@ -49,13 +59,13 @@ impl Parser {
     fn runner_infix(&self) -> fn(&mut TokenQueue, PartExpression, LexedToken, Precedence) -> PartExpression {
         match *self {
             Parser::Infix { runner, .. } => runner,
-            Parser::Prefix { .. } => panic!("Not supported")
+            Parser::Prefix { .. } | Parser::Postfix { .. } => panic!("Not supported")
         }
     }
 
     fn precedence_infix(&self) -> &Precedence {
         match self {
-            Parser::Infix { precedence, .. } => precedence,
+            Parser::Infix { precedence, .. } | Parser::Postfix { precedence, .. } => precedence,
             Parser::Prefix { .. } => panic!("Not supported")
         }
     }
@@ -63,38 +73,121 @@ impl Parser {
     fn runner_prefix(&self) -> fn(&mut TokenQueue, LexedToken) -> PartExpression {
         match *self {
             Parser::Prefix { runner, .. } => runner,
-            Parser::Infix { .. } => panic!("Not supported")
+            Parser::Infix { .. } | Parser::Postfix { .. } => panic!("Not supported")
         }
     }
 }
 
+// left-associative, a - b - c is (a - b) - c
 fn default_parse_infix(queue: &mut TokenQueue, left: PartExpression, token: LexedToken, precedence: Precedence) -> PartExpression {
     PartExpression::InfixOperator {
-        operator: token.content().to_owned(),
+        operator: spelling(&token),
         left: Box::new(left),
-        right: Box::new(parse_expression_part(queue, precedence.one_less().clone())),
+        right: Box::new(parse_expression_part(queue, precedence)),
         token
     }
 }
 
+// a ^ b ^ c is a ^ (b ^ c) and a = b = c is a = (b = c)
+fn right_parse_infix(queue: &mut TokenQueue, left: PartExpression, token: LexedToken, precedence: Precedence) -> PartExpression {
+    default_parse_infix(queue, left, token, precedence.one_less())
+}
+
+// operators are told apart by how they're usually spelled, aliases (like != for =!) are tokens with the same id but different content
+fn spelling(token: &LexedToken) -> String {
+    match token.token_type().id() {
+        "PLUS" => "+",
+        "MINUS" => "-",
+        "MULTIPLY" => "*",
+        "DIVIDE" => "/",
+        "FLOOR_DIVIDE" => "//",
+        "POW" => "^",
+        "EQUALS" => "==",
+        "NOT_EQUALS" => "=!",
+        "BIGGER_OR_EQUALS" => ">=",
+        "BIGGER" => ">",
+        "SMALLER_OR_EQUALS" => "<=",
+        "SMALLER" => "<",
+        "ASSIGN" => "=",
+        "NOT" => "!",
+        "AND" => "&&",
+        "OR" => "||",
+        _ => token.content()
+    }.to_owned()
+}
+
 fn infix_parser(token: Token) -> Parser {
     match token.id() {
         "PLUS" | "MINUS" => Parser::Infix {
             runner: default_parse_infix,
             precedence: Precedence::Sum
         },
-        "MULTIPLY" | "DIVIDE" | "POW" => Parser::Infix {
+        "MULTIPLY" | "DIVIDE" | "FLOOR_DIVIDE" => Parser::Infix {
             runner: default_parse_infix,
             precedence: Precedence::Product
         },
-        "EQUALS" | "NOT_EQUALS" | "BIGGER_OR_EQUALS" | "BIGGER" | "SMALLER_OR_EQUALS" => Parser::Infix {
+        "POW" => Parser::Infix {
+            runner: right_parse_infix,
+            precedence: Precedence::Exponent
+        },
+        // c ? a : b, only the branch the condition picks is evaluated, a ? b : c ? d : e is a ? b : (c ? d : e)
+        "QUESTION" => Parser::Infix {
+            runner: |queue, left, token, precedence| -> PartExpression {
+                let then = parse_expression_part(queue, Precedence::None);
+
+                if queue.is_empty() {
+                    token.err("Missing COLON");
+                }
+
+                queue.peek().check_id("COLON", "COLON expected");
+
+                PartExpression::Conditional {
+                    condition: Box::new(left),
+                    then: Box::new(then),
+                    otherwise: Box::new(parse_expression_part(queue, precedence.one_less())),
+                    token
+                }
+            },
+            precedence: Precedence::Ternary
+        },
+        // && binds tighter than ||, both only evaluate the right side if the left one doesn't decide
+        "AND" => Parser::Infix {
+            runner: default_parse_infix,
+            precedence: Precedence::And
+        },
+        "OR" => Parser::Infix {
+            runner: default_parse_infix,
+            precedence: Precedence::Or
+        },
+        "EQUALS" | "NOT_EQUALS" | "BIGGER_OR_EQUALS" | "BIGGER" | "SMALLER_OR_EQUALS" | "SMALLER" => Parser::Infix {
             runner: default_parse_infix,
             precedence: Precedence::Conditional
         },
         "ASSIGN" => Parser::Infix {
-            runner: default_parse_infix,
+            runner: right_parse_infix,
             precedence: Precedence::Assignment
         },
+        // a..b and a..b step s are range(a, b) and range(a, b, s)
+        "RANGE" => Parser::Infix {
+            runner: |queue, left, token, precedence| -> PartExpression {
+                let mut arguments = vec![left, parse_expression_part(queue, precedence)];
+
+                if queue.is_not_empty() && queue.get().token_type().keyword() == Some("STEP") {
+                    queue.remove();
+                    arguments.push(parse_expression_part(queue, Precedence::Range));
+                }
+
+                PartExpression::FunctionInvocation {
+                    val: Box::new(PartExpression::Identifier {
+                        val: "range".to_owned(),
+                        token: token.clone()
+                    }),
+                    arguments,
+                    token
+                }
+            },
+            precedence: Precedence::Range
+        },
         "OPEN_PARENTHESIS" => Parser::Infix {
             runner: |queue, left, token, _| -> PartExpression {
                 match left {
@@ -134,6 +227,78 @@ fn infix_parser(token: Token) -> Parser {
             },
             precedence: Precedence::FunctionInvocation
         },
+        // n! is factorial(n), after an operand ! can't be a prefix
+        "NOT" => Parser::Postfix {
+            runner: |left, token| -> PartExpression {
+                PartExpression::FunctionInvocation {
+                    val: Box::new(PartExpression::Identifier {
+                        val: "factorial".to_owned(),
+                        token: token.clone()
+                    }),
+                    arguments: vec![left],
+                    token
+                }
+            },
+            precedence: Precedence::FunctionInvocation
+        },
+        // t.0 is t[0], t.0.1 is lexed with a decimal number as its index
+        "DOT" => Parser::Infix {
+            runner: |queue, left, token, _| -> PartExpression {
+                if queue.is_empty() {
+                    token.err("Position expected");
+                }
+
+                let position = queue.peek().check_id("NUMBER", "Position expected");
+
+                position.content().split('.').fold(left, |value, index| PartExpression::Index {
+                    value: Box::new(value),
+                    index: Box::new(PartExpression::Number {
+                        val: index.parse::<BigInt>().unwrap_or_else(|_| position.err("Position expected")),
+                        token: position.clone()
+                    }),
+                    token: token.clone()
+                })
+            },
+            precedence: Precedence::FunctionInvocation
+        },
+        // xs[i] and xs[a:b], either bound of a slice can be left out
+        "OPEN_BRACKET" => Parser::Infix {
+            runner: |queue, left, token, _| -> PartExpression {
+                let bound = |queue: &mut TokenQueue, end: &str| -> Option<Box<PartExpression>> {
+                    if queue.is_not_empty() && queue.get().token_type().id().eq(end) {
+                        None
+                    } else {
+                        Some(Box::new(parse_expression_part(queue, Precedence::None)))
+                    }
+                };
+                let from = bound(queue, "COLON");
+                let expression = if queue.is_not_empty() && queue.get().token_type().id().eq("COLON") {
+                    queue.remove();
+
+                    PartExpression::Slice {
+                        value: Box::new(left),
+                        from,
+                        to: bound(queue, "CLOSE_BRACKET"),
+                        token: token.clone()
+                    }
+                } else {
+                    PartExpression::Index {
+                        value: Box::new(left),
+                        index: from.unwrap_or_else(|| token.err("Index expected")),
+                        token: token.clone()
+                    }
+                };
+
+                if queue.is_empty() {
+                    token.err_offset_with(ErrorCode::UnbalancedParentheses, "Missing CLOSE_BRACKET", 1);
+                }
+
+                queue.peek().check_id("CLOSE_BRACKET", "CLOSE_BRACKET expected");
+
+                expression
+            },
+            precedence: Precedence::FunctionInvocation
+        },
         _ => Parser::Infix {
             runner: |_, _, token, _ | -> PartExpression {
                 token.err(&format!("Unknown infix ('{}')", token.token_type().id()))
@@ -146,10 +311,128 @@ fn infix_parser(token: Token) -> Parser {
 fn prefix_parser(token: Token) -> Parser {
     Parser::Prefix {
         runner: match token.id() {
-            "MINUS" | "MULTIPLY" => |queue, t| -> PartExpression {
+            // calls, indexing and ^ belong to the operand, -f(x) is -(f(x)) and -x ^ 2 is -(x ^ 2)
+            "MINUS" | "MULTIPLY" | "NOT" => |queue, t| -> PartExpression {
                 PartExpression::PrefixOperator {
-                    prefix: t.content().to_owned(),
-                    expression: Box::new(parse_expression_part(queue, Precedence::Prefix)),
+                    prefix: spelling(&t),
+                    expression: Box::new(parse_expression_part(queue, Precedence::Product)),
+                    token: t
+                }
+            },
+            // match x | 0 -> a | 1 -> b | _ -> c
+            "MATCH" => |queue, t| -> PartExpression {
+                let value = parse_expression_part(queue, Precedence::None);
+                let mut arms = Vec::<(Option<PartExpression>, PartExpression)>::new();
+
+                while queue.is_not_empty() && queue.get().token_type().id().eq("BAR") {
+                    queue.remove();
+
+                    let pattern = if queue.is_not_empty() && queue.get().token_type().id().eq("WILDCARD") {
+                        queue.remove();
+
+                        None
+                    } else {
+                        Some(parse_expression_part(queue, Precedence::None))
+                    };
+
+                    if queue.is_empty() {
+                        t.err("Expected ARROW");
+                    }
+
+                    queue.peek().check_id("ARROW", "Expected ARROW");
+                    arms.push((pattern, parse_expression_part(queue, Precedence::None)));
+                }
+
+                if arms.is_empty() {
+                    t.err("Expected at least one arm ('| pattern -> value')");
+                }
+
+                PartExpression::Match {
+                    value: Box::new(value),
+                    arms,
+                    token: t
+                }
+            },
+            // [f(i) for i in 1..n if p(i)], for, in and if are only keywords here
+            "OPEN_BRACKET" => |queue, t| -> PartExpression {
+                let value = parse_expression_part(queue, Precedence::None);
+
+                keyword(queue, &t, "FOR");
+
+                let variable = queue.peek().check_id("IDENTIFIER", "Identifier expected").content().to_owned();
+
+                keyword(queue, &t, "IN");
+
+                let source = parse_expression_part(queue, Precedence::None);
+                let condition = if queue.is_not_empty() && queue.get().token_type().keyword() == Some("IF") {
+                    queue.remove();
+
+                    Some(Box::new(parse_expression_part(queue, Precedence::None)))
+                } else {
+                    None
+                };
+
+                if queue.is_empty() {
+                    t.err_offset_with(ErrorCode::UnbalancedParentheses, "Missing CLOSE_BRACKET", 1);
+                }
+
+                queue.peek().check_id("CLOSE_BRACKET", "CLOSE_BRACKET expected");
+
+                PartExpression::Comprehension {
+                    value: Box::new(value),
+                    variable,
+                    source: Box::new(source),
+                    condition,
+                    token: t
+                }
+            },
+            // {a, b, c} is set(a, b, c)
+            "OPEN_BRACE" => |queue, t| -> PartExpression {
+                let mut arguments = Vec::<PartExpression>::new();
+
+                loop {
+                    if queue.is_empty() {
+                        t.err_offset_with(ErrorCode::UnbalancedParentheses, "Missing CLOSE_BRACE", 1);
+                    }
+
+                    if queue.get().token_type().id().eq("CLOSE_BRACE") && arguments.is_empty() {
+                        queue.remove();
+
+                        break;
+                    }
+
+                    arguments.push(parse_expression_part(queue, Precedence::None));
+
+                    if queue.is_empty() {
+                        t.err_offset_with(ErrorCode::UnbalancedParentheses, "Missing CLOSE_BRACE", 1);
+                    }
+
+                    let next = queue.peek();
+
+                    match next.token_type().id() {
+                        "CLOSE_BRACE" => break,
+                        "COMMA" => {},
+                        _ => next.err("CLOSE_BRACE or COMMA expected")
+                    }
+                }
+
+                PartExpression::FunctionInvocation {
+                    val: Box::new(PartExpression::Identifier {
+                        val: "set".to_owned(),
+                        token: t.clone()
+                    }),
+                    arguments,
+                    token: t
+                }
+            },
+            // none is none()
+            "NONE" => |_, t| -> PartExpression {
+                PartExpression::FunctionInvocation {
+                    val: Box::new(PartExpression::Identifier {
+                        val: "none".to_owned(),
+                        token: t.clone()
+                    }),
+                    arguments: vec![],
                     token: t
                 }
             },
@@ -159,6 +442,12 @@ fn prefix_parser(token: Token) -> Parser {
                     token: t
                 }
             },
+            "STRING" => |_, t| -> PartExpression {
+                PartExpression::String {
+                    val: unescape(&t),
+                    token: t
+                }
+            },
             "IDENTIFIER" => |_, t| -> PartExpression {
                 PartExpression::Identifier {
                     val: t.content().to_owned(),
@@ -180,21 +469,59 @@ fn prefix_parser(token: Token) -> Parser {
                     }
 
                     if paras < 0 {
-                        next.err("Too many OPEN_PARENTHESIS");
+                        next.err_with(ErrorCode::UnbalancedParentheses, "Too many OPEN_PARENTHESIS");
                     } else if paras == 0 {
                         if expr_queue_vec.is_empty() {
                             next.err("Empty block");
                         }
 
                         let mut expr_queue = token_queue(expr_queue_vec);
+                        let first = parse_expression_part(&mut expr_queue, Precedence::None);
+
+                        // (a; b; c) is a block, a trailing ; is allowed
+                        if expr_queue.is_not_empty() && expr_queue.get().token_type().id().eq("SEMICOLON") {
+                            let mut expressions = vec![first];
+
+                            while expr_queue.is_not_empty() {
+                                expr_queue.peek().check_id("SEMICOLON", "CLOSE_PARENTHESIS or SEMICOLON expected");
 
-                        return parse_expression_part(&mut expr_queue, Precedence::None);
+                                if expr_queue.is_not_empty() {
+                                    expressions.push(parse_expression_part(&mut expr_queue, Precedence::None));
+                                }
+                            }
+
+                            return PartExpression::Block {
+                                expressions,
+                                token: t
+                            };
+                        }
+
+                        if expr_queue.is_empty() || expr_queue.get().token_type().id().ne("COMMA") {
+                            return first;
+                        }
+
+                        // (a, b, c) is tuple(a, b, c)
+                        let mut arguments = vec![first];
+
+                        while expr_queue.is_not_empty() {
+                            expr_queue.peek().check_id("COMMA", "CLOSE_PARENTHESIS or COMMA expected");
+                            arguments.push(parse_expression_part(&mut expr_queue, Precedence::None));
+                        }
+
+                        return PartExpression::FunctionInvocation {
+                            val: Box::new(PartExpression::Identifier {
+                                val: "tuple".to_owned(),
+                                token: t.clone()
+                            }),
+                            arguments,
+                            token: t
+                        };
                     }
 
                     expr_queue_vec.push(next);
                 }
 
-                t.err_offset("Missing CLOSING_PARENTHESIS", 1);
+                t.err_offset_with(ErrorCode::UnbalancedParentheses, "Missing CLOSING_PARENTHESIS", 1);
             },
             _ => | _, t| -> PartExpression {
                 t.err(&format!("Unknown prefix ('{}')", t.token_type().id()));
@@ -203,47 +530,88 @@ fn prefix_parser(token: Token) -> Parser {
     }
 }
 
+// words that are only keywords in some places (like for in a list comprehension) are identifiers to the lexer
+fn keyword(queue: &mut TokenQueue, start: &LexedToken, keyword: &str) {
+    if queue.is_empty() {
+        start.err_offset_with(ErrorCode::UnbalancedParentheses, "Missing CLOSE_BRACKET", 1);
+    }
+
+    let next = queue.peek();
+
+    if next.token_type().keyword() != Some(keyword) {
+        next.err(&format!("Expected {}", keyword));
+    }
+}
+
+// the token still has its quotes, escapes are \" \\ \n \t and \$
+pub(crate) fn unescape(token: &LexedToken) -> String {
+    let content = token.content();
+    let mut out = String::new();
+    let mut chars = content[1..content.len() - 1].chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some(c @ ('"' | '\\' | '$')) => out.push(c),
+            _ => token.err("Invalid escape sequence in string")
+        }
+    }
+
+    out
+}
+
 pub fn actual_parse_expression(expr: PartExpression, variables: &Vec<Variable>, functions: &Vec<Function>) -> Expression {
-    return match expr {
+    match expr {
         PartExpression::Number { val, .. } => {
             Expression::NumberValue {
-                value: BigInt::from(val)
+                value: val
+            }
+        },
+        PartExpression::String { val, .. } => {
+            Expression::Value {
+                value: Value::String(val)
             }
         },
         PartExpression::Identifier { val, token } => {
-            if variables.into_iter().any(|var| var.name.eq(&val)) {
+            if variables.iter().any(|var| var.name.eq(&val)) {
                 return Expression::VariableAccess {
                     variable: val
                 };
             }
 
-            token.err("Variable not found");
+            token.err_with(ErrorCode::UndefinedVariable, &format!("Variable not found{}", suggestion(&val, variables.iter().map(|v| &v.name))));
         },
         PartExpression::PrefixOperator { prefix, expression, token } => {
             match prefix.as_str() {
                 "-" => {
-                    let expression = actual_parse_expression(*expression.clone(), &variables.clone(), &functions.clone());
-
+                    Expression::Negate {
+                        value: Arc::new(actual_parse_expression(*expression.clone(), &variables.clone(), &functions.clone())),
+                        span: Some(Arc::new(token.span()))
+                    }
+                },
+                "!" => { // 1 for 0, 0 for everything else
                     Expression::Math {
-                        var1: Box::new(expression.clone()),
-                        var2: Box::new(Expression::Math {
-                            var1: Box::new(expression),
-                            var2: Box::new(Expression::NumberValue {
-                                value: BigInt::from(2)
-                            }),
-                            math: MathType::Multiply
+                        var1: Arc::new(actual_parse_expression(*expression.clone(), &variables.clone(), &functions.clone())),
+                        var2: Arc::new(Expression::NumberValue {
+                            value: BigInt::from(0)
                         }),
-                        math: MathType::Subtract
+                        math: MathType::Equals,
+                        span: Some(Arc::new(token.span()))
                     }
-                }
+                },
                 "*" => {
                     let expression = actual_parse_expression(*expression.clone(), &variables.clone(), &functions.clone());
-                    let var;
-
-                    match expression {
-                        Expression::VariableAccess { variable } => var = variable,
-                        _ => token.err("Expected variable access on the left")
-                    }
+                    let var = match expression {
+                        Expression::VariableAccess { variable } => variable,
+                        _ => token.err_with(ErrorCode::InvalidAssignment, "Expected variable access on the left")
+                    };
 
                     Expression::Pointer {
                         to: var
@@ -254,11 +622,12 @@ pub fn actual_parse_expression(expr: PartExpression, variables: &Vec<Variable>,
         },
         PartExpression::InfixOperator { operator, left, right, token } => {
             match operator.as_str() {
-                "+" | "-" | "*" | "/" | "==" | "<" | ">" | "=!" | "<=" | ">=" | "^" => {
+                "+" | "-" | "*" | "/" | "//" | "==" | "<" | ">" | "=!" | "<=" | ">=" | "^" | "&&" | "||" => {
                     Expression::Math {
-                        var1: Box::new(actual_parse_expression(*left.clone(), &variables.clone(), &functions.clone())),
-                        var2: Box::new(actual_parse_expression(*right.clone(), &variables.clone(), &functions.clone())),
-                        math: MathType::of(operator)
+                        var1: Arc::new(actual_parse_expression(*left.clone(), &variables.clone(), &functions.clone())),
+                        var2: Arc::new(actual_parse_expression(*right.clone(), &variables.clone(), &functions.clone())),
+                        math: MathType::of(operator),
+                        span: Some(Arc::new(token.span()))
                     }
                 },
                 "=" => {
@@ -266,19 +635,19 @@ pub fn actual_parse_expression(expr: PartExpression, variables: &Vec<Variable>,
 
                     match actual_left {
                         Expression::VariableAccess { .. } => {},
-                        _ => token.err("Expected variable access on left side of infix operator")
+                        _ => token.err_with(ErrorCode::InvalidAssignment, "Expected variable access on left side of infix operator")
                     }
 
                     let var = actual_left.variable_access_variable().to_owned();
-                    let actual_var = variables.into_iter().find(|v| v.name.eq(&var)).unwrap();
+                    let actual_var = variables.iter().find(|v| v.name.eq(&var)).unwrap();
 
                     if actual_var.constant {
-                        token.err("Cannot reassign constant");
+                        token.err_with(ErrorCode::ConstantReassignment, "Cannot reassign constant");
                     }
 
                     Expression::VariableAssignment {
                         variable: var,
-                        value: Box::new(actual_parse_expression(*right.clone(), &variables.clone(), &functions.clone()))
+                        value: Arc::new(actual_parse_expression(*right.clone(), &variables.clone(), &functions.clone()))
                     }
                 },
                 _ => token.err("Unknown infix")
@@ -291,17 +660,117 @@ pub fn actual_parse_expression(expr: PartExpression, variables: &Vec<Variable>,
             };
             let args = arguments.into_iter().map(|a| actual_parse_expression(a, variables, functions)).collect::<Vec<Expression>>();
 
-            if functions.into_iter().find(|f| f.name.eq(&name) && f.parameters.len() == args.len()).is_none() {
-                val.token().err("Function not found");
+            if !functions.iter().any(|f| f.name.eq(&name) && f.accepts(args.len())) {
+                let definitions = functions.iter().filter(|f| f.name.eq(&name)).map(|f| (f.parameters.len(), f.variadic)).collect::<Vec<(usize, bool)>>();
+
+                if !definitions.is_empty() {
+                    val.token().err_with(ErrorCode::ArityMismatch, &arity_mismatch(&name, &definitions, args.len()));
+                }
+
+                val.token().err_with(ErrorCode::UndefinedFunction, &format!("Function not found{}", suggestion(&name, functions.iter().filter(|f| f.accepts(args.len())).map(|f| &f.name))));
             }
 
             Expression::FunctionInvocation {
                 function: name,
-                arguments: args
+                arguments: args,
+                span: Some(Arc::new(val.token().span()))
+            }
+        },
+        PartExpression::Match { value, arms, .. } => {
+            Expression::Match {
+                value: Arc::new(actual_parse_expression(*value, variables, functions)),
+                arms: arms.into_iter().map(|(pattern, value)| MatchArm {
+                    pattern: pattern.map(|p| actual_parse_expression(p, variables, functions)),
+                    value: actual_parse_expression(value, variables, functions)
+                }).collect()
+            }
+        },
+        PartExpression::Comprehension { value, variable, source, condition, .. } => {
+            let mut scope = variables.clone();
+
+            scope.push(fake_variable(variable.clone()));
+
+            Expression::Comprehension {
+                value: Arc::new(actual_parse_expression(*value, &scope, functions)),
+                source: Arc::new(actual_parse_expression(*source, variables, functions)),
+                condition: condition.map(|c| Arc::new(actual_parse_expression(*c, &scope, functions))),
+                variable
+            }
+        },
+        PartExpression::Where { variable, value, body, .. } => {
+            let mut scope = variables.clone();
+
+            scope.push(fake_variable(variable.clone()));
+
+            Expression::Where {
+                value: Arc::new(actual_parse_expression(*value, variables, functions)),
+                body: Arc::new(actual_parse_expression(*body, &scope, functions)),
+                variable
+            }
+        },
+        PartExpression::Block { expressions, .. } => {
+            Expression::Block {
+                expressions: expressions.into_iter().map(|expr| actual_parse_expression(expr, variables, functions)).collect()
+            }
+        },
+        PartExpression::Index { value, index, token } => {
+            Expression::Index {
+                value: Arc::new(actual_parse_expression(*value, variables, functions)),
+                index: Arc::new(actual_parse_expression(*index, variables, functions)),
+                span: token.span()
+            }
+        },
+        PartExpression::Slice { value, from, to, token } => {
+            Expression::Slice {
+                value: Arc::new(actual_parse_expression(*value, variables, functions)),
+                from: from.map(|from| Arc::new(actual_parse_expression(*from, variables, functions))),
+                to: to.map(|to| Arc::new(actual_parse_expression(*to, variables, functions))),
+                span: token.span()
+            }
+        },
+        PartExpression::Conditional { condition, then, otherwise, .. } => {
+            Expression::Conditional {
+                condition: Arc::new(actual_parse_expression(*condition, variables, functions)),
+                then: Arc::new(actual_parse_expression(*then, variables, functions)),
+                otherwise: Arc::new(actual_parse_expression(*otherwise, variables, functions))
             }
         },
         PartExpression::None | PartExpression::Comment => panic!("Can't parse PartExpression::None | PartExpression::Comment")
-    };
+    }
+}
+
+// the closest known name if it's only a typo away, names that are too different aren't suggested
+fn suggestion<'a>(name: &str, known: impl Iterator<Item = &'a String>) -> String {
+    let limit = (name.chars().count() / 3).max(1);
+    let closest = known.map(|k| (distance(name, k), k)).filter(|(d, _)| *d <= limit).min_by_key(|(d, _)| *d);
+
+    closest.map(|(_, k)| format!(" (did you mean `{}`?)", k)).unwrap_or_default()
+}
+
+// edit distance in characters, swapping two neighbouring characters (fbi for fib) counts as one edit
+fn distance(a: &str, b: &str) -> usize {
+    let (a, b) = (a.chars().collect::<Vec<char>>(), b.chars().collect::<Vec<char>>());
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+
+    d[0] = (0..=b.len()).collect();
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = (a[i - 1] != b[j - 1]) as usize;
+
+            d[i][j] = (d[i - 1][j - 1] + cost).min(d[i - 1][j] + 1).min(d[i][j - 1] + 1);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+
+    d[a.len()][b.len()]
 }
 
 #[derive(PartialEq, Debug)]
@@ -312,6 +781,10 @@ pub enum PartExpression {
         val: BigInt,
         token: LexedToken
     },
+    String {
+        val: String,
+        token: LexedToken
+    },
     Identifier {
         val: String,
         token: LexedToken
@@ -331,6 +804,45 @@ pub enum PartExpression {
         val: Box<PartExpression>,
         arguments: Vec<PartExpression>,
         token: LexedToken
+    },
+    Match {
+        value: Box<PartExpression>,
+        arms: Vec<(Option<PartExpression>, PartExpression)>, // None is the _ pattern
+        token: LexedToken
+    },
+    Comprehension {
+        value: Box<PartExpression>,
+        variable: String,
+        source: Box<PartExpression>,
+        condition: Option<Box<PartExpression>>,
+        token: LexedToken
+    },
+    Where {
+        variable: String,
+        value: Box<PartExpression>,
+        body: Box<PartExpression>,
+        token: LexedToken
+    },
+    Block {
+        expressions: Vec<PartExpression>,
+        token: LexedToken
+    },
+    Index {
+        value: Box<PartExpression>,
+        index: Box<PartExpression>,
+        token: LexedToken
+    },
+    Slice {
+        value: Box<PartExpression>,
+        from: Option<Box<PartExpression>>,
+        to: Option<Box<PartExpression>>,
+        token: LexedToken
+    },
+    Conditional {
+        condition: Box<PartExpression>,
+        then: Box<PartExpression>,
+        otherwise: Box<PartExpression>,
+        token: LexedToken
     }
 }
 
@@ -343,6 +855,12 @@ impl Clone for PartExpression {
                     token: token.clone()
                 }
             }
+            PartExpression::String { val, token } => {
+                PartExpression::String {
+                    val: val.to_owned(),
+                    token: token.clone()
+                }
+            }
             PartExpression::Identifier { val, token } => {
                 PartExpression::Identifier {
                     val: val.to_owned(),
@@ -371,6 +889,59 @@ impl Clone for PartExpression {
                     token: token.clone()
                 }
             },
+            PartExpression::Match { value, arms, token } => {
+                PartExpression::Match {
+                    value: Box::new(*value.clone()),
+                    arms: arms.to_vec(),
+                    token: token.clone()
+                }
+            },
+            PartExpression::Comprehension { value, variable, source, condition, token } => {
+                PartExpression::Comprehension {
+                    value: Box::new(*value.clone()),
+                    variable: variable.to_owned(),
+                    source: Box::new(*source.clone()),
+                    condition: condition.clone(),
+                    token: token.clone()
+                }
+            },
+            PartExpression::Where { variable, value, body, token } => {
+                PartExpression::Where {
+                    variable: variable.to_owned(),
+                    value: Box::new(*value.clone()),
+                    body: Box::new(*body.clone()),
+                    token: token.clone()
+                }
+            },
+            PartExpression::Block { expressions, token } => {
+                PartExpression::Block {
+                    expressions: expressions.to_vec(),
+                    token: token.clone()
+                }
+            },
+            PartExpression::Index { value, index, token } => {
+                PartExpression::Index {
+                    value: Box::new(*value.clone()),
+                    index: Box::new(*index.clone()),
+                    token: token.clone()
+                }
+            },
+            PartExpression::Slice { value, from, to, token } => {
+                PartExpression::Slice {
+                    value: Box::new(*value.clone()),
+                    from: from.clone(),
+                    to: to.clone(),
+                    token: token.clone()
+                }
+            },
+            PartExpression::Conditional { condition, then, otherwise, token } => {
+                PartExpression::Conditional {
+                    condition: Box::new(*condition.clone()),
+                    then: Box::new(*then.clone()),
+                    otherwise: Box::new(*otherwise.clone()),
+                    token: token.clone()
+                }
+            },
             PartExpression::None => PartExpression::None,
             PartExpression::Comment => PartExpression::Comment
         }
@@ -381,10 +952,18 @@ impl PartExpression {
     fn token(&self) -> &LexedToken {
         match self {
             PartExpression::Number { token, .. } => token,
+            PartExpression::String { token, .. } => token,
             PartExpression::Identifier { token, .. } => token,
             PartExpression::PrefixOperator { token, .. } => token,
             PartExpression::InfixOperator { token, .. } => token,
             PartExpression::FunctionInvocation { token, .. } => token,
+            PartExpression::Match { token, .. } => token,
+            PartExpression::Comprehension { token, .. } => token,
+            PartExpression::Where { token, .. } => token,
+            PartExpression::Block { token, .. } => token,
+            PartExpression::Index { token, .. } => token,
+            PartExpression::Slice { token, .. } => token,
+            PartExpression::Conditional { token, .. } => token,
             _ => panic!("token(&self) not available for this")
         }
     }
@@ -394,9 +973,14 @@ impl PartExpression {
 pub enum Precedence {
     None,
     Assignment,
+    Ternary,
+    Or,
+    And,
     Conditional,
+    Range,
     Sum,
     Product,
+    Exponent,
     FunctionInvocation,
     Prefix
 }
@@ -405,22 +989,27 @@ impl Precedence {
     fn entries(&self) -> HashMap<u8, Precedence> {
         let mut map = HashMap::<u8, Precedence>::new();
 
-        for precedence in vec![Precedence::None, Precedence::Conditional, Precedence::Sum, Precedence::Product, Precedence::FunctionInvocation, Precedence::Prefix] {
+        for precedence in [Precedence::None, Precedence::Assignment, Precedence::Ternary, Precedence::Or, Precedence::And, Precedence::Conditional, Precedence::Range, Precedence::Sum, Precedence::Product, Precedence::Exponent, Precedence::FunctionInvocation, Precedence::Prefix] {
             map.insert(precedence.order(), precedence);
         }
 
-        return map;
+        map
     }
 
     fn order(&self) -> u8 {
         match *self {
             Precedence::None => 0,
             Precedence::Assignment => 1,
-            Precedence::Conditional => 2,
-            Precedence::Sum => 3,
-            Precedence::Product => 4,
-            Precedence::FunctionInvocation => 5,
-            Precedence::Prefix => 6
+            Precedence::Ternary => 2,
+            Precedence::Or => 3,
+            Precedence::And => 4,
+            Precedence::Conditional => 5,
+            Precedence::Range => 6,
+            Precedence::Sum => 7,
+            Precedence::Product => 8,
+            Precedence::Exponent => 9,
+            Precedence::FunctionInvocation => 10,
+            Precedence::Prefix => 11
         }
     }
 
@@ -440,9 +1029,14 @@ impl Precedence {
     fn clone(&self) -> Precedence {
         match *self {
             Precedence::None => Precedence::None,
+            Precedence::Ternary => Precedence::Ternary,
+            Precedence::Or => Precedence::Or,
+            Precedence::And => Precedence::And,
             Precedence::Conditional => Precedence::Conditional,
+            Precedence::Range => Precedence::Range,
             Precedence::Sum => Precedence::Sum,
             Precedence::Product => Precedence::Product,
+            Precedence::Exponent => Precedence::Exponent,
             Precedence::FunctionInvocation => Precedence::FunctionInvocation,
             Precedence::Prefix => Precedence::Prefix,
             Precedence::Assignment => Precedence::Assignment
@@ -0,0 +1,236 @@
+use crate::lexer::LexedToken;
+use crate::error::ErrorCode;
+use crate::parser::nesting;
+use std::iter::Peekable;
+use std::vec::IntoIter;
+
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct Macro {
+    pub name: String,
+    pub parameters: Vec<String>,
+    pub template: Vec<LexedToken>
+}
+
+// collects all macro definitions and substitutes their invocations before the actual parsing happens
+pub fn expand_macros(tokens: Vec<LexedToken>) -> Vec<LexedToken> {
+    let mut macros = Vec::<Macro>::new();
+    let mut rest = Vec::<LexedToken>::new();
+    let mut iter = tokens.into_iter().peekable();
+
+    while let Some(next) = iter.next() {
+        if next.token_type().id().eq("MACRO") {
+            macros.push(read_macro(next, &mut iter));
+        } else {
+            rest.push(next);
+        }
+    }
+
+    if macros.is_empty() {
+        return rest;
+    }
+
+    expand(rest, &macros, 0, &mut 0)
+}
+
+fn read_macro(keyword: LexedToken, iter: &mut Peekable<IntoIter<LexedToken>>) -> Macro {
+    let name = iter.next().unwrap_or_else(|| keyword.err("Expected identifier")).check_id("IDENTIFIER", "Expected identifier");
+
+    iter.next().unwrap_or_else(|| name.err("Expected (")).check_id("OPEN_PARENTHESIS", "Expected (");
+
+    let mut parameters = Vec::<String>::new();
+
+    loop {
+        let next = iter.next().unwrap_or_else(|| name.err_with(ErrorCode::UnbalancedParentheses, "Missing CLOSING_PARENTHESIS"));
+
+        match next.token_type().id() {
+            "CLOSE_PARENTHESIS" if parameters.is_empty() => break,
+            "IDENTIFIER" => {
+                if parameters.contains(next.content()) {
+                    next.err("Duplicate macro parameter");
+                }
+
+                parameters.push(next.content().to_owned());
+
+                let separator = iter.next().unwrap_or_else(|| next.err_with(ErrorCode::UnbalancedParentheses, "Missing CLOSING_PARENTHESIS"));
+
+                match separator.token_type().id() {
+                    "CLOSE_PARENTHESIS" => break,
+                    "COMMA" => {},
+                    _ => separator.err("CLOSE_PARENTHESIS or COMMA expected")
+                }
+            },
+            _ => next.err("Identifier expected")
+        }
+    }
+
+    let assign = iter.next().unwrap_or_else(|| name.err("Expected =")).check_id("ASSIGN", "Expected =");
+    let mut template = Vec::<LexedToken>::new();
+    let mut lines_left = 1;
+
+    while lines_left > 0 {
+        let next = match iter.peek() {
+            Some(next) => next,
+            None => break
+        };
+
+        match next.token_type().id() {
+            "PIPE" => lines_left += 1,
+            "NEW_LINE" | "SEMICOLON" => lines_left -= 1,
+            _ => template.push(next.clone())
+        }
+
+        if lines_left > 0 {
+            iter.next();
+        }
+    }
+
+    if template.is_empty() {
+        assign.err("Expected template");
+    }
+
+    Macro {
+        name: name.content().to_owned(),
+        parameters,
+        template
+    }
+}
+
+// names is how many binders were renamed so far, every expansion gets fresh ones
+fn expand(tokens: Vec<LexedToken>, macros: &[Macro], depth: usize, names: &mut usize) -> Vec<LexedToken> {
+    let mut result = Vec::<LexedToken>::new();
+    let mut i = 0;
+
+    while i < tokens.len() {
+        let next = &tokens[i];
+        let invocation = next.token_type().id().eq("IDENTIFIER")
+            && tokens.get(i + 1).is_some_and(|t| t.token_type().id().eq("OPEN_PARENTHESIS"))
+            && macros.iter().any(|m| m.name.eq(next.content()));
+
+        if !invocation {
+            result.push(next.clone());
+            i += 1;
+
+            continue;
+        }
+
+        if depth >= MAX_DEPTH {
+            next.err_with(ErrorCode::InvalidMacro, "Macro expansion too deep (recursive macro?)");
+        }
+
+        let open = tokens[i + 1].clone();
+        let (arguments, close_index) = read_arguments(&tokens, i + 1);
+        let close = tokens[close_index].clone();
+        let found = macros.iter().find(|m| m.name.eq(next.content()) && m.parameters.len() == arguments.len()).unwrap_or_else(|| {
+            next.err_with(ErrorCode::InvalidMacro, &format!("Macro takes {} argument(s), {} were supplied", macros.iter().find(|m| m.name.eq(next.content())).unwrap().parameters.len(), arguments.len()))
+        });
+        let mut expansion = vec![open.clone()]; // parenthesize the expansion and every argument so substitution can't change precedence
+        let binders = binders(found);
+
+        *names += 1;
+
+        for t in &found.template {
+            let identifier = t.token_type().id().eq("IDENTIFIER");
+
+            match found.parameters.iter().position(|p| identifier && p.eq(t.content())) {
+                Some(index) => {
+                    expansion.push(open.clone());
+                    expansion.extend(arguments[index].iter().cloned());
+                    expansion.push(close.clone());
+                },
+                None if identifier && binders.contains(t.content()) => expansion.push(t.renamed(format!("{}__{}", t.content(), names))),
+                None => expansion.push(t.clone())
+            }
+        }
+
+        expansion.push(close);
+        result.extend(expand(expansion, macros, depth + 1, names));
+
+        i = close_index + 1;
+    }
+
+    result
+}
+
+// the variables the template binds itself (for i in, where a = ..., b = ...), they're renamed so that arguments using the same names aren't captured
+fn binders(found: &Macro) -> Vec<String> {
+    let template = &found.template;
+    let mut binders = Vec::<String>::new();
+    let mut wherepart = None;
+    let mut depth = 0;
+
+    for (i, t) in template.iter().enumerate() {
+        let id = t.token_type().id();
+        let next = template.get(i + 1).filter(|n| n.token_type().id().eq("IDENTIFIER") && n.token_type().keyword().is_none());
+
+        depth = nesting(id, depth);
+
+        let binds = match id {
+            "WHERE" => {
+                wherepart = Some(depth);
+
+                true
+            },
+            "COMMA" => wherepart == Some(depth) && template.get(i + 2).is_some_and(|a| a.token_type().id().eq("ASSIGN")),
+            _ => t.token_type().keyword() == Some("FOR")
+        };
+
+        if let Some(next) = next.filter(|_| binds) {
+            if !found.parameters.contains(next.content()) && !binders.contains(next.content()) {
+                binders.push(next.content().to_owned());
+            }
+        }
+    }
+
+    binders
+}
+
+fn read_arguments(tokens: &[LexedToken], open_index: usize) -> (Vec<Vec<LexedToken>>, usize) {
+    let mut arguments = Vec::<Vec<LexedToken>>::new();
+    let mut current = Vec::<LexedToken>::new();
+    let mut depth = 0; // brackets and braces too, commas in lists and sets don't separate arguments
+    let mut continued = false;
+
+    for (i, t) in tokens.iter().enumerate().skip(open_index + 1) {
+        let id = t.token_type().id();
+
+        match id {
+            "PIPE" => {
+                continued = true;
+
+                continue;
+            },
+            "NEW_LINE" if continued => {
+                continued = false;
+
+                continue;
+            },
+            "CLOSE_PARENTHESIS" if depth == 0 => {
+                if !current.is_empty() {
+                    arguments.push(current);
+                } else if !arguments.is_empty() {
+                    t.err("Expected argument");
+                }
+
+                return (arguments, i);
+            },
+            "COMMA" if depth == 0 => {
+                if current.is_empty() {
+                    t.err("Expected argument");
+                }
+
+                arguments.push(current);
+                current = vec![];
+
+                continue;
+            },
+            "NEW_LINE" => t.err_with(ErrorCode::UnbalancedParentheses, "Missing CLOSING_PARENTHESIS"),
+            _ => depth = nesting(id, depth)
+        }
+
+        current.push(t.clone());
+    }
+
+    tokens[open_index].err_offset("Missing CLOSING_PARENTHESIS", 1)
+}
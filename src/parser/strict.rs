@@ -0,0 +1,124 @@
+use crate::ast::{AST, Expression};
+use crate::error::ErrorCode;
+use crate::lexer::LexedToken;
+
+// the tokens are the keywords of the definitions and the first tokens of the loose expressions, in the same order as in the AST
+pub fn check(ast: &AST, variables: &[LexedToken], functions: &[LexedToken], loose_expressions: &[LexedToken]) {
+    for (i, token) in variables.iter().enumerate() {
+        let v = &ast.variables[i];
+
+        if ast.variables[..i].iter().any(|other| other.name.eq(&v.name)) {
+            token.err_with(ErrorCode::StrictViolation, &format!("Variable '{}' is already defined", v.name));
+        }
+    }
+
+    let externals = ast.functions.len() - functions.len(); // externals come first
+
+    for (i, token) in functions.iter().enumerate() {
+        let f = &ast.functions[externals + i];
+
+        if ast.functions[..externals + i].iter().any(|other| other.name.eq(&f.name) && other.variadic == f.variadic && other.parameters.len() == f.parameters.len()) {
+            token.err_with(ErrorCode::StrictViolation, &format!("Function '{}' with {} parameter(s) is already defined", f.name, f.parameters.len()));
+        }
+
+        if let Some(parameter) = f.parameters.iter().find(|p| ast.variables.iter().any(|v| v.name.eq(*p))) {
+            token.err_with(ErrorCode::StrictViolation, &format!("Parameter '{}' of '{}' shadows the variable '{}'", parameter, f.name, parameter));
+        }
+
+        if let Some(global) = global(&f.definition, &f.parameters, ast) {
+            token.err_with(ErrorCode::StrictViolation, &format!("Function '{}' uses the global variable '{}' (pass it as a parameter or make it const)", f.name, global));
+        }
+    }
+
+    for (expr, token) in ast.loose_expressions.iter().zip(loose_expressions) {
+        match expr {
+            Expression::FunctionInvocation { .. } | Expression::VariableAssignment { .. } => {},
+            _ => token.err_with(ErrorCode::StrictViolation, "Discarded value (loose expressions have to be calls or assignments)")
+        }
+    }
+}
+
+// the first non constant global a function body reads or assigns
+fn global<'a>(expr: &'a Expression, parameters: &[String], ast: &AST) -> Option<&'a String> {
+    let own = match expr {
+        Expression::VariableAccess { variable } | Expression::Pointer { to: variable } | Expression::VariableAssignment { variable, .. } => Some(variable),
+        _ => None
+    }.filter(|v| !parameters.contains(v) && ast.variables.iter().any(|g| g.name.eq(*v) && !g.constant));
+
+    own.or_else(|| match expr {
+        Expression::Negate { value, .. } => global(value, parameters, ast),
+        Expression::Conditional { condition, then, otherwise } => global(condition, parameters, ast).or_else(|| global(then, parameters, ast)).or_else(|| global(otherwise, parameters, ast)),
+        Expression::Math { var1, var2, .. } => global(var1, parameters, ast).or_else(|| global(var2, parameters, ast)),
+        Expression::FunctionInvocation { arguments, .. } => arguments.iter().find_map(|arg| global(arg, parameters, ast)),
+        Expression::VariableAssignment { value, .. } => global(value, parameters, ast),
+        Expression::Match { value, arms } => global(value, parameters, ast).or_else(|| arms.iter().find_map(|arm| {
+            arm.pattern.as_ref().and_then(|pattern| global(pattern, parameters, ast)).or_else(|| global(&arm.value, parameters, ast))
+        })),
+        Expression::Comprehension { value, variable, source, condition } => {
+            let scope = parameters.iter().cloned().chain(Some(variable.clone())).collect::<Vec<String>>();
+
+            global(source, parameters, ast).or_else(|| global(value, &scope, ast)).or_else(|| condition.as_ref().and_then(|c| global(c, &scope, ast)))
+        },
+        Expression::Where { variable, value, body } => {
+            let scope = parameters.iter().cloned().chain(Some(variable.clone())).collect::<Vec<String>>();
+
+            global(value, parameters, ast).or_else(|| global(body, &scope, ast))
+        },
+        Expression::Block { expressions } => expressions.iter().find_map(|expr| global(expr, parameters, ast)),
+        Expression::Index { value, index, .. } => global(value, parameters, ast).or_else(|| global(index, parameters, ast)),
+        Expression::Slice { value, from, to, .. } => global(value, parameters, ast).or_else(|| from.iter().chain(to).find_map(|bound| global(bound, parameters, ast))),
+        _ => None
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::default_externals;
+    use crate::error::{ErrorCode, catch};
+    use crate::lexer::{full_lex, LexerData};
+    use crate::parser::parse_with;
+
+    // the message of the first violation, None if the source is fine
+    fn violation(source: &str) -> Option<String> {
+        let tokens = full_lex(source.to_owned(), "<strict>".to_owned(), "#".to_owned(), &LexerData::default());
+
+        catch(|| {
+            parse_with(tokens, default_externals(), true);
+        }).err().map(|e| {
+            assert_eq!(e.code(), ErrorCode::StrictViolation);
+
+            e.message().to_owned()
+        })
+    }
+
+    #[test]
+    fn allowed() {
+        assert_eq!(violation("const c = 2\nlet t = 0\ndefine f(n) = n * c\nt = f(t)\nprintln(f(3))\n"), None);
+    }
+
+    #[test]
+    fn redefinitions() {
+        assert_eq!(violation("let a = 1\nlet a = 2\n"), Some("Variable 'a' is already defined".to_owned()));
+        assert_eq!(violation("define f(x) = x\ndefine f(y) = y\n"), Some("Function 'f' with 1 parameter(s) is already defined".to_owned()));
+        assert_eq!(violation("define f(x) = x\ndefine f(x, y) = y\n"), None);
+    }
+
+    #[test]
+    fn shadowing() {
+        assert_eq!(violation("const x = 1\ndefine f(x) = x\n"), Some("Parameter 'x' of 'f' shadows the variable 'x'".to_owned()));
+    }
+
+    #[test]
+    fn globals() {
+        assert_eq!(violation("let g = 1\ndefine f(x) = x + g\n"), Some("Function 'f' uses the global variable 'g' (pass it as a parameter or make it const)".to_owned()));
+        assert_eq!(violation("let g = 1\ndefine f(x) = [i + g for i in 1..x]\n"), Some("Function 'f' uses the global variable 'g' (pass it as a parameter or make it const)".to_owned()));
+        assert_eq!(violation("let g = 1\ndefine f(x) = y where y = g\n"), Some("Function 'f' uses the global variable 'g' (pass it as a parameter or make it const)".to_owned()));
+        assert_eq!(violation("let g = 1\ndefine f(x) = g where g = x\n"), None);
+    }
+
+    #[test]
+    fn discarded_values() {
+        assert_eq!(violation("1 + 2\n"), Some("Discarded value (loose expressions have to be calls or assignments)".to_owned()));
+        assert_eq!(violation("println(1 + 2)\n"), None);
+    }
+}
@@ -0,0 +1,47 @@
+const WIDTH: f64 = 640.0;
+const HEIGHT: f64 = 400.0;
+const MARGIN: f64 = 50.0;
+
+// line chart of the points (sorted by x), the axes are labeled with the ranges of the values
+pub fn svg(title: &str, points: &[(f64, f64)]) -> String {
+    let (x_min, x_max) = range(points.iter().map(|p| p.0));
+    let (y_min, y_max) = range(points.iter().map(|p| p.1));
+    let x = |v: f64| MARGIN + (v - x_min) / (x_max - x_min) * (WIDTH - 2.0 * MARGIN);
+    let y = |v: f64| HEIGHT - MARGIN - (v - y_min) / (y_max - y_min) * (HEIGHT - 2.0 * MARGIN);
+    let mut out = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\" font-family=\"monospace\" font-size=\"12\">\n", WIDTH, HEIGHT);
+
+    out.push_str(&format!("  <rect width=\"{}\" height=\"{}\" fill=\"white\"/>\n", WIDTH, HEIGHT));
+    out.push_str(&format!("  <text x=\"{}\" y=\"{}\" text-anchor=\"middle\" font-size=\"14\">{}</text>\n", WIDTH / 2.0, MARGIN / 2.0, escape(title)));
+
+    // axes, the x axis is drawn at y = 0 if that's in range
+    let axis = if y_min <= 0.0 && 0.0 <= y_max { y(0.0) } else { HEIGHT - MARGIN };
+
+    out.push_str(&format!("  <line x1=\"{}\" y1=\"{:.2}\" x2=\"{}\" y2=\"{:.2}\" stroke=\"gray\"/>\n", MARGIN, axis, WIDTH - MARGIN, axis));
+    out.push_str(&format!("  <line x1=\"{0}\" y1=\"{1}\" x2=\"{0}\" y2=\"{2}\" stroke=\"gray\"/>\n", MARGIN, MARGIN, HEIGHT - MARGIN));
+    out.push_str(&format!("  <text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}</text>\n", MARGIN, HEIGHT - MARGIN + 20.0, x_min));
+    out.push_str(&format!("  <text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}</text>\n", WIDTH - MARGIN, HEIGHT - MARGIN + 20.0, x_max));
+    out.push_str(&format!("  <text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}</text>\n", MARGIN - 5.0, HEIGHT - MARGIN + 4.0, y_min));
+    out.push_str(&format!("  <text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}</text>\n", MARGIN - 5.0, MARGIN + 4.0, y_max));
+
+    let line = points.iter().map(|(px, py)| format!("{:.2},{:.2}", x(*px), y(*py))).collect::<Vec<String>>().join(" ");
+
+    out.push_str(&format!("  <polyline points=\"{}\" fill=\"none\" stroke=\"steelblue\" stroke-width=\"2\"/>\n", line));
+    out.push_str("</svg>\n");
+
+    out
+}
+
+// widened if all values are the same so that the scale doesn't divide by zero
+fn range(values: impl Iterator<Item = f64>) -> (f64, f64) {
+    let (min, max) = values.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| (min.min(v), max.max(v)));
+
+    if min < max {
+        (min, max)
+    } else {
+        (min - 1.0, max + 1.0)
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
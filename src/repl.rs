@@ -0,0 +1,181 @@
+use crate::context::Context;
+use crate::lexer::{spans, LexerData};
+use crate::parser::{continues, nesting};
+use crate::style;
+use rustyline::{Editor, Helper};
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use std::borrow::Cow;
+use std::path::PathBuf;
+use std::env;
+
+const PROMPT: &str = ">> ";
+const CONTINUATION_PROMPT: &str = ".. ";
+
+const KEYWORDS: [&str; 7] = ["LET", "CONST", "DEFINE", "WHERE", "EXTERNAL", "CACHE", "MACRO"];
+
+// colors the line being typed by lexing it again on every change
+struct Highlight {
+    data: LexerData
+}
+
+impl Helper for Highlight {}
+
+impl Completer for Highlight {
+    type Candidate = String;
+}
+
+impl Hinter for Highlight {
+    type Hint = String;
+}
+
+impl Validator for Highlight {}
+
+impl Highlighter for Highlight {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        let mut out = String::new();
+
+        for (id, range) in spans(line, "#", &self.data) {
+            let text = &line[range];
+            let code = match id {
+                _ if KEYWORDS.contains(&id) => Some(35), // magenta
+                "NUMBER" => Some(33), // yellow
+                "STRING" => Some(32), // green
+                "IDENTIFIER" => Some(36), // cyan
+                "COMMENT" => Some(90), // gray
+                "ERROR" => Some(31), // red
+                "WHITESPACE" | "OPEN_PARENTHESIS" | "CLOSE_PARENTHESIS" | "COMMA" => None,
+                _ => Some(34) // operators in blue
+            };
+
+            match code {
+                Some(code) => out.push_str(&style::color(text, code)),
+                None => out.push_str(text)
+            }
+        }
+
+        Cow::Owned(out)
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize, _forced: bool) -> bool {
+        true
+    }
+}
+
+pub fn repl() {
+    let mut editor = Editor::<Highlight, DefaultHistory>::new().expect("Failed to initialize line editor");
+    let history = history_file();
+    let data = LexerData::default();
+
+    if style::enabled() {
+        editor.set_helper(Some(Highlight { data: data.clone() }));
+    }
+
+    if let Some(history) = &history {
+        let _ = editor.load_history(history); // there is no history on the first start
+    }
+
+    let mut context = Context::new();
+    let mut entries = 0;
+
+    context.runtime.echo = true;
+
+    while let Some(entry) = read_entry(&mut editor, &data) {
+        if entry.trim().is_empty() {
+            continue;
+        }
+
+        let _ = editor.add_history_entry(entry.as_str());
+
+        entries += 1;
+
+        // the session just keeps going after errors
+        if let Err(err) = context.exec_file(&entry, &format!("<repl:{}>", entries)) {
+            println!("{}", err);
+        }
+    }
+
+    if let Some(history) = &history {
+        let _ = editor.save_history(history);
+    }
+}
+
+fn history_file() -> Option<PathBuf> {
+    env::var_os("HOME").or_else(|| env::var_os("USERPROFILE")).map(|home| PathBuf::from(home).join(".math_history"))
+}
+
+fn read_entry(editor: &mut Editor<Highlight, DefaultHistory>, data: &LexerData) -> Option<String> {
+    let mut entry = String::new();
+
+    loop {
+        let prompt = if entry.is_empty() {
+            PROMPT
+        } else {
+            CONTINUATION_PROMPT
+        };
+
+        match editor.readline(prompt) {
+            Ok(line) => {
+                entry.push_str(&line);
+
+                let (depth, continued) = pending(&entry, data);
+
+                if depth > 0 { // unlike in files, open parentheses implicitly join the next line
+                    entry.push(' ');
+                } else {
+                    entry.push('\n');
+
+                    if !continued {
+                        return Some(entry);
+                    }
+                }
+            },
+            Err(ReadlineError::Interrupted) => { // ctrl+c discards the current entry
+                entry.clear();
+            },
+            Err(ReadlineError::Eof) => return None,
+            Err(err) => panic!("Error while reading line ({})", err)
+        }
+    }
+}
+
+// how many parentheses, brackets and braces are open and whether the entry ends like a line that continues (a trailing | or binary operator),
+// lexed the same way as files so that strings and comments don't count
+fn pending(entry: &str, data: &LexerData) -> (usize, bool) {
+    let mut depth = 0;
+    let mut last = "NEW_LINE";
+
+    for (id, _) in entry.lines().flat_map(|line| spans(line, "#", data)) {
+        if id.ne("WHITESPACE") && id.ne("COMMENT") {
+            depth = nesting(id, depth);
+            last = id;
+        }
+    }
+
+    (depth, last.eq("PIPE") || continues(last, depth))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::pending;
+    use crate::lexer::LexerData;
+
+    #[test]
+    fn entries() {
+        let data = LexerData::default();
+
+        assert_eq!(pending("println(\"(\")", &data), (0, false));
+        assert_eq!(pending("println(\"#(\")", &data), (0, false));
+        assert_eq!(pending("1 + 2 # (", &data), (0, false));
+        assert_eq!(pending("println(1,", &data), (1, true));
+        assert_eq!(pending("{1,", &data), (1, true));
+        assert_eq!(pending("[i for i in 1..3", &data), (1, false));
+        assert_eq!(pending("1 +", &data), (0, true));
+        assert_eq!(pending("let a = 1 |", &data), (0, true));
+        assert_eq!(pending("let a = 1 |\n2", &data), (0, false));
+    }
+}
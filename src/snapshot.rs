@@ -0,0 +1,98 @@
+use crate::ast::Expression;
+use crate::binary::{Reader, Writer};
+use crate::error::{ErrorCode, MathError};
+use crate::interpreter::runtime::{RuntimeAST, RuntimeExpression, RuntimeVariable};
+use crate::value::Value;
+use std::sync::Arc;
+
+// the state a computation builds up while running, restored into the same program to resume it:
+// variables that were assigned values and the results of cached functions (by name and arity, ids change between runs)
+const MAGIC: &[u8] = b"MATHS";
+const FORMAT: u8 = 1;
+
+pub fn snapshot(runtime: &RuntimeAST) -> Vec<u8> {
+    let variables = runtime.variables.iter().filter(|v| !v.function_argument).filter_map(|v| match &v.definition.orig {
+        Expression::Value { value } if !v.definition.is_pointer => Some((&v.name, value)),
+        _ => None
+    }).collect::<Vec<(&String, &Value)>>();
+    let mut w = Writer::new(MAGIC);
+
+    w.u8(FORMAT);
+    w.usize(variables.len());
+
+    for (name, value) in variables {
+        w.string(name);
+        w.value(value);
+    }
+
+    w.usize(runtime.memo.len());
+
+    for ((id, arguments), result) in &runtime.memo {
+        let function = &runtime.functions[*id];
+
+        w.string(&function.name);
+        w.usize(function.parameters.len());
+        w.bool(function.variadic);
+        w.usize(arguments.len());
+        arguments.iter().for_each(|a| w.value(a));
+        w.value(result);
+    }
+
+    w.finish()
+}
+
+// returns how many variables and cached results were restored, cached results of functions that don't exist anymore are left out
+pub fn restore(runtime: &mut RuntimeAST, bytes: &[u8]) -> Result<usize, MathError> {
+    let invalid = || MathError::new(ErrorCode::InvalidArgument, "Not a snapshot (or a damaged one)");
+    let mut r = Reader::new(bytes, MAGIC).ok_or_else(invalid)?;
+
+    if r.u8() != Some(FORMAT) {
+        return Err(invalid());
+    }
+
+    let mut variables = Vec::<RuntimeVariable>::new();
+    let mut memo = Vec::<((usize, Vec<Value>), Value)>::new();
+
+    for _ in 0..r.usize().ok_or_else(invalid)? {
+        let (name, value) = r.string().zip(r.value()).ok_or_else(invalid)?;
+
+        variables.push(RuntimeVariable {
+            name,
+            definition: Arc::new(RuntimeExpression {
+                orig: Expression::Value {
+                    value
+                },
+                is_pointer: false,
+                pointer_to: Box::new(None)
+            }),
+            function_argument: false,
+            constant: false
+        });
+    }
+
+    for _ in 0..r.usize().ok_or_else(invalid)? {
+        let name = r.string().ok_or_else(invalid)?;
+        let parameters = r.usize().ok_or_else(invalid)?;
+        let variadic = r.bool().ok_or_else(invalid)?;
+        let arguments = (0..r.usize().ok_or_else(invalid)?).map(|_| r.value()).collect::<Option<Vec<Value>>>().ok_or_else(invalid)?;
+        let result = r.value().ok_or_else(invalid)?;
+        let id = runtime.function_names.get(&name).and_then(|ids| ids.iter().find(|id| {
+            let f = &runtime.functions[**id];
+
+            f.cached && f.parameters.len() == parameters && f.variadic == variadic
+        }));
+
+        if let Some(id) = id {
+            memo.push(((*id, arguments), result));
+        }
+    }
+
+    r.end().ok_or_else(invalid)?;
+
+    let restored = variables.len() + memo.len();
+
+    variables.into_iter().for_each(|v| runtime.define_variable(v));
+    runtime.memo.extend(memo);
+
+    Ok(restored)
+}
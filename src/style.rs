@@ -0,0 +1,45 @@
+use std::env;
+use std::io::{stderr, stdout, IsTerminal};
+
+const PROGRESS_WIDTH: u64 = 30;
+
+pub const COLORS: [(&str, u8); 9] = [
+    ("black", 30),
+    ("red", 31),
+    ("green", 32),
+    ("yellow", 33),
+    ("blue", 34),
+    ("magenta", 35),
+    ("cyan", 36),
+    ("white", 37),
+    ("gray", 90)
+];
+
+// styling is left out when stdout isn't a terminal (or NO_COLOR is set) so that redirected output stays plain text
+pub fn enabled() -> bool {
+    stdout().is_terminal() && env::var_os("NO_COLOR").is_none()
+}
+
+pub fn color_code(name: &str) -> Option<u8> {
+    COLORS.iter().find(|(n, _)| n.eq(&name)).map(|(_, code)| *code)
+}
+
+pub fn color(text: &str, code: u8) -> String {
+    format!("\x1b[{}m{}\x1b[0m", code, text)
+}
+
+pub fn bold(text: &str) -> String {
+    format!("\x1b[1m{}\x1b[0m", text)
+}
+
+// progress bars are redrawn in place, so they're only drawn on terminals
+pub fn progress_enabled() -> bool {
+    stderr().is_terminal()
+}
+
+// [##########----------]  50% (5/10)
+pub fn progress_bar(current: u64, total: u64) -> String {
+    let filled = current * PROGRESS_WIDTH / total;
+
+    format!("\r[{}{}] {:>3}% ({}/{})", "#".repeat(filled as usize), "-".repeat((PROGRESS_WIDTH - filled) as usize), current * 100 / total, current, total)
+}
@@ -0,0 +1,44 @@
+use crate::ast::AST;
+use crate::default_externals;
+use crate::interpreter::runtime::{RuntimeAST, RuntimeExpression};
+use crate::style;
+use crate::value::Value;
+use std::mem::take;
+
+// runs the test declarations of a program (test "name" = condition) one after another, its loose expressions aren't run
+pub fn run(mut ast: AST) -> bool {
+    let tests = take(&mut ast.tests);
+    let mut runtime = RuntimeAST::create(AST {
+        loose_expressions: vec![],
+        locations: vec![],
+        ..ast
+    }, default_externals());
+    let mut failed = 0;
+    let styled = style::enabled();
+    let ok = if styled { style::color("ok", 32) } else { "ok".to_owned() };
+    let failed_label = if styled { style::bold(&style::color("FAILED", 31)) } else { "FAILED".to_owned() };
+
+    for test in &tests {
+        let name = format!("{}:{} {}", test.location.file, test.location.line + 1, test.name);
+
+        match runtime.guarded(|runtime| RuntimeExpression::execute_expr(&test.condition, runtime)) {
+            Ok(value) if value == Value::bool(true) => println!("test {} ... {}", name, ok),
+            Ok(value) => {
+                println!("test {} ... {} (was {})", name, failed_label, value);
+
+                failed += 1;
+            },
+            Err(err) => {
+                println!("test {} ... {}", name, failed_label);
+                println!("{}", err);
+
+                failed += 1;
+            }
+        }
+    }
+
+    println!();
+    println!("test result: {}. {} passed; {} failed", if failed == 0 { &ok } else { &failed_label }, tests.len() - failed, failed);
+
+    failed == 0
+}
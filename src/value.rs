@@ -0,0 +1,246 @@
+use num_bigint::{BigInt, Sign};
+use std::convert::TryFrom;
+use crate::error::{ErrorCode, raise};
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter, Result};
+use std::hash::{Hash, Hasher};
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Value {
+    Small(i64), // numbers that fit are always stored like this (arithmetic on them doesn't allocate), so equal numbers are equal values
+    Number(BigInt),
+    Fraction(BigInt, BigInt), // reduced, the denominator is bigger than 1 (rational numeric)
+    Decimal(BigInt, u32), // digits and how many of them are after the point, without trailing zeros (decimal numeric)
+    List(Vec<Value>),
+    String(String),
+    Range(BigInt, BigInt, BigInt), // from, to (inclusive) and a step that isn't 0, the numbers are only made when they're needed
+    Tuple(Vec<Value>), // written (a, b, c), (a) is just a
+    Set(Set), // whole numbers, written {a, b, c}
+    None // the absence of a value (e.g. find without a match), written none
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct Set(pub HashSet<BigInt>);
+
+impl Set {
+    // in order, so that sets are shown (and written) the same way every time
+    pub fn sorted(&self) -> Vec<&BigInt> {
+        let mut numbers = self.0.iter().collect::<Vec<&BigInt>>();
+
+        numbers.sort();
+
+        numbers
+    }
+}
+
+// equal sets hash the same however their numbers are stored
+impl Hash for Set {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.sorted().hash(state);
+    }
+}
+
+impl Value {
+    pub fn number(&self) -> BigInt {
+        match self {
+            Value::Small(value) => BigInt::from(*value),
+            Value::Number(value) => value.clone(),
+            _ => raise(ErrorCode::TypeMismatch, &format!("Expected a number but got {} ('{}')", self.type_name(), self))
+        }
+    }
+
+    pub fn list(&self) -> &Vec<Value> {
+        match self {
+            Value::List(values) => values,
+            _ => raise(ErrorCode::TypeMismatch, &format!("Expected a list but got {} ('{}')", self.type_name(), self))
+        }
+    }
+
+    pub fn tuple(&self) -> &Vec<Value> {
+        match self {
+            Value::Tuple(values) => values,
+            _ => raise(ErrorCode::TypeMismatch, &format!("Expected a tuple but got {} ('{}')", self.type_name(), self))
+        }
+    }
+
+    pub fn set(&self) -> &HashSet<BigInt> {
+        match self {
+            Value::Set(set) => &set.0,
+            _ => raise(ErrorCode::TypeMismatch, &format!("Expected a set but got {} ('{}')", self.type_name(), self))
+        }
+    }
+
+    pub fn range(&self) -> (&BigInt, &BigInt, &BigInt) {
+        match self {
+            Value::Range(from, to, step) => (from, to, step),
+            _ => raise(ErrorCode::TypeMismatch, &format!("Expected a range but got {} ('{}')", self.type_name(), self))
+        }
+    }
+
+    // the values of a list or the numbers of a range or set (from smallest to largest)
+    pub fn elements(&self) -> Vec<Value> {
+        match self {
+            Value::Set(set) => set.sorted().into_iter().map(Value::from).collect(),
+            Value::Range(from, to, step) => {
+                let mut elements = Vec::<Value>::new();
+                let mut x = from.clone();
+
+                while (step.sign() == Sign::Plus && &x <= to) || (step.sign() == Sign::Minus && &x >= to) {
+                    elements.push(Value::from(&x));
+                    x += step;
+                }
+
+                elements
+            },
+            _ => self.list().clone()
+        }
+    }
+
+    pub fn string(&self) -> &String {
+        match self {
+            Value::String(value) => value,
+            _ => raise(ErrorCode::TypeMismatch, &format!("Expected a string but got {} ('{}')", self.type_name(), self))
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Small(_) | Value::Number(_) => "a number",
+            Value::Fraction(_, _) => "a fraction",
+            Value::Decimal(_, _) => "a decimal",
+            Value::List(_) => "a list",
+            Value::String(_) => "a string",
+            Value::Range(_, _, _) => "a range",
+            Value::Tuple(_) => "a tuple",
+            Value::Set(_) => "a set",
+            Value::None => "none"
+        }
+    }
+
+    // 0 is false and every other number is true (for if, &&, ||, ? and !)
+    pub fn is_true(&self) -> bool {
+        match self {
+            Value::Small(value) => *value != 0,
+            Value::Fraction(_, _) | Value::Decimal(_, _) => true, // never 0
+            _ => self.number().sign() != Sign::NoSign
+        }
+    }
+
+    // numbers are written out in full, JSON doesn't limit their size
+    pub fn json(&self) -> String {
+        match self {
+            Value::Small(value) => value.to_string(),
+            Value::Number(value) => value.to_string(),
+            Value::Fraction(_, _) | Value::Range(_, _, _) => format!("\"{}\"", self),
+            Value::Decimal(_, _) => self.to_string(),
+            Value::List(values) | Value::Tuple(values) => format!("[{}]", values.iter().map(Value::json).collect::<Vec<String>>().join(", ")),
+            Value::Set(set) => format!("[{}]", set.sorted().iter().map(|n| n.to_string()).collect::<Vec<String>>().join(", ")),
+            Value::None => "null".to_owned(),
+            Value::String(value) => format!("\"{}\"", value.chars().map(|c| match c {
+                '"' => "\\\"".to_owned(),
+                '\\' => "\\\\".to_owned(),
+                '\n' => "\\n".to_owned(),
+                '\r' => "\\r".to_owned(),
+                '\t' => "\\t".to_owned(),
+                c if c.is_control() => format!("\\u{:04x}", c as u32),
+                c => c.to_string()
+            }).collect::<String>())
+        }
+    }
+
+    pub fn bool(value: bool) -> Value {
+        Value::from(if value { 1 } else { 0 })
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, Value::Small(_) | Value::Number(_) | Value::Fraction(_, _) | Value::Decimal(_, _))
+    }
+
+    // p/q in lowest terms, a whole number if q divides p
+    pub fn fraction(p: BigInt, q: BigInt) -> Value {
+        let g = gcd(&p, &q);
+        let (p, q) = if q.sign() == Sign::Minus { (-p / &g, -q / &g) } else { (p / &g, q / &g) };
+
+        if q == BigInt::from(1) {
+            Value::from(p)
+        } else {
+            Value::Fraction(p, q)
+        }
+    }
+
+    // digits / 10^scale
+    pub fn decimal(mut digits: BigInt, mut scale: u32) -> Value {
+        let ten = BigInt::from(10);
+
+        while scale > 0 && &digits % &ten == BigInt::from(0) {
+            digits /= &ten;
+            scale -= 1;
+        }
+
+        if scale == 0 {
+            Value::from(digits)
+        } else {
+            Value::Decimal(digits, scale)
+        }
+    }
+}
+
+impl From<BigInt> for Value {
+    fn from(value: BigInt) -> Self {
+        match i64::try_from(&value) {
+            Ok(value) => Value::Small(value),
+            Err(_) => Value::Number(value)
+        }
+    }
+}
+
+impl From<&BigInt> for Value {
+    fn from(value: &BigInt) -> Self {
+        match i64::try_from(value) {
+            Ok(value) => Value::Small(value),
+            Err(_) => Value::Number(value.clone())
+        }
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Small(value)
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Value::Small(value) => write!(f, "{}", value),
+            Value::Number(value) => write!(f, "{}", value),
+            Value::Fraction(p, q) => write!(f, "{}/{}", p, q),
+            Value::Decimal(digits, scale) => {
+                let s = format!("{:0>width$}", digits.magnitude(), width = *scale as usize + 1);
+                let (whole, fraction) = s.split_at(s.len() - *scale as usize);
+
+                write!(f, "{}{}.{}", if digits.sign() == Sign::Minus { "-" } else { "" }, whole, fraction)
+            },
+            Value::List(values) => write!(f, "[{}]", values.iter().map(|v| v.to_string()).collect::<Vec<String>>().join(", ")),
+            Value::String(value) => write!(f, "{}", value),
+            Value::Range(from, to, step) if *step == BigInt::from(1) => write!(f, "{}..{}", from, to),
+            Value::Range(from, to, step) => write!(f, "{}..{} step {}", from, to, step),
+            Value::Tuple(values) => write!(f, "({})", values.iter().map(|v| v.to_string()).collect::<Vec<String>>().join(", ")),
+            Value::Set(set) => write!(f, "{{{}}}", set.sorted().iter().map(|n| n.to_string()).collect::<Vec<String>>().join(", ")),
+            Value::None => write!(f, "none")
+        }
+    }
+}
+
+fn gcd(a: &BigInt, b: &BigInt) -> BigInt {
+    let (mut a, mut b) = (BigInt::from(a.magnitude().clone()), BigInt::from(b.magnitude().clone()));
+
+    while b != BigInt::from(0) {
+        let r = &a % &b;
+
+        a = b;
+        b = r;
+    }
+
+    a
+}
@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+use std::fs::{read, write};
+use std::io::{Error, ErrorKind, Result};
+use std::sync::Mutex;
+
+// what the file builtins read from and write to, the real filesystem unless the embedder sandboxes the program
+pub trait Vfs: Send + Sync {
+    fn read(&self, path: &str) -> Result<Vec<u8>>;
+
+    fn write(&self, path: &str, content: &[u8]) -> Result<()>;
+}
+
+// paths are relative to the working directory
+pub struct RealFs;
+
+// files only exist as long as it does, nothing outside of it is visible
+#[derive(Default)]
+pub struct MemoryFs {
+    files: Mutex<HashMap<String, Vec<u8>>>
+}
+
+impl MemoryFs {
+    pub fn new() -> Self {
+        MemoryFs::default()
+    }
+
+    // starts out with the given files
+    pub fn with(files: &[(&str, &str)]) -> Self {
+        MemoryFs {
+            files: Mutex::new(files.iter().map(|(path, content)| ((*path).to_owned(), content.as_bytes().to_vec())).collect())
+        }
+    }
+
+    pub fn get(&self, path: &str) -> Option<Vec<u8>> {
+        self.files.lock().unwrap().get(path).cloned()
+    }
+}
+
+impl Vfs for RealFs {
+    fn read(&self, path: &str) -> Result<Vec<u8>> {
+        read(path)
+    }
+
+    fn write(&self, path: &str, content: &[u8]) -> Result<()> {
+        write(path, content)
+    }
+}
+
+impl Vfs for MemoryFs {
+    fn read(&self, path: &str) -> Result<Vec<u8>> {
+        self.get(path).ok_or_else(|| Error::new(ErrorKind::NotFound, "No such file"))
+    }
+
+    fn write(&self, path: &str, content: &[u8]) -> Result<()> {
+        self.files.lock().unwrap().insert(path.to_owned(), content.to_vec());
+
+        Ok(())
+    }
+}